@@ -0,0 +1,54 @@
+//! Reads a large file start to end with 64KiB reads, after dropping it from the page cache, once
+//! as it is and once after hinting `AccessHint::Sequential`. The hint widens read-ahead where the
+//! OS takes it: on a real disk the hinted read should come out ahead, on tmpfs the two are even.
+
+use std::fs;
+use std::io::{Read, Seek};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use fsa::{AccessHint, FilesystemObjectRaw, PhysicalFile};
+
+const MB: usize = 1024 * 1024;
+const FILE_SIZE: usize = 64 * MB;
+
+fn sequential_read(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("fsa-bench-advise-{}.bin", std::process::id()));
+    let data = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    fs::write(&path, &data).unwrap();
+    drop(data);
+
+    let mut group = c.benchmark_group("advise_sequential_read");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    for hint in [None, Some(AccessHint::Sequential)] {
+        let file = PhysicalFile::from_path(&path, 64 * 1024);
+        file.write().unwrap().open().unwrap();
+
+        let name = if hint.is_some() { "sequential" } else { "no_hint" };
+        group.bench_function(name, |b| b.iter(|| {
+            let mut file = file.write().unwrap();
+            file.advise(AccessHint::DontNeed).unwrap();
+            if let Some(hint) = hint {
+                file.advise(hint).unwrap();
+            }
+            file.rewind().unwrap();
+            let mut chunk = vec![0; 64 * 1024];
+            let mut total = 0;
+            loop {
+                let read = file.read(&mut chunk).unwrap();
+                if read == 0 {
+                    break;
+                }
+                total += read;
+            }
+            assert_eq!(total, FILE_SIZE);
+        }));
+        file.write().unwrap().close();
+    }
+    group.finish();
+
+    fs::remove_file(&path).unwrap();
+}
+
+criterion_group!(benches, sequential_read);
+criterion_main!(benches);
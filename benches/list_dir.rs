@@ -0,0 +1,36 @@
+//! Lists a directory of 10k files through `list` and through `get_children`. `list` only reads
+//! the names and kinds, while `get_children` builds (and caches) an object per entry, so it
+//! should take well under half the time.
+
+use std::fs;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use fsa::{FilesystemObjectRaw, PhysicalDirectory};
+
+const ENTRIES: usize = 10_000;
+
+fn list_dir(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("fsa-bench-list-{}", std::process::id()));
+    fs::create_dir_all(&path).unwrap();
+    for i in 0..ENTRIES {
+        fs::write(path.join(format!("file-{i:05}.txt")), "").unwrap();
+    }
+
+    let mut group = c.benchmark_group("list_dir");
+    group.sample_size(20);
+    // A fresh directory object each time, so get_children can't answer from its cache.
+    group.bench_function("list", |b| b.iter(|| {
+        let dir = PhysicalDirectory::from_path(&path);
+        assert_eq!(dir.read().unwrap().list().unwrap().len(), ENTRIES);
+    }));
+    group.bench_function("get_children", |b| b.iter(|| {
+        let dir = PhysicalDirectory::from_path(&path);
+        assert_eq!(dir.read().unwrap().get_children().unwrap().len(), ENTRIES);
+    }));
+    group.finish();
+
+    fs::remove_dir_all(&path).unwrap();
+}
+
+criterion_group!(benches, list_dir);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+//! Reads small chunks at random offsets of a large file, once through the read buffer and once
+//! served from a mapping with `set_mmap_reads`. The mapping spares a syscall and a copy per read,
+//! so it should come out well ahead.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use fsa::PhysicalFile;
+
+const MB: usize = 1024 * 1024;
+const FILE_SIZE: usize = 64 * MB;
+const CHUNK: usize = 4096;
+const READS: usize = 1000;
+
+fn data_file() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("fsa-bench-mmap-{}.bin", std::process::id()));
+    let data: Vec<u8> = (0..FILE_SIZE).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, data).unwrap();
+    path
+}
+
+/// Offsets spread over the whole file, the same for every run.
+fn offsets() -> Vec<u64> {
+    let mut state = 0x2545_f491_u32;
+    (0..READS)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            (state as usize % (FILE_SIZE - CHUNK)) as u64
+        })
+        .collect()
+}
+
+fn random_read(c: &mut Criterion) {
+    let path = data_file();
+    let offsets = offsets();
+
+    let mut group = c.benchmark_group("random_read");
+    group.throughput(Throughput::Bytes((READS * CHUNK) as u64));
+    for mapped in [false, true] {
+        let file = PhysicalFile::from_path(&path, 64 * 1024);
+        file.write().unwrap().set_mmap_reads(mapped).unwrap();
+
+        let name = if mapped { "mapped" } else { "buffered" };
+        group.bench_function(name, |b| b.iter(|| {
+            let mut file = file.write().unwrap();
+            let mut chunk = vec![0; CHUNK];
+            for &offset in &offsets {
+                file.seek(SeekFrom::Start(offset)).unwrap();
+                file.read_exact(&mut chunk).unwrap();
+            }
+        }));
+    }
+    group.finish();
+
+    fs::remove_file(path).unwrap();
+}
+
+criterion_group!(benches, random_read);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+//! Reads a large deflated zip entry start to end. Each buffer fill used to decompress the entry
+//! again from the start, so the time grew with the square of its size; throughput should now be
+//! about the same for every size.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use fsa::{FilesystemObjectRaw, PhysicalFile, ZipDirectory};
+use zip::write::SimpleFileOptions;
+
+const MB: usize = 1024 * 1024;
+
+/// Writes an archive holding one deflated entry of `size` bytes of text that compresses
+/// roughly as well as real-world text does.
+fn deflated_archive(size: usize) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("fsa-bench-zip-{}-{size}.zip", std::process::id()));
+    let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+    writer.start_file("data.txt", SimpleFileOptions::default()).unwrap();
+
+    let mut state = 0x2545_f491_u32;
+    let mut written = 0;
+    let mut line = Vec::new();
+    while written < size {
+        line.clear();
+        for _ in 0..12 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            line.extend_from_slice(&b"abcdefghijklmnopqrstuvwxyz"[..(state % 9 + 2) as usize]);
+            line.push(b' ');
+        }
+        line.push(b'\n');
+        let take = line.len().min(size - written);
+        writer.write_all(&line[..take]).unwrap();
+        written += take;
+    }
+    writer.finish().unwrap();
+    path
+}
+
+fn sequential_read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zip_sequential_read");
+    group.sample_size(10);
+    for size in [10 * MB, 100 * MB] {
+        let path = deflated_archive(size);
+        let archive = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = archive.read().unwrap().get_child("data.txt").unwrap();
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(format!("{}MB", size / MB), |b| b.iter(|| {
+            let mut entry = entry.write().unwrap();
+            entry.rewind().unwrap();
+            let mut chunk = vec![0; 64 * 1024];
+            let mut total = 0;
+            loop {
+                let read = entry.read(&mut chunk).unwrap();
+                if read == 0 {
+                    break;
+                }
+                total += read;
+            }
+            entry.close();
+            assert_eq!(total, size);
+        }));
+
+        drop(entry);
+        drop(archive);
+        fs::remove_file(path).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sequential_read);
+criterion_main!(benches);
@@ -0,0 +1,535 @@
+//! The [async](`self`) module provides [`AsyncFilesystemObject`], an async counterpart of
+//! [`FilesystemObjectRaw`](super::FilesystemObjectRaw), along with physical implementations backed
+//! by [`tokio::fs`].
+//!
+//! Async objects are plain handles on a path: they don't share children or handles with each
+//! other, so there's nothing to lock and they can be held across `.await`s freely.
+
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use std::future::Future;
+use std::io::{ErrorKind as IoErrorKind, SeekFrom};
+use std::path::{PathBuf, Path};
+use std::pin::Pin;
+use std::fmt;
+
+use super::{FsError, FsResult, FsKind, OpenMode};
+
+/// Future returned by [`AsyncFilesystemObject`] methods. Boxed so the trait stays usable as a
+/// trait object.
+pub type FsFuture<'a, T = ()> = Pin<Box<dyn Future<Output = FsResult<T>> + Send + 'a>>;
+
+pub type AsyncObject = Box<dyn AsyncFilesystemObject>;
+
+/// Async counterpart of [`FilesystemObjectRaw`](super::FilesystemObjectRaw). As there, files and
+/// directories share the trait, and methods that don't apply to the object's kind error.
+pub trait AsyncFilesystemObject: Send + Sync + fmt::Debug {
+    /// Returns the name of the object.
+    fn name(&self) -> &Path;
+    /// Returns whether the object is a file or a directory.
+    fn kind(&self) -> FsKind;
+    /// Whether the object is a file.
+    fn is_file(&self) -> bool { self.kind() == FsKind::File }
+    /// Whether the object is a directory.
+    fn is_dir(&self) -> bool { self.kind() == FsKind::Directory }
+    /// Returns the full path to the object.
+    fn get_full_path(&self) -> PathBuf;
+
+    /// Checks whether the object exists in its backing storage.
+    fn exists(&self) -> FsFuture<'_, bool>;
+    /// If file, returns the size of the file in bytes. Else, errors.
+    fn size(&self) -> FsFuture<'_, usize>;
+
+    /// If file, opens the file. Else, errors.
+    fn open(&mut self) -> FsFuture<'_>;
+    /// If file, checks whether the file is currently opened.
+    fn is_open(&self) -> bool;
+    /// If file, flushes and closes the file.
+    fn close(&mut self) -> FsFuture<'_>;
+
+    /// If file, reads into `buf` from the current position, opening the file first if needed.
+    /// Else, errors.
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> FsFuture<'a, usize>;
+    /// If file, writes `buf` at the current position. Else, errors.
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> FsFuture<'a, usize>;
+    /// If file, flushes pending writes. Else, errors.
+    fn flush(&mut self) -> FsFuture<'_>;
+
+    /// If directory, retrieves its children sorted by name, using the results of
+    /// [`AsyncFilesystemObject::scan`] if there are any. Else, errors.
+    fn get_children(&self) -> FsFuture<'_, Vec<AsyncObject>>;
+    /// Retrieves a specific child by name.
+    fn get_child<'a>(&'a self, name: &'a str) -> FsFuture<'a, AsyncObject>;
+    /// Checks if a child with the given name exists in the directory.
+    fn has_child<'a>(&'a self, name: &'a str) -> FsFuture<'a, bool>;
+    /// Creates a new file within the directory, failing with [`FsError::AlreadyExists`] if the
+    /// name is taken.
+    fn new_file<'a>(&'a mut self, name: &'a str) -> FsFuture<'a, AsyncObject>;
+    /// Creates a new subdirectory within the directory, failing with [`FsError::AlreadyExists`] if
+    /// the name is taken.
+    fn new_dir<'a>(&'a mut self, name: &'a str) -> FsFuture<'a, AsyncObject>;
+    /// Scans the directory contents and caches the results.
+    fn scan(&mut self) -> FsFuture<'_>;
+}
+
+pub struct AsyncPhysicalDirectory {
+    name: PathBuf,
+    path: PathBuf,
+    /// Names and kinds found by the last scan.
+    entries: Option<Vec<(PathBuf, FsKind)>>,
+}
+
+pub struct AsyncPhysicalFile {
+    name: PathBuf,
+    path: PathBuf,
+    file: Option<fs::File>,
+    open_mode: OpenMode,
+}
+
+impl AsyncPhysicalDirectory {
+    /// Creates a directory object for the given path. The path is used as-is as the directory's
+    /// name, so relative paths stay relative.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        Self{
+            name: path.as_ref().to_path_buf(),
+            path: path.as_ref().to_path_buf(),
+            entries: None,
+        }
+    }
+
+    fn display_path(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+
+    fn child(&self, name: &Path, kind: FsKind) -> AsyncObject {
+        let path = self.path.join(name);
+        match kind {
+            FsKind::Directory => Box::new(AsyncPhysicalDirectory{ name: name.to_path_buf(), path, entries: None }),
+            FsKind::File => Box::new(AsyncPhysicalFile::new(name, path)),
+        }
+    }
+
+    async fn read_entries(&self) -> FsResult<Vec<(PathBuf, FsKind)>> {
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(&self.path).await?;
+        while let Some(item) = dir.next_entry().await? {
+            let file_type = item.file_type().await?;
+            let kind = match (file_type.is_dir(), file_type.is_file()) {
+                (true, _) => FsKind::Directory,
+                (_, true) => FsKind::File,
+                _ => continue,
+            };
+            entries.push((PathBuf::from(item.file_name()), kind));
+        }
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(entries)
+    }
+
+    async fn check_vacant(&self, name: &str) -> FsResult {
+        let path = self.path.join(name);
+        if fs::symlink_metadata(&path).await.is_ok() {
+            return Err(FsError::already_exists(path.to_string_lossy().to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl AsyncPhysicalFile {
+    fn new(name: &Path, path: PathBuf) -> Self {
+        Self{
+            name: name.to_path_buf(),
+            path,
+            file: None,
+            open_mode: OpenMode::Read,
+        }
+    }
+
+    /// Creates a file object for the given path.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let name = path.file_name().map(Path::new).unwrap_or(path);
+
+        Self::new(name, path.to_path_buf())
+    }
+
+    /// Sets how [`AsyncFilesystemObject::open`] opens the file, taking effect the next time it's
+    /// opened. As with [`PhysicalFile`](super::PhysicalFile), in the default [`OpenMode::Read`]
+    /// writes go through a separate handle, at the current position.
+    pub fn set_open_mode(&mut self, mode: OpenMode) {
+        self.open_mode = mode;
+    }
+
+    fn display_path(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+
+    async fn open_handle(&mut self) -> FsResult {
+        let mut file = fs::OpenOptions::from(self.open_mode.options()).open(&self.path).await?;
+        if self.open_mode == OpenMode::Append {
+            file.seek(SeekFrom::End(0)).await?;
+        }
+        self.file = Some(file);
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AsyncPhysicalDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncPhysicalDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("scanned", &self.entries.is_some())
+            .finish()
+    }
+}
+
+impl fmt::Debug for AsyncPhysicalFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncPhysicalFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("open", &self.is_open())
+            .finish()
+    }
+}
+
+impl AsyncFilesystemObject for AsyncPhysicalDirectory {
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn exists(&self) -> FsFuture<'_, bool> {
+        Box::pin(async move {
+            Ok(fs::metadata(&self.path).await.is_ok_and(|metadata| metadata.is_dir()))
+        })
+    }
+
+    fn size(&self) -> FsFuture<'_, usize> {
+        Box::pin(async move { Err(FsError::not_a_file(self.display_path(), self.kind())) })
+    }
+
+    fn open(&mut self) -> FsFuture<'_> {
+        Box::pin(async move { Err(FsError::not_a_file(self.display_path(), self.kind())) })
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn close(&mut self) -> FsFuture<'_> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn read<'a>(&'a mut self, _buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move { Err(FsError::not_a_file(self.display_path(), self.kind())) })
+    }
+
+    fn write<'a>(&'a mut self, _buf: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move { Err(FsError::not_a_file(self.display_path(), self.kind())) })
+    }
+
+    fn flush(&mut self) -> FsFuture<'_> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn get_children(&self) -> FsFuture<'_, Vec<AsyncObject>> {
+        Box::pin(async move {
+            let entries = match &self.entries {
+                Some(entries) => entries.clone(),
+                None => self.read_entries().await?,
+            };
+
+            Ok(entries.iter().map(|(name, kind)| self.child(name, *kind)).collect())
+        })
+    }
+
+    fn get_child<'a>(&'a self, name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move {
+            let kind = match &self.entries {
+                Some(entries) => entries.iter()
+                    .find(|(entry, _)| entry.as_os_str() == name)
+                    .map(|(_, kind)| *kind),
+                None => match fs::metadata(self.path.join(name)).await {
+                    Ok(metadata) if metadata.is_dir() => Some(FsKind::Directory),
+                    Ok(metadata) if metadata.is_file() => Some(FsKind::File),
+                    Ok(_) => None,
+                    Err(err) if err.kind() == IoErrorKind::NotFound => None,
+                    Err(err) => return Err(err.into()),
+                },
+            };
+
+            kind.map(|kind| self.child(Path::new(name), kind))
+                .ok_or_else(|| FsError::file_not_present(self.display_path(), name.to_string()))
+        })
+    }
+
+    fn has_child<'a>(&'a self, name: &'a str) -> FsFuture<'a, bool> {
+        Box::pin(async move {
+            match self.get_child(name).await {
+                Ok(_) => Ok(true),
+                Err(FsError::FileNotPresent(..)) => Ok(false),
+                Err(err) => Err(err),
+            }
+        })
+    }
+
+    fn new_file<'a>(&'a mut self, name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move {
+            self.check_vacant(name).await?;
+            if let Some(entries) = self.entries.as_mut() {
+                entries.push((PathBuf::from(name), FsKind::File));
+            }
+
+            Ok(self.child(Path::new(name), FsKind::File))
+        })
+    }
+
+    fn new_dir<'a>(&'a mut self, name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move {
+            self.check_vacant(name).await?;
+            fs::create_dir(self.path.join(name)).await?;
+            if let Some(entries) = self.entries.as_mut() {
+                entries.push((PathBuf::from(name), FsKind::Directory));
+            }
+
+            Ok(self.child(Path::new(name), FsKind::Directory))
+        })
+    }
+
+    fn scan(&mut self) -> FsFuture<'_> {
+        Box::pin(async move {
+            self.entries = Some(self.read_entries().await?);
+            Ok(())
+        })
+    }
+}
+
+impl AsyncFilesystemObject for AsyncPhysicalFile {
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn exists(&self) -> FsFuture<'_, bool> {
+        Box::pin(async move {
+            Ok(fs::metadata(&self.path).await.is_ok_and(|metadata| metadata.is_file()))
+        })
+    }
+
+    fn size(&self) -> FsFuture<'_, usize> {
+        Box::pin(async move { Ok(fs::metadata(&self.path).await?.len() as usize) })
+    }
+
+    fn open(&mut self) -> FsFuture<'_> {
+        Box::pin(async move {
+            self.close().await?;
+            self.open_handle().await
+        })
+    }
+
+    fn is_open(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn close(&mut self) -> FsFuture<'_> {
+        Box::pin(async move {
+            self.flush().await?;
+            self.file = None;
+
+            Ok(())
+        })
+    }
+
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if self.file.is_none() {
+                self.open_handle().await?;
+            }
+
+            Ok(self.file.as_mut().unwrap().read(buf).await?)
+        })
+    }
+
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if self.open_mode != OpenMode::Read {
+                if self.file.is_none() {
+                    self.open_handle().await?;
+                }
+                return Ok(self.file.as_mut().unwrap().write(buf).await?);
+            }
+
+            let position = match self.file.as_mut() {
+                Some(file) => file.stream_position().await?,
+                None => 0,
+            };
+
+            let mut writer = fs::OpenOptions::new().write(true).create(true).truncate(false).open(&self.path).await?;
+            writer.seek(SeekFrom::Start(position)).await?;
+            writer.write_all(buf).await?;
+            writer.flush().await?;
+
+            if self.file.is_none() {
+                self.open_handle().await?;
+            }
+            self.file.as_mut().unwrap().seek(SeekFrom::Start(position + buf.len() as u64)).await?;
+
+            Ok(buf.len())
+        })
+    }
+
+    fn flush(&mut self) -> FsFuture<'_> {
+        Box::pin(async move {
+            if let Some(file) = self.file.as_mut() {
+                file.flush().await?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn get_children(&self) -> FsFuture<'_, Vec<AsyncObject>> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+
+    fn get_child<'a>(&'a self, _name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+
+    fn has_child<'a>(&'a self, _name: &'a str) -> FsFuture<'a, bool> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+
+    fn new_file<'a>(&'a mut self, _name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+
+    fn new_dir<'a>(&'a mut self, _name: &'a str) -> FsFuture<'a, AsyncObject> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+
+    fn scan(&mut self) -> FsFuture<'_> {
+        Box::pin(async move { Err(FsError::not_a_directory(self.display_path(), self.kind())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    async fn read_all(file: &mut dyn AsyncFilesystemObject) -> Vec<u8> {
+        let mut contents = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let count = file.read(&mut buf).await.unwrap();
+            if count == 0 {
+                return contents;
+            }
+            contents.extend_from_slice(&buf[..count]);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_and_writes_round_trip() {
+        let tmp = TempDir::new("async-io");
+        tmp.write("data.txt", "hello world");
+
+        let mut file = AsyncPhysicalFile::from_path(tmp.join("data.txt"));
+        assert!(file.exists().await.unwrap());
+        assert_eq!(file.size().await.unwrap(), 11);
+        assert_eq!(read_all(&mut file).await, b"hello world");
+        assert!(file.is_open());
+
+        // In read mode writes land at the current position, here the end.
+        assert_eq!(file.write(b"!").await.unwrap(), 1);
+        file.flush().await.unwrap();
+        assert_eq!(std::fs::read_to_string(tmp.join("data.txt")).unwrap(), "hello world!");
+
+        file.set_open_mode(OpenMode::Write);
+        file.open().await.unwrap();
+        file.write(b"fresh").await.unwrap();
+        file.close().await.unwrap();
+        assert!(!file.is_open());
+        assert_eq!(std::fs::read_to_string(tmp.join("data.txt")).unwrap(), "fresh");
+        file.set_open_mode(OpenMode::Read);
+        assert_eq!(read_all(&mut file).await, b"fresh");
+
+        let dir = AsyncPhysicalDirectory::from_path(tmp.path());
+        assert!(matches!(dir.size().await, Err(FsError::NotAFile(..))));
+        assert!(matches!(file.get_children().await, Err(FsError::NotADirectory(..))));
+        assert!(dir.exists().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn scans_list_children_sorted() {
+        let tmp = TempDir::new("async-scan");
+        tmp.write("b.txt", "b");
+        tmp.write("a/inner.txt", "inner");
+        tmp.write("c.txt", "c");
+
+        let mut dir = AsyncPhysicalDirectory::from_path(tmp.path());
+        let listed = |children: Vec<AsyncObject>| -> Vec<(String, FsKind)> {
+            children.iter().map(|child| (child.name().to_string_lossy().to_string(), child.kind())).collect()
+        };
+        let expected = [("a".to_string(), FsKind::Directory), ("b.txt".to_string(), FsKind::File), ("c.txt".to_string(), FsKind::File)];
+        assert_eq!(listed(dir.get_children().await.unwrap()), expected);
+
+        dir.scan().await.unwrap();
+        tmp.write("d.txt", "d");
+        // The scan's results are used until the next one.
+        assert_eq!(listed(dir.get_children().await.unwrap()), expected);
+        assert!(!dir.has_child("d.txt").await.unwrap());
+        dir.scan().await.unwrap();
+        assert!(dir.has_child("d.txt").await.unwrap());
+
+        let sub = dir.get_child("a").await.unwrap();
+        assert!(sub.is_dir());
+        let mut inner = sub.get_child("inner.txt").await.unwrap();
+        assert_eq!(inner.get_full_path(), tmp.join("a").join("inner.txt"));
+        assert_eq!(read_all(&mut *inner).await, b"inner");
+        assert!(matches!(dir.get_child("missing").await, Err(FsError::FileNotPresent(..))));
+    }
+
+    #[tokio::test]
+    async fn creating_a_taken_name_fails() {
+        let tmp = TempDir::new("async-create");
+        tmp.write("taken.txt", "");
+        std::fs::create_dir(tmp.join("taken-dir")).unwrap();
+
+        let mut dir = AsyncPhysicalDirectory::from_path(tmp.path());
+        dir.scan().await.unwrap();
+        let mut file = dir.new_file("new.txt").await.unwrap();
+        file.write(b"created").await.unwrap();
+        file.close().await.unwrap();
+        assert_eq!(std::fs::read_to_string(tmp.join("new.txt")).unwrap(), "created");
+        let sub = dir.new_dir("sub").await.unwrap();
+        assert!(sub.is_dir() && tmp.join("sub").is_dir());
+        assert!(dir.has_child("new.txt").await.unwrap() && dir.has_child("sub").await.unwrap());
+
+        for name in ["taken.txt", "taken-dir", "new.txt", "sub"] {
+            let path = tmp.join(name).to_string_lossy().to_string();
+            assert_eq!(dir.new_file(name).await.unwrap_err(), FsError::already_exists(path.clone()));
+            assert_eq!(dir.new_dir(name).await.unwrap_err(), FsError::already_exists(path));
+        }
+    }
+}
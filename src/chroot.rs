@@ -0,0 +1,683 @@
+//! The [chroot](`self`) module provides [`ChrootDirectory`], a wrapper presenting a directory as
+//! the root of its own tree.
+//!
+//! Everything above the wrapped directory is hidden: the wrapper has no parent, and objects
+//! reached through it report paths from `/`, the wrapped directory, on down. Files and
+//! directories reached through the wrapper are wrapped in turn, so handing one out never leaks a
+//! way back up. [`ChrootDirectory::resolve`] clamps `..` at the root the way `chroot(2)` does,
+//! and names handed to lookups, creation and renames must be single path segments, so they can't
+//! climb out either.
+//! Identities, timestamps and everything else besides paths and parents come from the wrapped
+//! objects unchanged.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+use std::path::{Component, PathBuf, Path};
+use std::time::SystemTime;
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind, DirEntry,
+    normalize_lexically, check_segment,
+    wrapper::{WrappedChildren, recache_renamed, fill_buf_copy},
+};
+
+pub struct ChrootDirectory {
+    inner: FilesystemObject,
+    name: PathBuf,
+    /// Path from the chroot's root, which is `/`.
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+
+    /// Wrapped children, kept so each child is wrapped once.
+    children: WrappedChildren,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+pub struct ChrootFile {
+    inner: FilesystemObject,
+    name: PathBuf,
+    path: PathBuf,
+    parent: FilesystemObject,
+    /// Copy of the inner file's buffered bytes, for `BufRead`.
+    buffer: Vec<u8>,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+impl ChrootDirectory {
+    /// Wraps the directory `inner` as the root of a tree of its own, at path `/`.
+    pub fn new(inner: FilesystemObject) -> FsResult<Arc<RwLock<Self>>> {
+        {
+            let inner = inner.read().unwrap();
+            if !inner.is_dir() {
+                return Err(FsError::not_a_directory(inner.get_full_path().to_string_lossy().to_string(), inner.kind()));
+            }
+        }
+
+        Ok(Self::new_node(inner, PathBuf::from("/"), PathBuf::from("/"), None))
+    }
+
+    fn new_node(inner: FilesystemObject, name: PathBuf, path: PathBuf, parent: Option<FilesystemObject>) -> Arc<RwLock<Self>> {
+        let new = Self{
+            inner,
+            name,
+            path,
+            parent,
+
+            children: WrappedChildren::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Returns the wrapper for the inner child `child` named `name`, creating it if needed.
+    fn wrap(&self, name: &str, child: FilesystemObject) -> FsResult<FilesystemObject> {
+        self.children.wrap(name, child, |name, child, kind| self.make_wrapper(name, child, kind))
+    }
+
+    /// Makes a wrapper named `name` for the inner child `child`, of kind `kind`.
+    fn make_wrapper(&self, name: &str, child: FilesystemObject, kind: FsKind) -> FsResult<FilesystemObject> {
+        let path = self.path.join(name);
+        Ok(match kind {
+            FsKind::Directory => Self::new_node(child, PathBuf::from(name), path, Some(self.get())),
+            FsKind::File => ChrootFile::new(child, PathBuf::from(name), path, self.get()),
+        })
+    }
+
+    /// Finds the object at `path` under this directory. `.` and `..` are resolved lexically, with
+    /// `..` at this directory staying there, and a leading `/` means this directory too, so on
+    /// the root no path leads outside the chroot.
+    pub fn resolve(&self, path: &str) -> FsResult<FilesystemObject> {
+        let target = normalize_lexically(&Path::new("/").join(path));
+
+        let mut current: Option<FilesystemObject> = None;
+        for component in target.components() {
+            let Component::Normal(segment) = component else { continue };
+            let segment = segment.to_string_lossy();
+            current = Some(match current {
+                Some(dir) => dir.read().unwrap().get_child(&segment)?,
+                None => self.get_child(&segment)?,
+            });
+        }
+
+        Ok(current.unwrap_or_else(|| self.get()))
+    }
+}
+
+impl ChrootFile {
+    fn new(inner: FilesystemObject, name: PathBuf, path: PathBuf, parent: FilesystemObject) -> Arc<RwLock<Self>> {
+        let new = Self{
+            inner,
+            name,
+            path,
+            parent,
+            buffer: Vec::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+}
+
+impl fmt::Debug for ChrootDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChrootDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ChrootFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChrootFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Read for ChrootDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for ChrootDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for ChrootDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for ChrootDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for ChrootDirectory {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.read().unwrap().exists()
+    }
+
+    fn revalidate(&self) -> FsResult<FsKind> {
+        self.inner.read().unwrap().revalidate()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.inner.read().unwrap().modified()
+    }
+
+    /// `None` at the root, which hides the rest of the tree.
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Ok(ObjectId::Chroot(Box::new(self.inner.read().unwrap().identity()?)))
+    }
+
+    fn readonly(&self) -> bool {
+        self.inner.read().unwrap().readonly()
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.inner.write().unwrap().set_readonly(readonly)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.read().unwrap().capabilities()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        self.inner.read().unwrap().fs_type()
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().free_space()
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().total_space()
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("chroot", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        let children = self.inner.read().unwrap().get_children()?;
+        children.into_iter()
+            .map(|child| self.children.wrap_named(child, |name, child, kind| self.make_wrapper(name, child, kind)))
+            .collect()
+    }
+
+    fn count_children(&self) -> FsResult<usize> {
+        self.inner.read().unwrap().count_children()
+    }
+
+    fn list(&self) -> FsResult<Vec<DirEntry>> {
+        self.inner.read().unwrap().list()
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        check_segment(name)?;
+        let child = self.inner.read().unwrap().get_child(name)?;
+        self.wrap(name, child)
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        check_segment(name)?;
+        self.inner.read().unwrap().has_child(name)
+    }
+
+    fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult {
+        check_segment(name)?;
+        check_segment(new_name)?;
+        self.children.child_rename(&self.inner, name, new_name)
+    }
+
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        let Some(parent) = self.parent.clone() else {
+            return Err(FsError::unsupported("chroot", "renaming the root"));
+        };
+        check_segment(new_name)?;
+        self.inner.write().unwrap().rename(new_name)?;
+
+        let old_name = std::mem::replace(&mut self.name, PathBuf::from(new_name));
+        self.path = self.path.with_file_name(new_name);
+        // Wrapped children carry the old path.
+        self.children.clear();
+
+        recache_renamed(&parent, &old_name, new_name, self.get())
+    }
+
+    fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        check_segment(name)?;
+        let file = self.inner.write().unwrap().new_file(name, buffer_size)?;
+        self.children.remove(name);
+        self.wrap(name, file)
+    }
+
+    fn new_dir(&mut self, name: &str) -> FsResult<FilesystemObject> {
+        check_segment(name)?;
+        let dir = self.inner.write().unwrap().new_dir(name)?;
+        self.children.remove(name);
+        self.wrap(name, dir)
+    }
+
+    fn create_temp_file(&mut self, prefix: &str, ext: &str) -> FsResult<FilesystemObject> {
+        let file = self.inner.write().unwrap().create_temp_file(prefix, ext)?;
+        self.children.wrap_named(file, |name, file, kind| self.make_wrapper(name, file, kind))
+    }
+
+    fn create_temp_dir(&mut self, prefix: &str) -> FsResult<FilesystemObject> {
+        let dir = self.inner.write().unwrap().create_temp_dir(prefix)?;
+        self.children.wrap_named(dir, |name, dir, kind| self.make_wrapper(name, dir, kind))
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.remove(name);
+        self.inner.write().unwrap().drop_child(name)
+    }
+
+    fn cache_child(&mut self, name: &str, child: FilesystemObject) -> FsResult {
+        self.children.insert(name, child);
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        self.inner.write().unwrap().scan()
+    }
+
+    fn validate_cache(&self) -> FsResult<bool> {
+        self.inner.read().unwrap().validate_cache()
+    }
+
+    /// Repairs the inner directory's cache, then drops the wrappers of children that changed kind.
+    fn repair_cache(&mut self) -> FsResult {
+        self.children.repair(&self.inner)
+    }
+
+    fn delete(&mut self) -> FsResult {
+        self.inner.write().unwrap().delete()?;
+        self.children.clear();
+        if let Some(parent) = self.parent.as_ref() {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+
+    fn delete_all(&mut self) -> FsResult {
+        self.inner.write().unwrap().delete_all()?;
+        self.children.clear();
+        if let Some(parent) = self.parent.as_ref() {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.children.clear();
+        self.inner.write().unwrap().invalidate();
+    }
+}
+
+impl Read for ChrootFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.write().unwrap().read(buf)
+    }
+}
+
+impl BufRead for ChrootFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        fill_buf_copy(&self.inner, &mut self.buffer)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.write().unwrap().consume(amt);
+    }
+}
+
+impl Seek for ChrootFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.inner.write().unwrap().seek(pos)
+    }
+}
+
+impl Write for ChrootFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.inner.write().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().flush()
+    }
+}
+
+impl FilesystemObjectRaw for ChrootFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.read().unwrap().exists()
+    }
+
+    fn revalidate(&self) -> FsResult<FsKind> {
+        self.inner.read().unwrap().revalidate()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        self.inner.read().unwrap().size()
+    }
+
+    fn truncate(&mut self, len: usize) -> FsResult {
+        self.inner.write().unwrap().truncate(len)
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().position()
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        self.inner.read().unwrap().read_at(offset, len)
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.inner.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Ok(ObjectId::Chroot(Box::new(self.inner.read().unwrap().identity()?)))
+    }
+
+    fn readonly(&self) -> bool {
+        self.inner.read().unwrap().readonly()
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.inner.write().unwrap().set_readonly(readonly)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.read().unwrap().capabilities()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        self.inner.read().unwrap().fs_type()
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().free_space()
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().total_space()
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("chroot", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().open()
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.read().unwrap().is_open()
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_all()
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_data()
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let inner = self.inner.read().unwrap().duplicate()?;
+        Ok(ChrootFile::new(inner, self.name.clone(), self.path.clone(), self.parent.clone()))
+    }
+
+    fn close(&mut self) {
+        self.inner.write().unwrap().close();
+    }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        check_segment(new_name)?;
+        self.inner.write().unwrap().rename(new_name)?;
+
+        let old_name = std::mem::replace(&mut self.name, PathBuf::from(new_name));
+        self.path = self.path.with_file_name(new_name);
+
+        recache_renamed(&self.parent, &old_name, new_name, self.get())
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn set_buffer_size(&mut self, size: usize) {
+        self.inner.write().unwrap().set_buffer_size(size);
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn delete(&mut self) -> FsResult {
+        self.inner.write().unwrap().delete()?;
+        self.parent.write().unwrap().drop_child(&self.name.to_string_lossy())
+    }
+
+    fn invalidate(&mut self) {
+        self.inner.write().unwrap().invalidate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysicalDirectory;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn names_cannot_climb_out_of_the_jail() {
+        let tmp = TempDir::new("chroot-escape");
+        tmp.write("jail/a.txt", "inside");
+        tmp.write("outside.txt", "outside");
+        let root = ChrootDirectory::new(PhysicalDirectory::from_path(tmp.join("jail"))).unwrap();
+        let mut root = root.write().unwrap();
+
+        for name in ["../escaped_dir", "..", "sub/../../escaped_dir"] {
+            assert!(matches!(root.new_dir(name), Err(FsError::Generic(..))), "{name}");
+        }
+        assert!(matches!(root.new_file("../f.txt", 0), Err(FsError::Generic(..))));
+        assert!(matches!(root.child_rename("a.txt", "../moved.txt"), Err(FsError::Generic(..))));
+        assert!(matches!(root.child_rename("../outside.txt", "stolen.txt"), Err(FsError::Generic(..))));
+        assert!(matches!(root.get_child(".."), Err(FsError::Generic(..))));
+        assert!(matches!(root.has_child("../outside.txt"), Err(FsError::Generic(..))));
+
+        let file = root.get_child("a.txt").unwrap();
+        assert!(matches!(file.write().unwrap().rename("../moved.txt"), Err(FsError::Generic(..))));
+        let sub = root.new_dir("sub").unwrap();
+        assert!(matches!(sub.write().unwrap().rename("../../escaped_dir"), Err(FsError::Generic(..))));
+
+        let mut on_disk: Vec<_> = std::fs::read_dir(tmp.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        on_disk.sort();
+        assert_eq!(on_disk, ["jail", "outside.txt"]);
+        assert_eq!(std::fs::read_to_string(tmp.join("jail").join("a.txt")).unwrap(), "inside");
+
+        // `..` in resolved paths stops at the root.
+        let resolved = root.resolve("../../a.txt").unwrap();
+        assert_eq!(resolved.read().unwrap().get_full_path(), Path::new("/a.txt"));
+    }
+
+    #[test]
+    fn paths_start_at_the_root() {
+        let tmp = TempDir::new("chroot-paths");
+        tmp.write("jail/sub/file.txt", "nested");
+        let root = ChrootDirectory::new(PhysicalDirectory::from_path(tmp.join("jail"))).unwrap();
+
+        let file = root.read().unwrap().resolve("sub/./file.txt").unwrap();
+        let file = file.read().unwrap();
+        assert_eq!(file.get_full_path(), Path::new("/sub/file.txt"));
+        let sub = file.get_parent().unwrap();
+        assert_eq!(sub.read().unwrap().get_full_path(), Path::new("/sub"));
+        assert!(sub.read().unwrap().get_parent().unwrap().read().unwrap().get_parent().is_none());
+        assert!(root.read().unwrap().get_parent().is_none());
+    }
+
+    #[test]
+    fn identities_stay_inside_the_jail() {
+        let tmp = TempDir::new("chroot-identity");
+        tmp.write("jail/a.txt", "inside");
+        let physical = PhysicalDirectory::from_path(tmp.join("jail"));
+        let root = ChrootDirectory::new(physical.clone()).unwrap();
+
+        let file = root.read().unwrap().get_child("a.txt").unwrap();
+        let again = ChrootDirectory::new(physical.clone()).unwrap().read().unwrap().get_child("a.txt").unwrap();
+        let unwrapped = physical.read().unwrap().get_child("a.txt").unwrap();
+        assert!(file.read().unwrap().same_file(&again).unwrap());
+        assert!(!file.read().unwrap().same_file(&unwrapped).unwrap());
+        assert!(!matches!(root.read().unwrap().identity(), Ok(ObjectId::Physical { .. })));
+    }
+}
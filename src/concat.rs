@@ -0,0 +1,445 @@
+//! The [concat](`self`) module provides [`ConcatFile`], a read-only file presenting several files
+//! back to back as one stream, such as the parts of a split archive (`.001`, `.002`, ...).
+//!
+//! The parts' sizes are taken when the file is created and fix where each part starts, so parts
+//! shouldn't change size while they're concatenated. Each part keeps its own cursor, which the
+//! concatenation moves as it reads through it.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+use std::path::{PathBuf, Path};
+use std::time::SystemTime;
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind,
+    error::read_only_io,
+};
+
+pub struct ConcatFile {
+    name: PathBuf,
+    parts: Vec<FilesystemObject>,
+    /// Offset in the concatenation at which each part starts, plus the total size at the end.
+    starts: Vec<u64>,
+
+    position: u64,
+    /// The part whose cursor is known to sit at `position`, if any.
+    current: Option<usize>,
+    /// Copy of the current part's buffered bytes, for `BufRead`.
+    buffer: Vec<u8>,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+/// Concatenates `parts`, in order, into a [`ConcatFile`] named `concat`.
+pub fn concat_readers(parts: Vec<FilesystemObject>) -> FsResult<Arc<RwLock<ConcatFile>>> {
+    ConcatFile::new("concat", parts)
+}
+
+impl ConcatFile {
+    /// Concatenates `parts`, in order, into a file named `name`. Errors if any part isn't a file.
+    pub fn new(name: &str, parts: Vec<FilesystemObject>) -> FsResult<Arc<RwLock<Self>>> {
+        let mut starts = vec![0];
+        for part in &parts {
+            let size = part.read().unwrap().size()? as u64;
+            starts.push(starts.last().unwrap() + size);
+        }
+
+        let new = Self{
+            name: PathBuf::from(name),
+            parts,
+            starts,
+
+            position: 0,
+            current: None,
+            buffer: Vec::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        Ok(arc)
+    }
+
+    /// The files being concatenated, in order.
+    pub fn parts(&self) -> &[FilesystemObject] {
+        &self.parts
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    fn len(&self) -> u64 {
+        *self.starts.last().unwrap()
+    }
+
+    /// The index of the part holding `offset`, or `None` at or past the end.
+    fn part_at(&self, offset: u64) -> Option<usize> {
+        if offset >= self.len() {
+            return None;
+        }
+
+        // Skips over empty parts, which start where the next one does.
+        Some(self.starts.partition_point(|start| *start <= offset) - 1)
+    }
+
+    /// The part holding the current position, with its cursor moved there. `None` at the end.
+    fn current_part(&mut self) -> IoResult<Option<FilesystemObject>> {
+        let Some(index) = self.part_at(self.position) else {
+            return Ok(None);
+        };
+
+        let part = self.parts[index].clone();
+        if self.current != Some(index) {
+            part.write().unwrap().seek(SeekFrom::Start(self.position - self.starts[index]))?;
+            self.current = Some(index);
+        }
+
+        Ok(Some(part))
+    }
+
+    /// Moves the position on by `amt` bytes read from the current part.
+    fn advance(&mut self, amt: usize) {
+        self.position += amt as u64;
+        if self.current.is_some_and(|index| self.position >= self.starts[index + 1]) {
+            self.current = None;
+        }
+    }
+
+    /// Errors for a part that ended before the size it had when concatenated.
+    fn part_shrunk(&self) -> IoError {
+        let index = self.part_at(self.position).unwrap();
+        let part = self.parts[index].read().unwrap().get_full_path();
+        IoError::new(IoErrorKind::UnexpectedEof, format!("'{}' shrank while concatenated into '{}'", part.display(), self.display_path()))
+    }
+}
+
+impl fmt::Debug for ConcatFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcatFile")
+            .field("name", &self.name)
+            .field("parts", &self.parts.len())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Read for ConcatFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let Some(part) = self.current_part()? else {
+            return Ok(0);
+        };
+
+        let count = part.write().unwrap().read(buf)?;
+        if count == 0 && !buf.is_empty() {
+            return Err(self.part_shrunk());
+        }
+        self.advance(count);
+
+        Ok(count)
+    }
+}
+
+impl BufRead for ConcatFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        self.buffer.clear();
+        let Some(part) = self.current_part()? else {
+            return Ok(&self.buffer);
+        };
+
+        // The part's buffer can't be lent out past its lock guard, so it's copied.
+        let mut part = part.write().unwrap();
+        let available = part.fill_buf()?;
+        if available.is_empty() {
+            drop(part);
+            return Err(self.part_shrunk());
+        }
+        self.buffer.extend_from_slice(available);
+
+        Ok(&self.buffer)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.buffer.len());
+        self.buffer.drain(..amt);
+        if let Some(index) = self.current {
+            self.parts[index].write().unwrap().consume(amt);
+        }
+        self.advance(amt);
+    }
+}
+
+impl Seek for ConcatFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos as i64, 0),
+            SeekFrom::End(offset) => (self.len() as i64, offset),
+            SeekFrom::Current(offset) => (self.position as i64, offset),
+        };
+
+        let position = base.checked_add(offset)
+            .filter(|position| *position >= 0)
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        self.position = position as u64;
+        self.current = None;
+        self.buffer.clear();
+
+        Ok(self.position)
+    }
+}
+
+impl Write for ConcatFile {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(read_only_io(self.display_path()))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for ConcatFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        self.parts.iter().all(|part| part.read().unwrap().exists())
+    }
+
+    /// The sum of the parts' sizes when they were concatenated.
+    fn size(&self) -> FsResult<usize> {
+        Ok(self.len() as usize)
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Ok(self.position)
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        let end = offset.saturating_add(len as u64).min(self.len());
+        let mut data = Vec::with_capacity(end.saturating_sub(offset) as usize);
+
+        let mut position = offset;
+        while position < end {
+            let index = self.part_at(position).unwrap();
+            let part_end = self.starts[index + 1].min(end);
+            let chunk = self.parts[index].read().unwrap().read_at(position - self.starts[index], (part_end - position) as usize)?;
+            if chunk.is_empty() {
+                return Err(FsError::from(self.part_shrunk()));
+            }
+
+            position += chunk.len() as u64;
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(data)
+    }
+
+    /// The latest modification time among the parts.
+    fn modified(&self) -> FsResult<SystemTime> {
+        let mut latest = SystemTime::UNIX_EPOCH;
+        for part in &self.parts {
+            latest = latest.max(part.read().unwrap().modified()?);
+        }
+
+        Ok(latest)
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        None
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.name.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Err(FsError::unsupported("concat", "identity"))
+    }
+
+    fn readonly(&self) -> bool {
+        true
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        match readonly {
+            true => Ok(()),
+            false => Err(FsError::unsupported("concat", "writing")),
+        }
+    }
+
+    /// Concatenations can only be read.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("concat".to_string())
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("concat", "free_space"))
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("concat", "total_space"))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("concat", "move_to"))
+    }
+
+    /// Opens every part.
+    fn open(&mut self) -> IoResult<()> {
+        for part in &self.parts {
+            part.write().unwrap().open()?;
+        }
+        self.position = 0;
+        self.current = None;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        self.parts.iter().all(|part| part.read().unwrap().is_open())
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::unsupported("concat", "sync_all"))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::unsupported("concat", "sync_data"))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let parts = self.parts.iter()
+            .map(|part| part.read().unwrap().duplicate())
+            .collect::<FsResult<Vec<_>>>()?;
+
+        Ok(ConcatFile::new(&self.name.to_string_lossy(), parts)?)
+    }
+
+    /// Closes every part.
+    fn close(&mut self) {
+        for part in &self.parts {
+            part.write().unwrap().close();
+        }
+        self.current = None;
+        self.buffer.clear();
+    }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::unsupported("concat", "rename"))
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Sets the buffer size of every part.
+    fn set_buffer_size(&mut self, size: usize) {
+        for part in &self.parts {
+            part.write().unwrap().set_buffer_size(size);
+        }
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::unsupported("concat", "delete"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryFile;
+
+    fn parts() -> Vec<FilesystemObject> {
+        vec![
+            MemoryFile::from_bytes("archive.001", b"hello "),
+            MemoryFile::from_bytes("archive.002", b""),
+            MemoryFile::from_bytes("archive.003", b"split "),
+            MemoryFile::from_bytes("archive.004", b"world"),
+        ]
+    }
+
+    #[test]
+    fn reads_cross_part_boundaries() {
+        let file = concat_readers(parts()).unwrap();
+        let mut file = file.write().unwrap();
+        assert_eq!(file.size().unwrap(), 17);
+
+        let mut all = String::new();
+        file.read_to_string(&mut all).unwrap();
+        assert_eq!(all, "hello split world");
+
+        // Spanning the end of one part, an empty one, and the start of the next.
+        file.seek(SeekFrom::Start(3)).unwrap();
+        let mut span = [0; 6];
+        file.read_exact(&mut span).unwrap();
+        assert_eq!(&span, b"lo spl");
+
+        file.seek(SeekFrom::End(-8)).unwrap();
+        let mut tail = String::new();
+        file.read_to_string(&mut tail).unwrap();
+        assert_eq!(tail, "it world");
+        assert_eq!(file.read_at(10, 4).unwrap(), b"t wo");
+    }
+
+    #[test]
+    fn writes_are_refused() {
+        let file = concat_readers(parts()).unwrap();
+        assert!(file.write().unwrap().write_all(b"nope").is_err());
+        assert_eq!(file.read().unwrap().capabilities(), Capabilities::default());
+    }
+}
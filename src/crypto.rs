@@ -0,0 +1,306 @@
+//! Transparent encryption of file contents, enabled by the `crypto` feature.
+//!
+//! An encrypted file is a header (magic, format version and a random nonce prefix) followed by
+//! chunks of at most [`CHUNK_SIZE`] plaintext bytes, each sealed with ChaCha20-Poly1305 and
+//! framed by a flag byte and its ciphertext length. Chunk nonces are the prefix plus a counter, and
+//! the flag marking the final chunk is authenticated, so reordered, dropped or appended chunks fail
+//! to decrypt just like modified ones.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    Write, Read,
+    SeekFrom,
+};
+
+use super::{FilesystemObject, FsError, FsResult};
+
+/// Plaintext bytes per sealed chunk.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+const MAGIC: &[u8; 4] = b"FSAE";
+const VERSION: u8 = 1;
+const PREFIX_SIZE: usize = 8;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + PREFIX_SIZE;
+const TAG_SIZE: usize = 16;
+
+const FLAG_MORE: u8 = 0;
+const FLAG_FINAL: u8 = 1;
+
+struct Reader {
+    prefix: [u8; PREFIX_SIZE],
+    counter: u32,
+    plaintext: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+struct Writer {
+    prefix: [u8; PREFIX_SIZE],
+    counter: u32,
+    pending: Vec<u8>,
+}
+
+enum State {
+    Idle,
+    Reading(Reader),
+    Writing(Writer),
+    Finished,
+}
+
+/// Wraps a file so reads decrypt and writes encrypt its contents, a chunk at a time.
+///
+/// Reading and writing both start at the beginning of the inner file, and an `EncryptedFile` does
+/// one or the other. Writing replaces the inner file's contents; call [`Self::finish`] once done
+/// so the final chunk gets sealed (dropping the wrapper does so too, ignoring errors). Corrupt or
+/// tampered contents, and a wrong key, make reads fail with [`FsError::DecryptionFailed`] wrapped
+/// in an I/O error, which `?` unwraps back into an [`FsError`].
+pub struct EncryptedFile {
+    inner: FilesystemObject,
+    cipher: ChaCha20Poly1305,
+    state: State,
+}
+
+fn nonce(prefix: &[u8; PREFIX_SIZE], counter: u32) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[PREFIX_SIZE..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+impl EncryptedFile {
+    pub fn new(inner: FilesystemObject, key: &[u8; 32]) -> Self {
+        Self{
+            inner,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            state: State::Idle,
+        }
+    }
+
+    /// Returns the wrapped file.
+    pub fn inner(&self) -> &FilesystemObject {
+        &self.inner
+    }
+
+    /// Seals whatever is still pending as the final chunk. Finishing before anything was written
+    /// produces an encrypted empty file; once reading, this does nothing.
+    pub fn finish(&mut self) -> FsResult {
+        if let State::Idle = self.state {
+            self.begin_write()?;
+        }
+
+        if let State::Writing(writer) = &mut self.state {
+            let chunk = std::mem::take(&mut writer.pending);
+            Self::seal_chunk(&self.cipher, &self.inner, writer, &chunk, FLAG_FINAL)?;
+            self.inner.write().unwrap().flush()?;
+            self.state = State::Finished;
+        }
+
+        Ok(())
+    }
+
+    fn decryption_failed(&self) -> IoError {
+        let path = self.inner.read().unwrap().get_full_path().to_string_lossy().to_string();
+        IoError::new(IoErrorKind::InvalidData, FsError::decryption_failed(path))
+    }
+
+    fn begin_write(&mut self) -> IoResult<()> {
+        let prefix: [u8; PREFIX_SIZE] = ChaCha20Poly1305::generate_nonce(&mut OsRng)[..PREFIX_SIZE].try_into().unwrap();
+
+        let mut inner = self.inner.write().unwrap();
+        inner.seek(SeekFrom::Start(0))?;
+        inner.truncate(0)?;
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        inner.write_all(&prefix)?;
+        drop(inner);
+
+        self.state = State::Writing(Writer{ prefix, counter: 0, pending: Vec::new() });
+        Ok(())
+    }
+
+    fn seal_chunk(cipher: &ChaCha20Poly1305, inner: &FilesystemObject, writer: &mut Writer, chunk: &[u8], flag: u8) -> IoResult<()> {
+        let counter = writer.counter;
+        writer.counter = counter.checked_add(1)
+            .ok_or_else(|| IoError::new(IoErrorKind::FileTooLarge, "too many chunks for one encrypted file"))?;
+
+        let sealed = cipher.encrypt(&nonce(&writer.prefix, counter), Payload{ msg: chunk, aad: &[flag] })
+            .map_err(|_| IoError::other("encryption failed"))?;
+
+        let mut inner = inner.write().unwrap();
+        inner.write_all(&[flag])?;
+        inner.write_all(&(sealed.len() as u32).to_le_bytes())?;
+        inner.write_all(&sealed)
+    }
+
+    fn begin_read(&mut self) -> IoResult<()> {
+        let mut header = [0; HEADER_SIZE];
+        {
+            let mut inner = self.inner.write().unwrap();
+            inner.seek(SeekFrom::Start(0))?;
+            if inner.read_exact(&mut header).is_err() {
+                drop(inner);
+                return Err(self.decryption_failed());
+            }
+        }
+
+        if &header[..MAGIC.len()] != MAGIC || header[MAGIC.len()] != VERSION {
+            return Err(self.decryption_failed());
+        }
+
+        self.state = State::Reading(Reader{
+            prefix: header[MAGIC.len() + 1..].try_into().unwrap(),
+            counter: 0,
+            plaintext: Vec::new(),
+            pos: 0,
+            done: false,
+        });
+        Ok(())
+    }
+
+    /// Reads and opens the next chunk. Returns `None` for anything malformed.
+    fn open_chunk(&mut self) -> IoResult<Option<()>> {
+        let State::Reading(reader) = &mut self.state else { unreachable!() };
+        let mut inner = self.inner.write().unwrap();
+
+        let mut frame = [0; 5];
+        if inner.read_exact(&mut frame).is_err() {
+            return Ok(None);
+        }
+        let flag = frame[0];
+        let len = u32::from_le_bytes(frame[1..].try_into().unwrap()) as usize;
+        if flag > FLAG_FINAL || !(TAG_SIZE..=CHUNK_SIZE + TAG_SIZE).contains(&len) {
+            return Ok(None);
+        }
+
+        let mut sealed = vec![0; len];
+        if inner.read_exact(&mut sealed).is_err() {
+            return Ok(None);
+        }
+
+        let Ok(plaintext) = self.cipher.decrypt(&nonce(&reader.prefix, reader.counter), Payload{ msg: &sealed, aad: &[flag] }) else {
+            return Ok(None);
+        };
+        reader.counter = reader.counter.wrapping_add(1);
+        reader.plaintext = plaintext;
+        reader.pos = 0;
+
+        if flag == FLAG_FINAL {
+            reader.done = true;
+            // Anything after the final chunk was appended by someone else.
+            if inner.read(&mut [0])? != 0 {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(()))
+    }
+}
+
+impl Read for EncryptedFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match self.state {
+            State::Idle => self.begin_read()?,
+            State::Reading(_) => {},
+            _ => return Err(IoError::new(IoErrorKind::InvalidInput, "encrypted file is being written")),
+        }
+
+        loop {
+            let State::Reading(reader) = &mut self.state else { unreachable!() };
+            if reader.pos < reader.plaintext.len() {
+                let count = (reader.plaintext.len() - reader.pos).min(buf.len());
+                buf[..count].copy_from_slice(&reader.plaintext[reader.pos..reader.pos + count]);
+                reader.pos += count;
+                return Ok(count);
+            }
+            if reader.done {
+                return Ok(0);
+            }
+
+            if self.open_chunk()?.is_none() {
+                return Err(self.decryption_failed());
+            }
+        }
+    }
+}
+
+impl Write for EncryptedFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        match self.state {
+            State::Idle => self.begin_write()?,
+            State::Writing(_) => {},
+            _ => return Err(IoError::new(IoErrorKind::InvalidInput, "encrypted file is being read or was finished")),
+        }
+
+        let State::Writing(writer) = &mut self.state else { unreachable!() };
+        writer.pending.extend_from_slice(buf);
+        // A full chunk is only sealed once more data follows, since the last one is marked final.
+        while writer.pending.len() > CHUNK_SIZE {
+            let rest = writer.pending.split_off(CHUNK_SIZE);
+            let chunk = std::mem::replace(&mut writer.pending, rest);
+            Self::seal_chunk(&self.cipher, &self.inner, writer, &chunk, FLAG_MORE)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    /// Flushes sealed chunks to the inner file. Pending plaintext stays buffered until
+    /// [`EncryptedFile::finish`].
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().flush()
+    }
+}
+
+impl Drop for EncryptedFile {
+    fn drop(&mut self) {
+        // `drop` can't report errors; call `finish` to see them.
+        if let State::Writing(_) = self.state {
+            let _ = self.finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::PhysicalFile;
+    use crate::test_util::TempDir;
+
+    fn decrypt(path: &std::path::Path, key: &[u8; 32]) -> FsResult<Vec<u8>> {
+        let inner: FilesystemObject = PhysicalFile::from_path(path, 4096);
+        let mut file = EncryptedFile::new(inner, key);
+        let mut out = Vec::new();
+        file.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn round_trips_and_detects_tampering() {
+        let tmp = TempDir::new("crypto");
+        let path = tmp.join("secret.bin");
+        fs::write(&path, b"").unwrap();
+        let key = [7; 32];
+        // Spans several chunks, with a partial one at the end.
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 1000).map(|i| (i % 251) as u8).collect();
+
+        let inner: FilesystemObject = PhysicalFile::from_path(&path, 4096);
+        let mut file = EncryptedFile::new(inner, &key);
+        file.write_all(&data).unwrap();
+        file.finish().unwrap();
+        drop(file);
+
+        let sealed = fs::read(&path).unwrap();
+        assert!(!sealed.windows(64).any(|window| window == &data[..64]));
+        assert_eq!(decrypt(&path, &key).unwrap(), data);
+        assert!(matches!(decrypt(&path, &[8; 32]), Err(FsError::DecryptionFailed(..))));
+
+        let mut tampered = sealed;
+        tampered[HEADER_SIZE + CHUNK_SIZE + 100] ^= 1;
+        fs::write(&path, &tampered).unwrap();
+        assert!(matches!(decrypt(&path, &key), Err(FsError::DecryptionFailed(..))));
+    }
+}
@@ -0,0 +1,106 @@
+//! Comparison of two trees by relative path and contents, e.g. an archive against the directory
+//! it was packaged from.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::{FilesystemObject, FilesystemObjectRaw, FsResult, walk_files};
+
+/// Differences of one tree relative to another. Paths are relative to the compared roots and
+/// sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    /// Files only present in the compared tree.
+    pub added: Vec<PathBuf>,
+    /// Files present in both trees whose contents differ.
+    pub changed: Vec<PathBuf>,
+    /// Files only present in the tree compared against.
+    pub removed: Vec<PathBuf>,
+}
+
+impl Delta {
+    /// Whether the two trees hold the same files with the same contents.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Collects every file under `dir`, keyed by its path relative to `dir`.
+fn collect<T: FilesystemObjectRaw + ?Sized>(dir: &T, out: &mut BTreeMap<PathBuf, FilesystemObject>) -> FsResult {
+    walk_files(dir, Path::new(""), &mut |path, file| {
+        out.insert(path, file);
+        Ok(())
+    })
+}
+
+pub(crate) fn delta<T: FilesystemObjectRaw + ?Sized>(tree: &T, against: &dyn FilesystemObjectRaw) -> FsResult<Delta> {
+    let mut ours = BTreeMap::new();
+    let mut theirs = BTreeMap::new();
+    collect(tree, &mut ours)?;
+    collect(against, &mut theirs)?;
+
+    let mut delta = Delta::default();
+    for (path, file) in &ours {
+        match theirs.get(path) {
+            None => delta.added.push(path.clone()),
+            Some(other) => {
+                if !file.write().unwrap().content_equals(other)? {
+                    delta.changed.push(path.clone());
+                }
+            },
+        }
+    }
+    delta.removed = theirs.into_keys()
+        .filter(|path| !ours.contains_key(path))
+        .collect();
+
+    Ok(delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysicalDirectory;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn delta_reports_added_and_removed_files() {
+        let (ours, theirs) = (TempDir::new("delta-ours"), TempDir::new("delta-theirs"));
+        ours.write("same.txt", "same");
+        ours.write("sub/new.txt", "new");
+        theirs.write("same.txt", "same");
+        theirs.write("old.txt", "old");
+
+        let ours = PhysicalDirectory::from_path(ours.path());
+        let theirs = PhysicalDirectory::from_path(theirs.path());
+        let delta = ours.read().unwrap().archive_delta(&*theirs.read().unwrap()).unwrap();
+        assert_eq!(delta, Delta {
+            added: vec![Path::new("sub").join("new.txt")],
+            changed: vec![],
+            removed: vec![PathBuf::from("old.txt")],
+        });
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn archive_delta_finds_the_changed_entry() {
+        use std::io::Write;
+
+        let tmp = TempDir::new("delta-zip");
+        tmp.write("dir/a.txt", "same");
+        tmp.write("dir/sub/b.txt", "old contents");
+
+        let path = tmp.join("archive.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, contents) in [("a.txt", "same"), ("sub/b.txt", "new contents")] {
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let archive = crate::ZipDirectory::new(crate::PhysicalFile::from_path(&path, 0)).unwrap();
+        let dir = PhysicalDirectory::from_path(tmp.join("dir"));
+        let delta = archive.read().unwrap().archive_delta(&*dir.read().unwrap()).unwrap();
+        assert_eq!(delta, Delta { changed: vec![Path::new("sub").join("b.txt")], ..Delta::default() });
+    }
+}
@@ -1,39 +1,777 @@
 #[cfg(feature = "zip")]
 use zip::result::ZipError;
 
-use std::io::Error as IoError;
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+#[cfg(feature = "backtrace")]
+use std::{backtrace::Backtrace, sync::Arc};
 
-#[derive(derive_more::From, Debug)]
+use super::FsKind;
+
+/// With the `backtrace` feature, every variant also carries a `Trace` of where it was raised as its
+/// last field, except the ones wrapping another [`FsError`], which report that error's. Crate
+/// errors are easiest made with the constructors below, which capture it when enabled.
+#[derive(Debug)]
 pub enum FsError {
-    NotAFile(String),
-    NotADirectory(String),
+    /// A file-only operation was attempted on the object at this path, which is of the given kind.
+    NotAFile(String, FsKind, #[cfg(feature = "backtrace")] Trace),
+    /// A directory-only operation was attempted on the object at this path, which is of the given
+    /// kind.
+    NotADirectory(String, FsKind, #[cfg(feature = "backtrace")] Trace),
 
-    #[from]
-    IoError(IoError),
-    #[from]
+    IoError(IoError, #[cfg(feature = "backtrace")] Trace),
+    /// An I/O operation failed because its target, or a directory on the way to it, doesn't exist.
+    NotFound(IoError, #[cfg(feature = "backtrace")] Trace),
+    /// An I/O operation was refused by the OS for lack of permissions.
+    PermissionDenied(IoError, #[cfg(feature = "backtrace")] Trace),
     #[cfg(feature = "zip")]
-    ZipError(ZipError),
+    ZipError(ZipError, #[cfg(feature = "backtrace")] Trace),
+
+    FileNotPresent(String, String, #[cfg(feature = "backtrace")] Trace),
+    FileNotOpen(String, #[cfg(feature = "backtrace")] Trace),
+    /// Creating or resolving the named segment of a multi-segment path failed.
+    PathSegment(String, Box<FsError>),
+    Unsupported {
+        backend: &'static str,
+        op: &'static str,
+        #[cfg(feature = "backtrace")]
+        trace: Trace,
+    },
+    /// The object at this path is read-only.
+    ReadOnly(String, #[cfg(feature = "backtrace")] Trace),
+    /// Something already exists at this path. When the OS reported it, rather than the crate's
+    /// own checks, its error is kept too, and the path is empty since the OS error doesn't say.
+    AlreadyExists(String, Option<IoError>, #[cfg(feature = "backtrace")] Trace),
+    /// The file at this path is locked by another handle.
+    Locked(String, #[cfg(feature = "backtrace")] Trace),
+    /// The object at this path was deleted. Deleting through the object or one of its ancestors
+    /// retires it for good. Deletion by something else, including replacing the file by a rename,
+    /// is noticed while the object has it open and only drops the open handle; an explicit
+    /// `open` picks up whatever is at the path by then.
+    Deleted(String, #[cfg(feature = "backtrace")] Trace),
+    /// An archive entry is named this, which is absolute, starts with a drive letter or climbs out
+    /// with `..`, so joining it onto a path could lead outside the archive's root.
+    UnsafePath(String, #[cfg(feature = "backtrace")] Trace),
+    /// A move that had to copy `from` to `to` finished the copy, but deleting the original failed,
+    /// so both now exist.
+    MoveIncomplete { from: String, to: String, error: Box<FsError> },
+    /// Writing to the object at `path` would take the space used under its quota past `limit`
+    /// bytes.
+    QuotaExceeded {
+        path: String,
+        limit: u64,
+        #[cfg(feature = "backtrace")]
+        trace: Trace,
+    },
+    /// The contents of the file at `path` didn't hash to the expected digest.
+    #[cfg(feature = "hash")]
+    HashMismatch {
+        path: String,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+        #[cfg(feature = "backtrace")]
+        trace: Trace,
+    },
+    /// The encrypted file at this path is corrupt, truncated, was tampered with, or the key is
+    /// wrong.
+    #[cfg(feature = "crypto")]
+    DecryptionFailed(String, #[cfg(feature = "backtrace")] Trace),
+    /// Line `line` (counting from 1) of the file at `path` isn't valid JSON for the expected type.
+    #[cfg(feature = "serde")]
+    JsonLine {
+        path: String,
+        line: usize,
+        error: serde_json::Error,
+        #[cfg(feature = "backtrace")]
+        trace: Trace,
+    },
+
+    Generic(String, #[cfg(feature = "backtrace")] Trace),
+}
+
+/// Where an [`FsError`] was raised: the backtrace captured there, returned by
+/// [`FsError::backtrace`]. Clones share it.
+#[cfg(feature = "backtrace")]
+#[derive(Clone)]
+pub struct Trace {
+    backtrace: Arc<Backtrace>,
+}
+
+#[cfg(feature = "backtrace")]
+impl Trace {
+    /// Captures the trace of the caller. Like [`Backtrace::capture`], the backtrace is only
+    /// actually walked when the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variable
+    /// enables it; otherwise it reports itself as disabled.
+    pub fn capture() -> Self {
+        Self{ backtrace: Arc::new(Backtrace::capture()) }
+    }
+}
 
-    FileNotPresent(String, String),
-    FileNotOpen(String),
+/// Builds the tuple variant `$variant` from `$field`s, adding a trace when the `backtrace` feature
+/// is on: `$trace` if given, else one captured here. Without the feature `$trace` isn't evaluated.
+macro_rules! traced {
+    ($variant:path, ($($field:expr),* $(,)?) $(, $trace:expr)?) => {{
+        #[cfg(feature = "backtrace")]
+        let error = $variant($($field,)* traced!(@trace $($trace)?));
+        #[cfg(not(feature = "backtrace"))]
+        let error = $variant($($field),*);
+        error
+    }};
+    (@trace) => { Trace::capture() };
+    (@trace $trace:expr) => { $trace };
+}
 
-    #[from]
-    Generic(String),
+#[cfg(feature = "backtrace")]
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace").finish_non_exhaustive()
+    }
 }
 
-impl std::fmt::Display for FsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FsError::NotAFile(path) => write!(f, "Not a file: {path}"),
-            FsError::NotADirectory(path) => write!(f, "Not a directory: {path}"),
-            FsError::IoError(error) => write!(f, "{error}"),
+            FsError::NotAFile(path, kind, ..) => write!(f, "Not a file: {path} is a {kind}"),
+            FsError::NotADirectory(path, kind, ..) => write!(f, "Not a directory: {path} is a {kind}"),
+            FsError::IoError(error, ..) => write!(f, "{error}"),
+            FsError::NotFound(error, ..) => write!(f, "{error}; check the path, or create its parent directory first"),
+            FsError::PermissionDenied(error, ..) => write!(f, "{error}; check the permissions of the object and its parents"),
             #[cfg(feature = "zip")]
-            FsError::ZipError(zerr) => write!(f, "{zerr}"),
-            FsError::FileNotPresent(_in, name) => write!(f, "[{_in}] no file named '{name}'"),
-            FsError::FileNotOpen(filename) => write!(f, "file '{filename}' is not open"),
-            FsError::Generic(msg) => write!(f, "{msg}"),
+            FsError::ZipError(zerr, ..) => write!(f, "{zerr}"),
+            FsError::FileNotPresent(_in, name, ..) => write!(f, "[{_in}] no file named '{name}'"),
+            FsError::FileNotOpen(filename, ..) => write!(f, "file '{filename}' is not open"),
+            FsError::PathSegment(segment, error) => write!(f, "at path segment '{segment}': {error}"),
+            FsError::Unsupported { backend, op, .. } => write!(f, "{backend} backend does not support {op}"),
+            FsError::ReadOnly(path, ..) => write!(f, "'{path}' is read-only"),
+            FsError::AlreadyExists(_, Some(error), ..) => write!(f, "{error}; remove what's there or pick another name"),
+            FsError::AlreadyExists(path, None, ..) => write!(f, "'{path}' already exists; remove it or pick another name"),
+            FsError::Locked(path, ..) => write!(f, "'{path}' is locked"),
+            FsError::Deleted(path, ..) => write!(f, "'{path}' was deleted"),
+            FsError::UnsafePath(name, ..) => write!(f, "entry name '{name}' leads outside the archive"),
+            FsError::MoveIncomplete { from, to, error } => write!(
+                f, "copied '{from}' to '{to}', but deleting the original failed: {error}",
+            ),
+            FsError::QuotaExceeded { path, limit, .. } => write!(f, "writing to '{path}' would exceed the quota of {limit} bytes"),
+            #[cfg(feature = "hash")]
+            FsError::HashMismatch { path, expected, actual, .. } => write!(
+                f, "hash mismatch for '{path}': expected {}, got {}", hex(expected), hex(actual),
+            ),
+            #[cfg(feature = "crypto")]
+            FsError::DecryptionFailed(path, ..) => write!(f, "failed to decrypt '{path}'"),
+            #[cfg(feature = "serde")]
+            FsError::JsonLine { path, line, error, .. } => write!(f, "'{path}' line {line}: {error}"),
+            FsError::Generic(msg, ..) => write!(f, "{msg}"),
         }
     }
 }
 
 impl std::error::Error for FsError {}
+
+/// Wrapped errors that aren't `Clone` themselves are rebuilt, so a clone displays the same and has
+/// the same [`FsError::io_kind`], but it's a different error object underneath:
+/// - I/O errors from the OS keep their error code, and ones wrapping an [`FsError`] clone it.
+///   Any other wrapped error is reduced to its message, so it can no longer be downcast.
+/// - Zip errors keep their variant, with I/O errors inside rebuilt as above.
+/// - JSON errors are reduced to their message, so their category, line and column can no longer
+///   be queried.
+///
+/// With the `backtrace` feature, the clone shares the original's trace.
+impl Clone for FsError {
+    fn clone(&self) -> Self {
+        match self {
+            FsError::NotAFile(path, kind, ..) => traced!(FsError::NotAFile, (path.clone(), *kind), self.trace().clone()),
+            FsError::NotADirectory(path, kind, ..) => {
+                traced!(FsError::NotADirectory, (path.clone(), *kind), self.trace().clone())
+            },
+            FsError::IoError(error, ..) => traced!(FsError::IoError, (clone_io(error)), self.trace().clone()),
+            FsError::NotFound(error, ..) => traced!(FsError::NotFound, (clone_io(error)), self.trace().clone()),
+            FsError::PermissionDenied(error, ..) => {
+                traced!(FsError::PermissionDenied, (clone_io(error)), self.trace().clone())
+            },
+            #[cfg(feature = "zip")]
+            FsError::ZipError(error, ..) => traced!(FsError::ZipError, (match error {
+                ZipError::Io(error) => ZipError::Io(clone_io(error)),
+                ZipError::InvalidArchive(msg) => ZipError::InvalidArchive(msg.clone()),
+                ZipError::UnsupportedArchive(msg) => ZipError::UnsupportedArchive(msg),
+                ZipError::FileNotFound => ZipError::FileNotFound,
+                ZipError::InvalidPassword => ZipError::InvalidPassword,
+                error => ZipError::InvalidArchive(error.to_string().into()),
+            }), self.trace().clone()),
+            FsError::FileNotPresent(path, name, ..) => {
+                traced!(FsError::FileNotPresent, (path.clone(), name.clone()), self.trace().clone())
+            },
+            FsError::FileNotOpen(path, ..) => traced!(FsError::FileNotOpen, (path.clone()), self.trace().clone()),
+            FsError::PathSegment(segment, error) => FsError::PathSegment(segment.clone(), error.clone()),
+            FsError::Unsupported { backend, op, .. } => FsError::Unsupported {
+                backend, op,
+                #[cfg(feature = "backtrace")]
+                trace: self.trace().clone(),
+            },
+            FsError::ReadOnly(path, ..) => traced!(FsError::ReadOnly, (path.clone()), self.trace().clone()),
+            FsError::AlreadyExists(path, error, ..) => {
+                traced!(FsError::AlreadyExists, (path.clone(), error.as_ref().map(clone_io)), self.trace().clone())
+            },
+            FsError::Locked(path, ..) => traced!(FsError::Locked, (path.clone()), self.trace().clone()),
+            FsError::Deleted(path, ..) => traced!(FsError::Deleted, (path.clone()), self.trace().clone()),
+            FsError::UnsafePath(name, ..) => traced!(FsError::UnsafePath, (name.clone()), self.trace().clone()),
+            FsError::MoveIncomplete { from, to, error } => FsError::MoveIncomplete {
+                from: from.clone(), to: to.clone(), error: error.clone(),
+            },
+            FsError::QuotaExceeded { path, limit, .. } => FsError::QuotaExceeded {
+                path: path.clone(), limit: *limit,
+                #[cfg(feature = "backtrace")]
+                trace: self.trace().clone(),
+            },
+            #[cfg(feature = "hash")]
+            FsError::HashMismatch { path, expected, actual, .. } => FsError::HashMismatch {
+                path: path.clone(), expected: expected.clone(), actual: actual.clone(),
+                #[cfg(feature = "backtrace")]
+                trace: self.trace().clone(),
+            },
+            #[cfg(feature = "crypto")]
+            FsError::DecryptionFailed(path, ..) => traced!(FsError::DecryptionFailed, (path.clone()), self.trace().clone()),
+            #[cfg(feature = "serde")]
+            FsError::JsonLine { path, line, error, .. } => FsError::JsonLine {
+                path: path.clone(), line: *line, error: serde::de::Error::custom(error),
+                #[cfg(feature = "backtrace")]
+                trace: self.trace().clone(),
+            },
+            FsError::Generic(msg, ..) => traced!(FsError::Generic, (msg.clone()), self.trace().clone()),
+        }
+    }
+}
+
+/// Constructors for the errors the crate raises itself, capturing a `Trace` of the caller when
+/// the `backtrace` feature is on.
+impl FsError {
+    pub fn not_a_file(path: String, kind: FsKind) -> Self {
+        traced!(FsError::NotAFile, (path, kind))
+    }
+
+    pub fn not_a_directory(path: String, kind: FsKind) -> Self {
+        traced!(FsError::NotADirectory, (path, kind))
+    }
+
+    pub fn file_not_present(path: String, name: String) -> Self {
+        traced!(FsError::FileNotPresent, (path, name))
+    }
+
+    pub fn file_not_open(path: String) -> Self {
+        traced!(FsError::FileNotOpen, (path))
+    }
+
+    pub fn unsupported(backend: &'static str, op: &'static str) -> Self {
+        FsError::Unsupported {
+            backend, op,
+            #[cfg(feature = "backtrace")]
+            trace: Trace::capture(),
+        }
+    }
+
+    pub fn read_only(path: String) -> Self {
+        traced!(FsError::ReadOnly, (path))
+    }
+
+    pub fn already_exists(path: String) -> Self {
+        traced!(FsError::AlreadyExists, (path, None))
+    }
+
+    pub fn locked(path: String) -> Self {
+        traced!(FsError::Locked, (path))
+    }
+
+    pub fn deleted(path: String) -> Self {
+        traced!(FsError::Deleted, (path))
+    }
+
+    pub fn unsafe_path(name: String) -> Self {
+        traced!(FsError::UnsafePath, (name))
+    }
+
+    pub fn quota_exceeded(path: String, limit: u64) -> Self {
+        FsError::QuotaExceeded {
+            path, limit,
+            #[cfg(feature = "backtrace")]
+            trace: Trace::capture(),
+        }
+    }
+
+    #[cfg(feature = "hash")]
+    pub fn hash_mismatch(path: String, expected: Vec<u8>, actual: Vec<u8>) -> Self {
+        FsError::HashMismatch {
+            path, expected, actual,
+            #[cfg(feature = "backtrace")]
+            trace: Trace::capture(),
+        }
+    }
+
+    #[cfg(feature = "crypto")]
+    pub fn decryption_failed(path: String) -> Self {
+        traced!(FsError::DecryptionFailed, (path))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn json_line(path: String, line: usize, error: serde_json::Error) -> Self {
+        FsError::JsonLine {
+            path, line, error,
+            #[cfg(feature = "backtrace")]
+            trace: Trace::capture(),
+        }
+    }
+
+    pub fn generic(msg: String) -> Self {
+        traced!(FsError::Generic, (msg))
+    }
+}
+
+impl FsError {
+    /// Returns the kind of the underlying I/O error, if this error wraps one. Counts
+    /// [`FsError::AlreadyExists`] as [`IoErrorKind::AlreadyExists`] too, whether or not the OS
+    /// reported it.
+    pub fn io_kind(&self) -> Option<IoErrorKind> {
+        match self {
+            FsError::IoError(error, ..) | FsError::NotFound(error, ..) | FsError::PermissionDenied(error, ..) => {
+                Some(error.kind())
+            },
+            FsError::AlreadyExists(..) => Some(IoErrorKind::AlreadyExists),
+            _ => None,
+        }
+    }
+
+    /// Returns the backtrace captured where this error was raised: where the crate found the
+    /// problem, or where a failing OS or archive call surfaced in it. Errors wrapping another
+    /// [`FsError`] return that one's. Like [`Backtrace::capture`], capturing only happens when
+    /// the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variable enables it; otherwise
+    /// the backtrace reports itself as disabled.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.trace().backtrace
+    }
+
+    #[cfg(feature = "backtrace")]
+    fn trace(&self) -> &Trace {
+        match self {
+            FsError::NotAFile(_, _, trace) | FsError::NotADirectory(_, _, trace)
+            | FsError::FileNotPresent(_, _, trace) => trace,
+            FsError::IoError(_, trace) | FsError::NotFound(_, trace) | FsError::PermissionDenied(_, trace)
+            | FsError::AlreadyExists(_, _, trace) => trace,
+            #[cfg(feature = "zip")]
+            FsError::ZipError(_, trace) => trace,
+            FsError::FileNotOpen(_, trace) | FsError::ReadOnly(_, trace) | FsError::Locked(_, trace)
+            | FsError::Deleted(_, trace) | FsError::UnsafePath(_, trace) | FsError::Generic(_, trace) => trace,
+            #[cfg(feature = "crypto")]
+            FsError::DecryptionFailed(_, trace) => trace,
+            FsError::Unsupported { trace, .. } | FsError::QuotaExceeded { trace, .. } => trace,
+            #[cfg(feature = "hash")]
+            FsError::HashMismatch { trace, .. } => trace,
+            #[cfg(feature = "serde")]
+            FsError::JsonLine { trace, .. } => trace,
+            FsError::PathSegment(_, error) | FsError::MoveIncomplete { error, .. } => error.trace(),
+        }
+    }
+}
+
+#[cfg(feature = "zip")]
+impl From<ZipError> for FsError {
+    fn from(error: ZipError) -> Self {
+        traced!(FsError::ZipError, (error))
+    }
+}
+
+/// `Read`/`Write` impls can only return I/O errors, so crate errors raised there travel wrapped in
+/// one. Converting back unwraps them again, keeping where they were raised.
+impl From<IoError> for FsError {
+    fn from(error: IoError) -> Self {
+        if error.get_ref().is_some_and(|inner| inner.is::<FsError>()) {
+            return *error.into_inner().unwrap().downcast::<FsError>().unwrap();
+        }
+
+        match error.kind() {
+            IoErrorKind::NotFound => traced!(FsError::NotFound, (error)),
+            IoErrorKind::PermissionDenied => traced!(FsError::PermissionDenied, (error)),
+            IoErrorKind::AlreadyExists => traced!(FsError::AlreadyExists, (String::new(), Some(error))),
+            _ => traced!(FsError::IoError, (error)),
+        }
+    }
+}
+
+impl From<String> for FsError {
+    fn from(msg: String) -> Self {
+        FsError::generic(msg)
+    }
+}
+
+impl From<FsError> for IoError {
+    fn from(error: FsError) -> Self {
+        match error {
+            FsError::IoError(error, ..) | FsError::NotFound(error, ..) | FsError::PermissionDenied(error, ..)
+            | FsError::AlreadyExists(_, Some(error), ..) => error,
+            error @ FsError::AlreadyExists(..) => IoError::new(IoErrorKind::AlreadyExists, error),
+            error => IoError::other(error),
+        }
+    }
+}
+
+/// Errors are equal if they're the same variant with equal fields, so tests can compare them with
+/// `assert_eq!`. Where they were raised doesn't count. Wrapped errors that can't be compared
+/// directly are compared by what's stable about them:
+/// - I/O errors by their [`IoErrorKind`] alone, since their messages vary by platform. Two
+///   errors of the same variant from different paths and causes can compare equal.
+/// - Zip errors by variant and message, and I/O errors inside them by kind.
+/// - JSON errors by their message, which includes the line and column.
+impl PartialEq for FsError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FsError::NotAFile(path, kind, ..), FsError::NotAFile(other_path, other_kind, ..))
+            | (FsError::NotADirectory(path, kind, ..), FsError::NotADirectory(other_path, other_kind, ..)) => {
+                path == other_path && kind == other_kind
+            },
+            (FsError::IoError(error, ..), FsError::IoError(other, ..))
+            | (FsError::NotFound(error, ..), FsError::NotFound(other, ..))
+            | (FsError::PermissionDenied(error, ..), FsError::PermissionDenied(other, ..)) => error.kind() == other.kind(),
+            (FsError::AlreadyExists(path, error, ..), FsError::AlreadyExists(other_path, other, ..)) => {
+                path == other_path && error.as_ref().map(IoError::kind) == other.as_ref().map(IoError::kind)
+            },
+            #[cfg(feature = "zip")]
+            (FsError::ZipError(error, ..), FsError::ZipError(other, ..)) => match (error, other) {
+                (ZipError::Io(error), ZipError::Io(other)) => error.kind() == other.kind(),
+                (ZipError::Io(_), ..) | (_, ZipError::Io(_)) => false,
+                (error, other) => error.to_string() == other.to_string(),
+            },
+            (FsError::FileNotPresent(path, name, ..), FsError::FileNotPresent(other_path, other_name, ..)) => {
+                path == other_path && name == other_name
+            },
+            (FsError::PathSegment(segment, error), FsError::PathSegment(other_segment, other)) => {
+                segment == other_segment && error == other
+            },
+            (
+                FsError::Unsupported { backend, op, .. },
+                FsError::Unsupported { backend: other_backend, op: other_op, .. },
+            ) => backend == other_backend && op == other_op,
+            (FsError::FileNotOpen(path, ..), FsError::FileNotOpen(other, ..))
+            | (FsError::ReadOnly(path, ..), FsError::ReadOnly(other, ..))
+            | (FsError::Locked(path, ..), FsError::Locked(other, ..))
+            | (FsError::Deleted(path, ..), FsError::Deleted(other, ..))
+            | (FsError::UnsafePath(path, ..), FsError::UnsafePath(other, ..))
+            | (FsError::Generic(path, ..), FsError::Generic(other, ..)) => path == other,
+            (
+                FsError::MoveIncomplete { from, to, error },
+                FsError::MoveIncomplete { from: other_from, to: other_to, error: other },
+            ) => from == other_from && to == other_to && error == other,
+            (
+                FsError::QuotaExceeded { path, limit, .. },
+                FsError::QuotaExceeded { path: other_path, limit: other_limit, .. },
+            ) => path == other_path && limit == other_limit,
+            #[cfg(feature = "hash")]
+            (
+                FsError::HashMismatch { path, expected, actual, .. },
+                FsError::HashMismatch { path: other_path, expected: other_expected, actual: other_actual, .. },
+            ) => path == other_path && expected == other_expected && actual == other_actual,
+            #[cfg(feature = "crypto")]
+            (FsError::DecryptionFailed(path, ..), FsError::DecryptionFailed(other, ..)) => path == other,
+            #[cfg(feature = "serde")]
+            (
+                FsError::JsonLine { path, line, error, .. },
+                FsError::JsonLine { path: other_path, line: other_line, error: other, .. },
+            ) => path == other_path && line == other_line && error.to_string() == other.to_string(),
+            _ => false,
+        }
+    }
+}
+
+/// Rebuilds an I/O error with the same kind and message, see the `Clone` impl of [`FsError`].
+fn clone_io(error: &IoError) -> IoError {
+    if let Some(code) = error.raw_os_error() {
+        return IoError::from_raw_os_error(code);
+    }
+
+    match error.get_ref() {
+        Some(inner) => match inner.downcast_ref::<FsError>() {
+            Some(inner) => IoError::new(error.kind(), inner.clone()),
+            None => IoError::new(error.kind(), inner.to_string()),
+        },
+        None => IoError::from(error.kind()),
+    }
+}
+
+#[cfg(feature = "hash")]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Wraps [`FsError::ReadOnly`] for the `Write` impls, which can only return I/O errors.
+pub(crate) fn read_only_io(path: String) -> IoError {
+    IoError::new(IoErrorKind::ReadOnlyFilesystem, FsError::read_only(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FilesystemObjectRaw, PhysicalDirectory};
+    use crate::test_util::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn scanning_an_unreadable_dir_is_permission_denied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Permissions don't stop root.
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+
+        let tmp = TempDir::new("chmod-000");
+        let path = tmp.join("locked");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+
+        let result = PhysicalDirectory::from_path(&path).write().unwrap().scan();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let error = result.unwrap_err();
+        assert!(matches!(error, FsError::PermissionDenied(..)), "{error:?}");
+        assert_eq!(error.io_kind(), Some(IoErrorKind::PermissionDenied));
+        assert!(error.to_string().contains("check the permissions"));
+    }
+
+    #[test]
+    fn os_errors_map_to_dedicated_variants() {
+        let tmp = TempDir::new("error-kinds");
+        std::fs::write(tmp.join("taken"), b"").unwrap();
+
+        let missing = FsError::from(std::fs::read(tmp.join("missing")).unwrap_err());
+        assert!(matches!(missing, FsError::NotFound(..)), "{missing:?}");
+        assert_eq!(missing.io_kind(), Some(IoErrorKind::NotFound));
+
+        let taken = std::fs::File::create_new(tmp.join("taken")).unwrap_err();
+        let taken = FsError::from(taken);
+        assert!(matches!(taken, FsError::AlreadyExists(_, Some(_), ..)), "{taken:?}");
+        assert!(taken.to_string().ends_with("; remove what's there or pick another name"), "{taken}");
+        assert!(!taken.to_string().starts_with('\''), "{taken}");
+        assert_eq!(taken.io_kind(), Some(IoErrorKind::AlreadyExists));
+        assert_eq!(IoError::from(taken).kind(), IoErrorKind::AlreadyExists);
+    }
+
+    /// An error of this kind as the OS reports it, with its error code, where libc provides the
+    /// codes. Elsewhere it's built from the kind alone.
+    fn os_error(kind: IoErrorKind) -> IoError {
+        #[cfg(unix)]
+        return IoError::from_raw_os_error(match kind {
+            IoErrorKind::NotFound => libc::ENOENT,
+            IoErrorKind::PermissionDenied => libc::EACCES,
+            IoErrorKind::AlreadyExists => libc::EEXIST,
+            IoErrorKind::BrokenPipe => libc::EPIPE,
+            kind => unreachable!("no error code for {kind:?}"),
+        });
+        #[cfg(not(unix))]
+        IoError::from(kind)
+    }
+
+    /// One error of every variant, with I/O errors of each flavour: from the OS, bare, with a
+    /// message, and wrapping an `FsError`.
+    fn every_variant() -> Vec<FsError> {
+        vec![
+            FsError::not_a_file("dir".to_string(), FsKind::Directory),
+            FsError::not_a_directory("file".to_string(), FsKind::File),
+            traced!(FsError::IoError, (os_error(IoErrorKind::BrokenPipe))),
+            traced!(FsError::IoError, (IoError::from(IoErrorKind::Interrupted))),
+            traced!(FsError::IoError, (IoError::other("custom message"))),
+            traced!(FsError::IoError, (read_only_io("locked.txt".to_string()))),
+            traced!(FsError::NotFound, (os_error(IoErrorKind::NotFound))),
+            traced!(FsError::PermissionDenied, (os_error(IoErrorKind::PermissionDenied))),
+            FsError::from(os_error(IoErrorKind::AlreadyExists)),
+            #[cfg(feature = "zip")]
+            FsError::from(ZipError::Io(os_error(IoErrorKind::BrokenPipe))),
+            #[cfg(feature = "zip")]
+            FsError::from(ZipError::InvalidArchive("bad header".into())),
+            #[cfg(feature = "zip")]
+            FsError::from(ZipError::FileNotFound),
+            FsError::file_not_present("dir".to_string(), "missing".to_string()),
+            FsError::file_not_open("closed.txt".to_string()),
+            FsError::PathSegment("sub".to_string(), Box::new(FsError::read_only("sub".to_string()))),
+            FsError::unsupported("memory", "scan"),
+            FsError::read_only("ro.txt".to_string()),
+            FsError::already_exists("taken.txt".to_string()),
+            FsError::locked("busy.txt".to_string()),
+            FsError::deleted("gone.txt".to_string()),
+            FsError::unsafe_path("../escape".to_string()),
+            FsError::MoveIncomplete {
+                from: "a".to_string(), to: "b".to_string(), error: Box::new(FsError::locked("a".to_string())),
+            },
+            FsError::quota_exceeded("full".to_string(), 1024),
+            #[cfg(feature = "hash")]
+            FsError::hash_mismatch("hashed".to_string(), vec![1, 2], vec![3, 4]),
+            #[cfg(feature = "crypto")]
+            FsError::decryption_failed("secret".to_string()),
+            #[cfg(feature = "serde")]
+            FsError::json_line("data.jsonl".to_string(), 3, serde_json::from_str::<u32>("nope").unwrap_err()),
+            FsError::generic("something broke".to_string()),
+        ]
+    }
+
+    #[test]
+    fn clones_display_the_same() {
+        for error in every_variant() {
+            let clone = error.clone();
+            assert_eq!(clone.to_string(), error.to_string());
+            assert_eq!(clone.io_kind(), error.io_kind());
+        }
+    }
+
+    #[test]
+    fn equality_is_structural() {
+        let errors = every_variant();
+        let clones = errors.clone();
+        for (i, error) in errors.iter().enumerate() {
+            for (j, other) in clones.iter().enumerate() {
+                assert_eq!(error == other, i == j, "{error:?} vs {other:?}");
+            }
+        }
+
+        let missing = |name: &str| FsError::file_not_present("dir".to_string(), name.to_string());
+        assert_eq!(missing("x"), missing("x"));
+        assert_ne!(missing("x"), missing("y"));
+        assert_ne!(missing("x"), FsError::file_not_present("other".to_string(), "x".to_string()));
+
+        // I/O errors compare by kind alone, but stay apart across variants.
+        let timed_out = |message| traced!(FsError::IoError, (IoError::new(IoErrorKind::TimedOut, message)));
+        assert_eq!(timed_out("first"), timed_out("second"));
+        assert_eq!(timed_out("first"), traced!(FsError::IoError, (IoError::from(IoErrorKind::TimedOut))));
+        assert_ne!(
+            traced!(FsError::IoError, (IoError::from(IoErrorKind::NotFound))),
+            traced!(FsError::NotFound, (IoError::from(IoErrorKind::NotFound))),
+        );
+
+        let nested = |inner| FsError::PathSegment("sub".to_string(), Box::new(inner));
+        assert_eq!(nested(FsError::locked("a".to_string())), nested(FsError::locked("a".to_string())));
+        assert_ne!(nested(FsError::locked("a".to_string())), nested(FsError::deleted("a".to_string())));
+        assert_ne!(FsError::unsupported("zip", "scan"), FsError::unsupported("zip", "rename"));
+    }
+
+    /// Without the `backtrace` feature, variants have only their own fields, so they can be built
+    /// and matched as such.
+    #[cfg(not(feature = "backtrace"))]
+    #[test]
+    fn variants_carry_no_trace_without_the_feature() {
+        let errors = vec![
+            FsError::NotAFile("dir".to_string(), FsKind::Directory),
+            FsError::NotADirectory("file".to_string(), FsKind::File),
+            FsError::IoError(IoError::from(IoErrorKind::Interrupted)),
+            FsError::NotFound(IoError::from(IoErrorKind::NotFound)),
+            FsError::PermissionDenied(IoError::from(IoErrorKind::PermissionDenied)),
+            #[cfg(feature = "zip")]
+            FsError::ZipError(ZipError::FileNotFound),
+            FsError::FileNotPresent("dir".to_string(), "missing".to_string()),
+            FsError::FileNotOpen("closed.txt".to_string()),
+            FsError::PathSegment("sub".to_string(), Box::new(FsError::ReadOnly("sub".to_string()))),
+            FsError::Unsupported { backend: "memory", op: "scan" },
+            FsError::ReadOnly("ro.txt".to_string()),
+            FsError::AlreadyExists("taken.txt".to_string(), None),
+            FsError::Locked("busy.txt".to_string()),
+            FsError::Deleted("gone.txt".to_string()),
+            FsError::UnsafePath("../escape".to_string()),
+            FsError::MoveIncomplete {
+                from: "a".to_string(), to: "b".to_string(), error: Box::new(FsError::Locked("a".to_string())),
+            },
+            FsError::QuotaExceeded { path: "full".to_string(), limit: 1024 },
+            #[cfg(feature = "hash")]
+            FsError::HashMismatch { path: "hashed".to_string(), expected: vec![1, 2], actual: vec![3, 4] },
+            #[cfg(feature = "crypto")]
+            FsError::DecryptionFailed("secret".to_string()),
+            #[cfg(feature = "serde")]
+            FsError::JsonLine {
+                path: "data.jsonl".to_string(), line: 3, error: serde_json::from_str::<u32>("nope").unwrap_err(),
+            },
+            FsError::Generic("something broke".to_string()),
+        ];
+
+        // The constructors build the same errors.
+        let constructed = [
+            FsError::not_a_file("dir".to_string(), FsKind::Directory),
+            FsError::read_only("ro.txt".to_string()),
+            FsError::already_exists("taken.txt".to_string()),
+            FsError::quota_exceeded("full".to_string(), 1024),
+            FsError::generic("something broke".to_string()),
+        ];
+        for error in &constructed {
+            assert!(errors.contains(error), "{error:?}");
+        }
+
+        for error in errors {
+            let shown = error.to_string();
+            match error {
+                FsError::NotAFile(path, kind) | FsError::NotADirectory(path, kind) => {
+                    assert!(shown.contains(&path) && shown.contains(&kind.to_string()));
+                },
+                FsError::IoError(error) | FsError::NotFound(error) | FsError::PermissionDenied(error) => {
+                    assert!(shown.starts_with(&error.to_string()));
+                },
+                #[cfg(feature = "zip")]
+                FsError::ZipError(error) => assert_eq!(shown, error.to_string()),
+                FsError::FileNotPresent(path, name) => assert!(shown.contains(&path) && shown.contains(&name)),
+                FsError::PathSegment(segment, _) => assert!(shown.contains(&segment)),
+                FsError::Unsupported { backend, op } => assert!(shown.contains(backend) && shown.contains(op)),
+                FsError::AlreadyExists(path, None) => assert!(shown.contains(&path)),
+                FsError::AlreadyExists(_, Some(_)) => unreachable!(),
+                FsError::FileNotOpen(path) | FsError::ReadOnly(path) | FsError::Locked(path)
+                | FsError::Deleted(path) | FsError::UnsafePath(path) | FsError::Generic(path) => {
+                    assert!(shown.contains(&path));
+                },
+                FsError::MoveIncomplete { from, to, error } => {
+                    assert!(shown.contains(&from) && shown.contains(&to) && shown.contains(&error.to_string()));
+                },
+                FsError::QuotaExceeded { path, limit } => {
+                    assert!(shown.contains(&path) && shown.contains(&limit.to_string()));
+                },
+                #[cfg(feature = "hash")]
+                FsError::HashMismatch { path, expected, actual } => {
+                    assert!(shown.contains(&path) && shown.contains(&hex(&expected)) && shown.contains(&hex(&actual)));
+                },
+                #[cfg(feature = "crypto")]
+                FsError::DecryptionFailed(path) => assert!(shown.contains(&path)),
+                #[cfg(feature = "serde")]
+                FsError::JsonLine { path, line, error } => {
+                    assert!(shown.contains(&path) && shown.contains(&line.to_string()) && shown.contains(&error.to_string()));
+                },
+            }
+        }
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn every_error_carries_a_backtrace() {
+        use std::backtrace::BacktraceStatus;
+
+        let tmp = TempDir::new("backtrace");
+        let missing = std::fs::read(tmp.join("missing")).unwrap_err();
+        let code = missing.raw_os_error();
+        let errors = [
+            FsError::from(missing),
+            FsError::read_only("ro.txt".to_string()),
+            FsError::unsupported("zip", "rename"),
+            FsError::already_exists("taken.txt".to_string()),
+            FsError::quota_exceeded("full".to_string(), 1024),
+            PhysicalDirectory::from_path(tmp.path()).read().unwrap().get_child("missing").unwrap_err(),
+        ];
+        for error in &errors {
+            let backtrace = error.backtrace();
+            // Only walked when the environment asks for it.
+            if backtrace.status() == BacktraceStatus::Captured {
+                assert!(backtrace.to_string().contains("every_error_carries_a_backtrace"), "{backtrace}");
+            }
+        }
+
+        // The OS error is kept as is.
+        let FsError::NotFound(error, ..) = &errors[0] else { panic!("{:?}", errors[0]) };
+        assert!(code.is_some());
+        assert_eq!(error.raw_os_error(), code);
+
+        // Clones and wrappers report where the original was raised.
+        let clone = errors[1].clone();
+        assert!(std::ptr::eq(clone.backtrace(), errors[1].backtrace()));
+        let wrapped = FsError::PathSegment("sub".to_string(), Box::new(clone));
+        assert!(std::ptr::eq(wrapped.backtrace(), errors[1].backtrace()));
+        let through_io = FsError::from(IoError::from(errors[1].clone()));
+        assert!(std::ptr::eq(through_io.backtrace(), errors[1].backtrace()));
+    }
+}
@@ -0,0 +1,180 @@
+//! Guards that keep a file open for as long as they live, made by
+//! [`open_read`](crate::FilesystemObjectRaw#method.open_read) and
+//! [`open_write`](crate::FilesystemObjectRaw#method.open_write).
+
+use std::io::{Result as IoResult, BufRead, Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+
+use super::{FilesystemObjectRaw, FsResult};
+
+/// Keeps a file open for reading, closing it when dropped. Derefs to the file for everything else.
+pub struct ReadGuard<'a> {
+    file: &'a mut (dyn FilesystemObjectRaw + 'static),
+}
+
+/// Keeps a file open for writing, closing it when dropped, which writes out pending bytes.
+/// [`WriteGuard::finish`] does the same but reports errors. Derefs to the file for everything else.
+pub struct WriteGuard<'a> {
+    file: &'a mut (dyn FilesystemObjectRaw + 'static),
+}
+
+impl<'a> ReadGuard<'a> {
+    pub(crate) fn new(file: &'a mut (dyn FilesystemObjectRaw + 'static)) -> FsResult<Self> {
+        file.open()?;
+        Ok(Self { file })
+    }
+}
+
+impl<'a> WriteGuard<'a> {
+    pub(crate) fn new(file: &'a mut (dyn FilesystemObjectRaw + 'static)) -> FsResult<Self> {
+        // A file that doesn't exist yet is left for the first flush to create, as a read-only
+        // open would fail on it.
+        if file.exists() {
+            file.open()?;
+        }
+        Ok(Self { file })
+    }
+
+    /// Writes out pending bytes and closes the file, reporting what dropping the guard would
+    /// swallow.
+    pub fn finish(self) -> FsResult {
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for ReadGuard<'_> {
+    fn drop(&mut self) {
+        self.file.close();
+    }
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        // `close` flushes too, but can't report errors; call `finish` to see them.
+        self.file.close();
+    }
+}
+
+impl Deref for ReadGuard<'_> {
+    type Target = dyn FilesystemObjectRaw;
+
+    fn deref(&self) -> &Self::Target {
+        self.file
+    }
+}
+
+impl DerefMut for ReadGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.file
+    }
+}
+
+impl Deref for WriteGuard<'_> {
+    type Target = dyn FilesystemObjectRaw;
+
+    fn deref(&self) -> &Self::Target {
+        self.file
+    }
+}
+
+impl DerefMut for WriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.file
+    }
+}
+
+impl Read for ReadGuard<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl BufRead for ReadGuard<'_> {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        self.file.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.file.consume(amt);
+    }
+}
+
+impl Seek for ReadGuard<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Write for WriteGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for WriteGuard<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{FilesystemObject, PhysicalFile};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn dropping_a_write_guard_writes_out_the_file() {
+        let tmp = TempDir::new("guard-write");
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("new.txt"), 64);
+        {
+            let mut file = file.write().unwrap();
+            let mut guard = file.open_write().unwrap();
+            guard.write_all(b"written through the guard").unwrap();
+            assert!(!tmp.join("new.txt").exists());
+        }
+        assert_eq!(fs::read(tmp.join("new.txt")).unwrap(), b"written through the guard");
+        assert!(!file.read().unwrap().is_open());
+
+        // An existing file is opened up front and overwritten in place.
+        {
+            let mut file = file.write().unwrap();
+            let mut guard = file.open_write().unwrap();
+            assert!(guard.is_open());
+            guard.write_all(b"WRITTEN").unwrap();
+            guard.finish().unwrap();
+        }
+        assert_eq!(fs::read(tmp.join("new.txt")).unwrap(), b"WRITTEN through the guard");
+        assert!(!file.read().unwrap().is_open());
+    }
+
+    #[test]
+    fn a_read_guard_reads_and_closes() {
+        let tmp = TempDir::new("guard-read");
+        tmp.write("data.txt", "first line\nsecond line\n");
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("data.txt"), 8);
+        {
+            let mut file = file.write().unwrap();
+            let mut guard = file.open_read().unwrap();
+            assert!(guard.is_open());
+            let mut line = String::new();
+            guard.read_line(&mut line).unwrap();
+            assert_eq!(line, "first line\n");
+            guard.seek(SeekFrom::Start(6)).unwrap();
+            let mut rest = String::new();
+            guard.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "line\nsecond line\n");
+        }
+        assert!(!file.read().unwrap().is_open());
+
+        let missing: FilesystemObject = PhysicalFile::from_path(tmp.join("missing.txt"), 8);
+        assert!(missing.write().unwrap().open_read().is_err());
+    }
+}
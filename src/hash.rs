@@ -0,0 +1,307 @@
+//! Content hashing helpers, enabled by the `hash` feature.
+
+use std::io::{Result as IoResult, SeekFrom, Write};
+use std::path::{Component, Path};
+
+use sha2::{Digest, Sha256};
+
+use super::{FilesystemObjectRaw, FsError, FsResult, walk_files};
+
+/// Hash algorithm used by [`FilesystemObjectRaw::digest`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigestAlgo {
+    Sha256,
+    Blake3,
+}
+
+enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algo: DigestAlgo) -> Self {
+        match algo {
+            DigestAlgo::Sha256 => Hasher::Sha256(Sha256::new()),
+            DigestAlgo::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(chunk),
+            Hasher::Blake3(hasher) => { hasher.update(chunk); },
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// What passed through a [`SummaryWriter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// The number of bytes written.
+    pub bytes: u64,
+    /// The BLAKE3 hash of the bytes written.
+    pub hash: [u8; 32],
+    /// The number of `\n` bytes written.
+    pub newlines: u64,
+}
+
+/// Writes through to a file while tallying what's written, made by
+/// [`write_with_summary`](crate::FilesystemObjectRaw#method.write_with_summary). Only bytes the
+/// file accepted are counted.
+pub struct SummaryWriter<'a> {
+    file: &'a mut dyn FilesystemObjectRaw,
+    hasher: blake3::Hasher,
+    bytes: u64,
+    newlines: u64,
+}
+
+impl<'a> SummaryWriter<'a> {
+    pub(crate) fn new(file: &'a mut dyn FilesystemObjectRaw) -> Self {
+        Self{ file, hasher: blake3::Hasher::new(), bytes: 0, newlines: 0 }
+    }
+
+    /// Flushes the file and returns the summary of everything written.
+    pub fn finish(self) -> FsResult<WriteSummary> {
+        self.file.flush()?;
+
+        Ok(WriteSummary{
+            bytes: self.bytes,
+            hash: *self.hasher.finalize().as_bytes(),
+            newlines: self.newlines,
+        })
+    }
+}
+
+impl Write for SummaryWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = &buf[..self.file.write(buf)?];
+        self.hasher.update(written);
+        self.bytes += written.len() as u64;
+        self.newlines += written.iter().filter(|byte| **byte == b'\n').count() as u64;
+
+        Ok(written.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.file.flush()
+    }
+}
+
+/// Feeds the full contents of a file to `update`, one buffer at a time. The file is rewound
+/// first, and left positioned at its end.
+fn stream_contents(file: &mut (impl FilesystemObjectRaw + ?Sized), mut update: impl FnMut(&[u8])) -> FsResult {
+    file.seek(SeekFrom::Start(0))?;
+    loop {
+        let chunk = file.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let len = chunk.len();
+        update(chunk);
+        file.consume(len);
+    }
+
+    Ok(())
+}
+
+/// Hashes the full contents of a file with `algo`.
+pub(crate) fn digest(file: &mut (impl FilesystemObjectRaw + ?Sized), algo: DigestAlgo) -> FsResult<Vec<u8>> {
+    let mut hasher = Hasher::new(algo);
+    stream_contents(file, |chunk| hasher.update(chunk))?;
+
+    Ok(hasher.finalize())
+}
+
+/// Reads the full contents of a file, hashing them in the same pass, and only returns them if the
+/// digest matches `expected`.
+pub(crate) fn read_verified(file: &mut (impl FilesystemObjectRaw + ?Sized), expected: &[u8], algo: DigestAlgo) -> FsResult<Vec<u8>> {
+    let mut hasher = Hasher::new(algo);
+    let mut contents = Vec::new();
+    stream_contents(file, |chunk| {
+        hasher.update(chunk);
+        contents.extend_from_slice(chunk);
+    })?;
+
+    let actual = hasher.finalize();
+    if actual != expected {
+        return Err(FsError::hash_mismatch(file.get_full_path().to_string_lossy().to_string(), expected.to_vec(), actual));
+    }
+
+    Ok(contents)
+}
+
+/// Hashes the full contents of a file with BLAKE3, streaming it through its own buffer.
+pub(crate) fn content_hash(file: &mut (impl FilesystemObjectRaw + ?Sized)) -> FsResult<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    stream_contents(file, |chunk| { hasher.update(chunk); })?;
+
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// Hashes the sorted `(relative path, content hash)` pairs of every file under `dir` with BLAKE3.
+/// Paths are joined with `/` whatever the platform, and each is length-prefixed so neighbouring
+/// entries can't run together.
+pub(crate) fn content_signature(dir: &(impl FilesystemObjectRaw + ?Sized)) -> FsResult<[u8; 32]> {
+    let mut entries = Vec::new();
+    walk_files(dir, Path::new(""), &mut |path, file| {
+        let hash = content_hash(&mut *file.write().unwrap())?;
+        entries.push((portable_path(&path), hash));
+        Ok(())
+    })?;
+    entries.sort_unstable();
+
+    let mut hasher = blake3::Hasher::new();
+    for (path, hash) in entries {
+        hasher.update(&(path.len() as u64).to_le_bytes());
+        hasher.update(path.as_bytes());
+        hasher.update(&hash);
+    }
+
+    Ok(*hasher.finalize().as_bytes())
+}
+
+fn portable_path(path: &Path) -> String {
+    let segments: Vec<_> = path.components()
+        .filter_map(|component| match component {
+            Component::Normal(segment) => Some(segment.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    segments.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysicalDirectory;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn find_duplicates_groups_identical_files() {
+        let tmp = TempDir::new("duplicates");
+        std::fs::create_dir(tmp.join("sub")).unwrap();
+        std::fs::write(tmp.join("a.txt"), "same").unwrap();
+        std::fs::write(tmp.join("sub").join("b.txt"), "same").unwrap();
+        std::fs::write(tmp.join("c.txt"), "diff").unwrap();
+        std::fs::write(tmp.join("d.txt"), "unique size").unwrap();
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let groups = dir.read().unwrap().find_duplicates().unwrap();
+        let paths: Vec<Vec<_>> = groups.iter()
+            .map(|group| group.iter().map(|file| file.read().unwrap().get_full_path()).collect())
+            .collect();
+        assert_eq!(paths, [[tmp.join("a.txt"), tmp.join("sub").join("b.txt")]]);
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    const ABC_BLAKE3: &str = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+
+    #[test]
+    fn digest_matches_known_hashes() {
+        let tmp = TempDir::new("digest");
+        std::fs::write(tmp.join("abc.txt"), "abc").unwrap();
+
+        let file = crate::PhysicalFile::from_path(tmp.join("abc.txt"), 2);
+        let mut file = file.write().unwrap();
+        assert_eq!(hex(&file.digest(DigestAlgo::Sha256).unwrap()), ABC_SHA256);
+        assert_eq!(hex(&file.digest(DigestAlgo::Blake3).unwrap()), ABC_BLAKE3);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn digest_of_zip_entries_matches_physical_files() {
+        use std::io::Write;
+
+        let tmp = TempDir::new("digest-zip");
+        let path = tmp.join("archive.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        writer.start_file("abc.txt", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(b"abc").unwrap();
+        writer.finish().unwrap();
+
+        let archive = crate::ZipDirectory::new(crate::PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = archive.read().unwrap().get_child("abc.txt").unwrap();
+        let mut entry = entry.write().unwrap();
+        assert_eq!(hex(&entry.digest(DigestAlgo::Sha256).unwrap()), ABC_SHA256);
+        assert_eq!(hex(&entry.digest(DigestAlgo::Blake3).unwrap()), ABC_BLAKE3);
+    }
+
+    #[test]
+    fn read_verified_checks_the_digest() {
+        let tmp = TempDir::new("read-verified");
+        std::fs::write(tmp.join("abc.txt"), "abc").unwrap();
+        let file = crate::PhysicalFile::from_path(tmp.join("abc.txt"), 0);
+        let mut file = file.write().unwrap();
+
+        let expected = file.digest(DigestAlgo::Sha256).unwrap();
+        assert_eq!(hex(&expected), ABC_SHA256);
+        assert_eq!(file.read_verified(&expected, DigestAlgo::Sha256).unwrap(), b"abc");
+
+        let mut wrong = expected.clone();
+        wrong[0] ^= 1;
+        match file.read_verified(&wrong, DigestAlgo::Sha256) {
+            Err(FsError::HashMismatch { expected, actual, .. }) => {
+                assert_eq!(expected, wrong);
+                assert_eq!(hex(&actual), ABC_SHA256);
+            },
+            result => panic!("expected a hash mismatch, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn content_signature_ignores_timestamps() {
+        let tmp = TempDir::new("signature");
+        tmp.write("src/main.rs", "fn main() {}");
+        tmp.write("README.md", "readme");
+        let signature = || PhysicalDirectory::from_path(tmp.path()).read().unwrap().content_signature().unwrap();
+        let original = signature();
+
+        let file = std::fs::File::options().write(true).open(tmp.join("README.md")).unwrap();
+        file.set_modified(std::time::SystemTime::UNIX_EPOCH).unwrap();
+        drop(file);
+        std::fs::create_dir(tmp.join("empty")).unwrap();
+        assert_eq!(signature(), original);
+
+        tmp.write("README.md", "edited");
+        let edited = signature();
+        assert_ne!(edited, original);
+
+        std::fs::rename(tmp.join("README.md"), tmp.join("README.txt")).unwrap();
+        assert_ne!(signature(), edited);
+    }
+
+    #[test]
+    fn write_summary_matches_the_written_data() {
+        let tmp = TempDir::new("summary");
+        std::fs::write(tmp.join("out.txt"), "").unwrap();
+        let data = "line one\nline two\n\nno newline".repeat(500);
+
+        let file: crate::FilesystemObject = crate::PhysicalFile::from_path(tmp.join("out.txt"), 64);
+        let mut file = file.write().unwrap();
+        let mut writer = file.write_with_summary().unwrap();
+        for chunk in data.as_bytes().chunks(7) {
+            std::io::Write::write_all(&mut writer, chunk).unwrap();
+        }
+        let summary = writer.finish().unwrap();
+
+        assert_eq!(std::fs::read_to_string(tmp.join("out.txt")).unwrap(), data);
+        assert_eq!(summary.bytes, data.len() as u64);
+        assert_eq!(summary.newlines, 1500);
+        assert_eq!(summary.hash, *blake3::hash(data.as_bytes()).as_bytes());
+        assert_eq!(summary.hash, file.digest(DigestAlgo::Blake3).unwrap()[..]);
+    }
+}
@@ -0,0 +1,961 @@
+//! The [iso](`self`) module provides read-only access to ISO 9660 disc images. Long names are
+//! taken from Rock Ridge (`NM` entries) when the image has them, then from a Joliet
+//! supplementary volume descriptor, falling back to plain ISO 9660 names with the `;1` version
+//! suffix stripped.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+use std::path::{PathBuf, Path};
+use std::time::SystemTime;
+use std::collections::HashMap;
+use std::fs;
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind,
+    error::read_only_io,
+    civil_time,
+};
+
+const SECTOR_SIZE: u64 = 2048;
+/// Volume descriptors start after the 16 sector system area.
+const FIRST_DESCRIPTOR: u64 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NameFormat {
+    Plain,
+    Joliet,
+    RockRidge,
+}
+
+struct DirectoryRecord {
+    name: String,
+    extent: u32,
+    size: u32,
+    is_dir: bool,
+    modified: SystemTime,
+}
+
+/// The image file, shared by every object of one image.
+struct IsoImage {
+    file: fs::File,
+    names: NameFormat,
+}
+
+pub struct IsoDirectory {
+    name: PathBuf,
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+    is_root: bool,
+
+    extent: u32,
+    size: u32,
+    modified: SystemTime,
+    children: RwLock<HashMap<String, FilesystemObject>>,
+    scanned: AtomicBool,
+
+    image: Arc<RwLock<IsoImage>>,
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+pub struct IsoFile {
+    name: PathBuf,
+    path: PathBuf,
+    parent: Arc<RwLock<IsoDirectory>>,
+
+    extent: u32,
+    size: u32,
+    modified: SystemTime,
+
+    image: Arc<RwLock<IsoImage>>,
+    buffer: Vec<u8>,
+    /// Offset within the file that `buffer` starts at.
+    buf_offset: u64,
+    buf_filled: usize,
+    cursor: usize,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+fn invalid_image(msg: &str) -> IoError {
+    IoError::new(IoErrorKind::InvalidData, format!("invalid ISO 9660 image: {msg}"))
+}
+
+/// Strips the `;1` version suffix, and the trailing dot left on names without an extension.
+fn strip_version(name: &str) -> &str {
+    let name = name.split_once(';').map_or(name, |(name, _)| name);
+    name.strip_suffix('.').unwrap_or(name)
+}
+
+fn decode_joliet(raw: &[u8]) -> String {
+    let units: Vec<u16> = raw.chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    strip_version(&String::from_utf16_lossy(&units)).to_string()
+}
+
+/// Finds the SUSP entry with the given signature in a system use area.
+fn susp_entries<'a>(system_use: &'a [u8], signature: &'a [u8; 2]) -> impl Iterator<Item = &'a [u8]> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        while offset + 4 <= system_use.len() {
+            let entry_len = system_use[offset + 2] as usize;
+            if entry_len < 4 || offset + entry_len > system_use.len() || &system_use[offset..offset + 2] == b"ST" {
+                return None;
+            }
+
+            let entry = &system_use[offset..offset + entry_len];
+            offset += entry_len;
+            if &entry[..2] == signature {
+                return Some(&entry[4..]);
+            }
+        }
+        None
+    })
+}
+
+/// Assembles a Rock Ridge alternate name from its `NM` entries, if there are any.
+fn rock_ridge_name(system_use: &[u8]) -> Option<String> {
+    let mut name = Vec::new();
+    let mut found = false;
+    for entry in susp_entries(system_use, b"NM") {
+        found = true;
+        if let Some((_flags, piece)) = entry.split_first() {
+            name.extend_from_slice(piece);
+        }
+    }
+
+    found.then(|| String::from_utf8_lossy(&name).to_string())
+}
+
+/// Reads the extent and data length out of a directory record.
+fn record_extent(record: &[u8]) -> (u32, u32) {
+    let extent = u32::from_le_bytes(record[2..6].try_into().unwrap());
+    let size = u32::from_le_bytes(record[10..14].try_into().unwrap());
+    (extent, size)
+}
+
+/// Reads the recording time out of a directory record. Unset dates come back as the epoch.
+fn record_time(record: &[u8]) -> SystemTime {
+    let [year, month, day, hour, minute, second, offset] = record[18..25].try_into().unwrap();
+    if month == 0 || day == 0 {
+        return SystemTime::UNIX_EPOCH;
+    }
+
+    // The offset from UTC is in 15 minute intervals.
+    let offset = offset as i8 as i64 * 15 * 60;
+    civil_time(1900 + year as i64, month as u32, day as u32, hour as u32, minute as u32, second as u32, offset)
+}
+
+fn system_use_area(record: &[u8]) -> &[u8] {
+    let name_len = record[32] as usize;
+    // Names of even length are followed by a padding byte.
+    let start = 33 + name_len + (1 - name_len % 2);
+    record.get(start..).unwrap_or(&[])
+}
+
+impl IsoImage {
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> IoResult<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)
+    }
+
+    fn read_records(&mut self, extent: u32, size: u32) -> IoResult<Vec<DirectoryRecord>> {
+        let mut data = vec![0; size as usize];
+        self.read_exact_at(extent as u64 * SECTOR_SIZE, &mut data)?;
+
+        let mut records = Vec::new();
+        let mut offset = 0;
+        while offset < data.len() {
+            let len = data[offset] as usize;
+            if len == 0 {
+                // Records never straddle sectors; the rest of this one is padding.
+                offset = (offset / SECTOR_SIZE as usize + 1) * SECTOR_SIZE as usize;
+                continue;
+            }
+            if len < 34 || offset + len > data.len() {
+                return Err(invalid_image("truncated directory record"));
+            }
+
+            let record = &data[offset..offset + len];
+            offset += len;
+
+            let name_len = record[32] as usize;
+            let raw_name = record.get(33..33 + name_len).ok_or_else(|| invalid_image("directory record name overflows"))?;
+            if name_len == 1 && raw_name[0] <= 1 {
+                // `.` and `..`
+                continue;
+            }
+
+            let name = match self.names {
+                NameFormat::Joliet => decode_joliet(raw_name),
+                NameFormat::RockRidge => rock_ridge_name(system_use_area(record))
+                    .unwrap_or_else(|| strip_version(&String::from_utf8_lossy(raw_name)).to_string()),
+                NameFormat::Plain => strip_version(&String::from_utf8_lossy(raw_name)).to_string(),
+            };
+            let (extent, size) = record_extent(record);
+
+            records.push(DirectoryRecord{
+                name,
+                extent,
+                size,
+                is_dir: record[25] & 0x02 != 0,
+                modified: record_time(record),
+            });
+        }
+
+        Ok(records)
+    }
+}
+
+impl IsoDirectory {
+    pub fn new(file: Arc<RwLock<super::PhysicalFile>>) -> FsResult<Arc<RwLock<Self>>> {
+        let (name, path, parent, mut file) = {
+            let mut file_guard = file.write().unwrap();
+            let name = file_guard.name().to_path_buf();
+            let path = file_guard.get_full_path();
+            let parent = file_guard.get_parent();
+            let file_handle = if let Some(fh) = file_guard.take_handle() {
+                fh
+            } else {
+                file_guard.open()?;
+                file_guard.take_handle().unwrap()
+            };
+            (name, path, parent, file_handle)
+        };
+
+        let mut primary_root = None;
+        let mut joliet_root = None;
+        for sector in FIRST_DESCRIPTOR.. {
+            let mut descriptor = [0; SECTOR_SIZE as usize];
+            file.seek(SeekFrom::Start(sector * SECTOR_SIZE))?;
+            file.read_exact(&mut descriptor)?;
+
+            if &descriptor[1..6] != b"CD001" {
+                return Err(invalid_image("missing volume descriptor").into());
+            }
+
+            let root_record = &descriptor[156..190];
+            let root = (record_extent(root_record), record_time(root_record));
+            match descriptor[0] {
+                1 => primary_root = Some(root),
+                2 if matches!(&descriptor[88..91], b"%/@" | b"%/C" | b"%/E") => joliet_root = Some(root),
+                255 => break,
+                _ => {},
+            }
+        }
+
+        let primary_root = primary_root.ok_or_else(|| invalid_image("no primary volume descriptor"))?;
+
+        // Rock Ridge images announce SUSP with an `SP` entry on the root's `.` record.
+        let mut first_record = [0; 255];
+        file.seek(SeekFrom::Start(primary_root.0.0 as u64 * SECTOR_SIZE))?;
+        file.read_exact(&mut first_record)?;
+        let record = &first_record[..(first_record[0] as usize).max(34)];
+        let has_rock_ridge = susp_entries(system_use_area(record), b"SP").next().is_some();
+
+        let (((extent, size), modified), names) = match (has_rock_ridge, joliet_root) {
+            (true, _) => (primary_root, NameFormat::RockRidge),
+            (false, Some(joliet_root)) => (joliet_root, NameFormat::Joliet),
+            (false, None) => (primary_root, NameFormat::Plain),
+        };
+
+        let image = Arc::new(RwLock::new(IsoImage{ file, names }));
+        let root = Self::new_node(&name, path, parent, extent, size, modified, image);
+        root.write().unwrap().is_root = true;
+
+        Ok(root)
+    }
+
+    fn new_node(
+        name: &Path, path: PathBuf, parent: Option<FilesystemObject>,
+        extent: u32, size: u32, modified: SystemTime, image: Arc<RwLock<IsoImage>>,
+    ) -> Arc<RwLock<Self>> {
+        let new = Self{
+            name: name.to_path_buf(),
+            path,
+            parent,
+            is_root: false,
+
+            extent,
+            size,
+            modified,
+            children: RwLock::new(HashMap::new()),
+            scanned: AtomicBool::new(false),
+
+            image,
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Populates the children cache from the directory's records if it hasn't been already.
+    fn scan_if_needed(&self) -> FsResult {
+        if self.scanned.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let handle = self.handle.get().unwrap().upgrade().unwrap();
+        let records = self.image.write().unwrap().read_records(self.extent, self.size)?;
+
+        let mut children = self.children.write().unwrap();
+        for record in records {
+            if children.contains_key(&record.name) {
+                continue;
+            }
+
+            let name = Path::new(&record.name);
+            let path = self.path.join(name);
+            let child: FilesystemObject = if record.is_dir {
+                IsoDirectory::new_node(name, path, Some(handle.clone()), record.extent, record.size, record.modified, self.image.clone())
+            } else {
+                IsoFile::new(name, path, handle.clone(), record.extent, record.size, record.modified, self.image.clone())
+            };
+            children.insert(record.name, child);
+        }
+
+        self.scanned.store(true, Ordering::Release);
+
+        Ok(())
+    }
+}
+
+impl IsoFile {
+    fn new(
+        name: &Path, path: PathBuf, parent: Arc<RwLock<IsoDirectory>>,
+        extent: u32, size: u32, modified: SystemTime, image: Arc<RwLock<IsoImage>>,
+    ) -> Arc<RwLock<Self>> {
+        let new = Self{
+            name: name.to_path_buf(),
+            path,
+            parent,
+
+            extent,
+            size,
+            modified,
+
+            image,
+            buffer: vec![0; SECTOR_SIZE as usize],
+            buf_offset: 0,
+            buf_filled: 0,
+            cursor: 0,
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    fn fill_buffer(&mut self) -> IoResult<()> {
+        self.buf_offset += self.buf_filled as u64;
+        self.cursor = 0;
+
+        let remaining = (self.size as u64).saturating_sub(self.buf_offset);
+        let count = (self.buffer.len() as u64).min(remaining) as usize;
+        let offset = self.extent as u64 * SECTOR_SIZE + self.buf_offset;
+        self.image.write().unwrap().read_exact_at(offset, &mut self.buffer[..count])?;
+        self.buf_filled = count;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for IsoDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Children are only counted, printing them would recurse through the whole tree.
+        f.debug_struct("IsoDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("scanned", &self.scanned.load(Ordering::Acquire))
+            .field("cached_children", &self.children.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Read for IsoDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for IsoDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for IsoDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for IsoDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for IsoDirectory {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn exists(&self) -> bool {
+        !self.is_root || self.path.is_file()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(self.modified)
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        let index = (!self.is_root).then_some(self.extent as u64);
+        Ok(ObjectId::Archive { archive: Arc::as_ptr(&self.image) as *const () as usize, index })
+    }
+
+    fn readonly(&self) -> bool {
+        true
+    }
+
+    fn set_readonly(&mut self, _readonly: bool) -> FsResult {
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities{
+            has_timestamps: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("iso9660".to_string())
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("iso", "free_space"))
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("iso", "total_space"))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        let children = self.children.read().unwrap();
+        let mut entries: Vec<_> = children.iter().collect();
+        entries.sort_unstable_by_key(|(name, _)| *name);
+
+        Ok(entries.into_iter().map(|(_, child)| child.clone()).collect())
+    }
+
+    fn count_children(&self) -> FsResult<usize> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().len())
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        self.try_get_child(name)?
+            .ok_or_else(|| FsError::file_not_present(self.display_path(), name.to_string()))
+    }
+
+    fn try_get_child(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().get(name).cloned())
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().contains_key(name))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn new_file_all(&mut self, _path: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn new_dir_all(&mut self, _path: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        self.scanned.store(false, Ordering::Release);
+        self.scan_if_needed()
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+}
+
+impl fmt::Debug for IsoFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IsoFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("open", &self.is_open())
+            .finish()
+    }
+}
+
+impl Read for IsoFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.cursor >= self.buf_filled {
+            self.fill_buffer()?;
+        }
+
+        let byte_count = (self.buf_filled - self.cursor).min(buf.len());
+        let end = self.cursor + byte_count;
+
+        buf[..byte_count].copy_from_slice(&self.buffer[self.cursor..end]);
+        self.cursor = end;
+
+        Ok(byte_count)
+    }
+}
+
+impl BufRead for IsoFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.cursor >= self.buf_filled {
+            self.fill_buffer()?;
+        }
+
+        Ok(&self.buffer[self.cursor..self.buf_filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cursor = (self.cursor + amt).min(self.buf_filled);
+    }
+}
+
+impl Seek for IsoFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        let position = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(pos) => (self.size as u64).checked_add_signed(pos),
+            SeekFrom::Current(pos) => (self.buf_offset + self.cursor as u64).checked_add_signed(pos),
+        };
+        let position = position.ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "Invalid seek offset"))?;
+
+        self.buf_offset = position;
+        self.buf_filled = 0;
+        self.cursor = 0;
+
+        Ok(position)
+    }
+}
+
+impl Write for IsoFile {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(read_only_io(self.display_path()))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for IsoFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        true
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Ok(self.size as usize)
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Ok(self.buf_offset + self.cursor as u64)
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        let count = (self.size as u64).saturating_sub(offset).min(len as u64) as usize;
+        let mut data = vec![0; count];
+        if count > 0 {
+            let start = self.extent as u64 * SECTOR_SIZE + offset;
+            self.image.write().unwrap().read_exact_at(start, &mut data)?;
+        }
+
+        Ok(data)
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(self.modified)
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Files are told apart by where their data starts, so hard linked entries match. Empty files
+    /// may share their extent, so those only match themselves.
+    fn identity(&self) -> FsResult<ObjectId> {
+        let index = match self.size {
+            0 => Arc::as_ptr(&self.get()) as *const () as u64,
+            _ => self.extent as u64,
+        };
+        Ok(ObjectId::Archive { archive: Arc::as_ptr(&self.image) as *const () as usize, index: Some(index) })
+    }
+
+    fn readonly(&self) -> bool {
+        true
+    }
+
+    fn set_readonly(&mut self, _readonly: bool) -> FsResult {
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities{
+            has_timestamps: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("iso9660".to_string())
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("iso", "free_space"))
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("iso", "total_space"))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::unsupported("iso", "sync_all"))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::unsupported("iso", "sync_data"))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Ok(IsoFile::new(&self.name, self.path.clone(), self.parent.clone(), self.extent, self.size, self.modified, self.image.clone()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Drops whatever is buffered, to be read again from the image at the same position.
+    fn set_buffer_size(&mut self, size: usize) {
+        self.buf_offset += self.cursor as u64;
+        self.buf_filled = 0;
+        self.cursor = 0;
+        self.buffer.resize(size.max(1), 0);
+        self.buf_offset += self.cursor as u64;
+        self.buf_filled = 0;
+        self.cursor = 0;
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    /// A directory record for `name`, pointing at `extent`.
+    fn record(name: &[u8], extent: u32, size: u32, is_dir: bool) -> Vec<u8> {
+        let mut record = vec![0; 33];
+        record[2..6].copy_from_slice(&extent.to_le_bytes());
+        record[6..10].copy_from_slice(&extent.to_be_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[14..18].copy_from_slice(&size.to_be_bytes());
+        record[18..25].copy_from_slice(&[124, 6, 15, 12, 30, 0, 0]);
+        record[25] = if is_dir { 0x02 } else { 0 };
+        record[28..32].copy_from_slice(&[1, 0, 0, 1]);
+        record[32] = name.len() as u8;
+        record.extend_from_slice(name);
+        if name.len().is_multiple_of(2) {
+            record.push(0);
+        }
+        record[0] = record.len() as u8;
+        record
+    }
+
+    /// Writes an image holding `files` in its root, named plainly and, if `joliet` is given, with
+    /// those Joliet names.
+    fn write_image(path: &Path, files: &[(&str, &[u8])], joliet: Option<&[&str]>) {
+        const PRIMARY_ROOT: u32 = 20;
+        const JOLIET_ROOT: u32 = 21;
+        const FIRST_DATA: u32 = 22;
+
+        let sector = |n: u32| n as usize * SECTOR_SIZE as usize;
+        let mut image = vec![0; sector(FIRST_DATA + files.len() as u32)];
+
+        let mut descriptor = |n: u32, kind: u8, root: Option<u32>| {
+            let start = sector(n);
+            image[start] = kind;
+            image[start + 1..start + 6].copy_from_slice(b"CD001");
+            image[start + 6] = 1;
+            if let Some(root) = root {
+                image[start + 156..start + 190].copy_from_slice(&record(&[0], root, SECTOR_SIZE as u32, true));
+            }
+            if kind == 2 {
+                image[start + 88..start + 91].copy_from_slice(b"%/E");
+            }
+        };
+        descriptor(16, 1, Some(PRIMARY_ROOT));
+        match joliet {
+            Some(_) => {
+                descriptor(17, 2, Some(JOLIET_ROOT));
+                descriptor(18, 255, None);
+            },
+            None => descriptor(17, 255, None),
+        }
+
+        let mut directory = |n: u32, names: Vec<Vec<u8>>| {
+            let mut records = record(&[0], n, SECTOR_SIZE as u32, true);
+            records.extend(record(&[1], PRIMARY_ROOT, SECTOR_SIZE as u32, true));
+            for (i, (name, (_, contents))) in names.iter().zip(files).enumerate() {
+                records.extend(record(name, FIRST_DATA + i as u32, contents.len() as u32, false));
+            }
+            image[sector(n)..sector(n) + records.len()].copy_from_slice(&records);
+        };
+        directory(PRIMARY_ROOT, files.iter().map(|(name, _)| format!("{name};1").into_bytes()).collect());
+        if let Some(names) = joliet {
+            let utf16 = |name: &&str| name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            directory(JOLIET_ROOT, names.iter().map(utf16).collect());
+        }
+
+        for (i, (_, contents)) in files.iter().enumerate() {
+            let start = sector(FIRST_DATA + i as u32);
+            image[start..start + contents.len()].copy_from_slice(contents);
+        }
+
+        fs::write(path, image).unwrap();
+    }
+
+    fn open_image(path: &Path) -> Arc<RwLock<IsoDirectory>> {
+        IsoDirectory::new(crate::PhysicalFile::from_path(path, 0)).unwrap()
+    }
+
+    #[test]
+    fn lists_and_reads_joliet_names() {
+        let tmp = TempDir::new("iso-joliet");
+        let path = tmp.join("disc.iso");
+        let files: [(&str, &[u8]); 2] = [("README.TXT", b"read me"), ("LONGNA~1.TXT", b"a long name")];
+        write_image(&path, &files, Some(&["README.txt", "A rather long file name.txt"]));
+
+        let iso = open_image(&path);
+        let mut names: Vec<_> = iso.read().unwrap().get_children().unwrap().iter()
+            .map(|child| child.read().unwrap().name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["A rather long file name.txt", "README.txt"]);
+
+        let file = iso.read().unwrap().get_child("A rather long file name.txt").unwrap();
+        let mut contents = String::new();
+        file.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "a long name");
+    }
+
+    #[test]
+    fn images_report_no_mutating_capabilities() {
+        let tmp = TempDir::new("iso-capabilities");
+        let path = tmp.join("disc.iso");
+        write_image(&path, &[("README.TXT", b"read me")], None);
+        let read_only = Capabilities{ has_timestamps: true, ..Capabilities::default() };
+
+        let iso = open_image(&path);
+        assert_eq!(iso.read().unwrap().capabilities(), read_only);
+        let file = iso.read().unwrap().get_child("README.TXT").unwrap();
+        assert_eq!(file.read().unwrap().capabilities(), read_only);
+    }
+
+    #[test]
+    fn plain_names_lose_their_version() {
+        let tmp = TempDir::new("iso-plain");
+        let path = tmp.join("disc.iso");
+        write_image(&path, &[("README.TXT", b"read me")], None);
+
+        let iso = open_image(&path);
+        let file = iso.read().unwrap().get_child("README.TXT").unwrap();
+        assert_eq!(file.read().unwrap().size().unwrap(), 7);
+    }
+}
@@ -1,55 +1,292 @@
 //! Filesystem Abstractions (FSA)
-//! 
+//!
 //! This crate provides an abstraction layer for working with different types of filesystems,
 //! including physical directories and files, as well as support for virtual ones. Virtual
 //! filesystems are used to do things like have files in-memory, read a ZIP file's contents,
 //! etc. It defines traits for generic file and directory handling, enabling a unified interface
 //! for interacting with various storage backends.
 
-// pub mod physical;
+pub mod physical;
 pub mod error;
+pub mod text;
+pub mod delta;
+pub mod merge;
+pub mod overlay;
+pub mod memory;
+pub mod quota;
+pub mod chroot;
+pub mod concat;
+pub mod retry;
+mod sys;
+mod guard;
+mod wrapper;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature="hash")]
+mod hash;
 // pub mod virt;
-// #[cfg(feature="zip")]
-// pub mod zip;
+#[cfg(feature="zip")]
+pub mod zip;
+#[cfg(feature="iso")]
+pub mod iso;
+#[cfg(feature="crypto")]
+pub mod crypto;
+#[cfg(feature="async")]
+pub mod r#async;
+#[cfg(feature="watch")]
+pub mod watch;
 
-use std::io::{Write, BufRead, Seek};
+use std::io::{Write, Read, BufRead, Seek, SeekFrom};
 use std::sync::{RwLock, Arc};
-use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::{Component, Path, PathBuf};
+use std::collections::BinaryHeap;
+use std::cmp::Reverse;
+use std::time::SystemTime;
+#[cfg(any(feature="zip", feature="iso"))]
+use std::time::Duration;
+#[cfg(feature="hash")]
+use std::collections::HashMap;
 
-// pub use physical::{PhysicalDirectory, PhysicalFile};
+pub use physical::{PhysicalDirectory, PhysicalFile, OpenMode, LinkKind, FileLock, AccessHint};
+#[cfg(feature="mmap")]
+pub use physical::MappedWindow;
+#[cfg(feature="mmap")]
+pub use memmap2::Mmap;
 // pub use virt::{VirtualDirectory, VirtualFile};
 pub use error::FsError;
+pub use text::{TextPolicy, LineEnding};
+pub use delta::Delta;
+pub use merge::{MergePolicy, MergeReport};
+pub use overlay::{OverlayDirectory, OverlayFile};
+pub use memory::MemoryFile;
+pub use quota::{QuotaDirectory, QuotaFile};
+pub use chroot::{ChrootDirectory, ChrootFile};
+pub use concat::{ConcatFile, concat_readers};
+pub use retry::RetryPolicy;
+pub use sys::DIRECT_IO_ALIGNMENT;
+pub use guard::{ReadGuard, WriteGuard};
+#[cfg(feature="hash")]
+pub use hash::{DigestAlgo, SummaryWriter, WriteSummary};
 
-// #[cfg(feature="zip")]
-// pub use zip::{ZipDirectory, ZipFile};
+#[cfg(feature="zip")]
+pub use zip::{ZipDirectory, ZipFile, ZipCompression, ZipEntryInfo};
+#[cfg(feature="iso")]
+pub use iso::{IsoDirectory, IsoFile};
+#[cfg(feature="crypto")]
+pub use crypto::EncryptedFile;
+#[cfg(feature="async")]
+pub use r#async::{AsyncFilesystemObject, AsyncPhysicalDirectory, AsyncPhysicalFile};
+#[cfg(feature="watch")]
+pub use watch::FsEvent;
 
 /// Result type used throughout the crate, wrapping `FsError`.
 pub type FsResult<T = ()> = std::result::Result<T, FsError>;
 
 pub type FilesystemObject = Arc<RwLock<dyn FilesystemObjectRaw>>;
 
-pub trait FilesystemObjectRaw: BufRead + Seek + Write {
+/// What kind of object a [`FilesystemObjectRaw`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FsKind {
+    File,
+    Directory,
+}
+
+/// A child as listed by [`FilesystemObjectRaw::list`], without a handle to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: FsKind,
+    /// The file's size when it was listed, if the backend had it at hand. `None` for directories.
+    pub size_hint: Option<u64>,
+}
+
+/// What an object's backend supports, as reported by [`FilesystemObjectRaw::capabilities`].
+/// Read-only objects report none of the mutating capabilities.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// File contents can be written.
+    pub writable: bool,
+    /// Files and directories can be created.
+    pub can_create: bool,
+    /// Objects can be deleted.
+    pub can_delete: bool,
+    /// Entries can be renamed.
+    pub can_rename: bool,
+    /// The backend keeps modification times.
+    pub has_timestamps: bool,
+    /// The backend keeps access permissions.
+    pub has_permissions: bool,
+}
+
+/// What a handle refers to underneath, as compared by [`FilesystemObjectRaw::same_file`]. Handles
+/// from different backends never match.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectId {
+    /// Device and inode on Unix, volume serial number and file index on Windows.
+    Physical { device: u64, inode: u64 },
+    /// The archive or image open at address `archive`, or the entry at `index` within it.
+    Archive { archive: usize, index: Option<u64> },
+    /// In-memory contents stored at this address.
+    Memory(usize),
+    /// The wrapped object with this identity, seen through a chroot. It only matches other chroot
+    /// handles, so code looking for a physical object doesn't reach past the chroot.
+    Chroot(Box<ObjectId>),
+}
+
+impl std::fmt::Display for FsKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsKind::File => write!(f, "file"),
+            FsKind::Directory => write!(f, "directory"),
+        }
+    }
+}
+
+pub trait FilesystemObjectRaw: BufRead + Seek + Write + Send + Sync + std::fmt::Debug {
     /// Returns a shared reference to the object as a FilesystemObject.
     fn get(&self) -> FilesystemObject;
 
     /// Returns the name of the object.
     fn name(&self) -> &Path;
-    /// Returns the stem (name without extension) of the object.
-    fn stem(&self) -> &str { return self.name().file_stem().unwrap().to_str().unwrap() }
-    /// Returns the name's extension, if any.
-    fn ext(&self) -> Option<&str> { return self.name().extension().map(|x| x.to_str().unwrap()) }
+    /// Returns the stem (name without its last extension) of the object. A leading dot doesn't
+    /// start an extension, so `.gitignore` is its own stem. `None` if the name isn't valid UTF-8 or
+    /// has no stem, like `..`.
+    fn stem(&self) -> Option<&str> { self.name().file_stem().and_then(|stem| stem.to_str()) }
+    /// Returns the name's last extension, if it has one and it's valid UTF-8.
+    fn ext(&self) -> Option<&str> { self.name().extension().and_then(|ext| ext.to_str()) }
+    /// Whether the name ends in `.ext`, ignoring ASCII case. The leading dot is optional, and
+    /// `ext` may span several extensions, like `tar.gz`. As with [`FilesystemObjectRaw::ext`], a
+    /// leading dot doesn't start an extension, so `.gz` has none.
+    fn has_extension(&self, ext: &str) -> bool {
+        let ext = ext.strip_prefix('.').unwrap_or(ext);
+        let Some(name) = self.name().to_str() else { return false };
+        let Some(dot) = name.len().checked_sub(ext.len() + 1) else { return false };
+
+        !ext.is_empty() && dot > 0 && name.as_bytes()[dot] == b'.' && name[dot + 1..].eq_ignore_ascii_case(ext)
+    }
+    /// Whether the name matches a wildcard `pattern`, where `*` stands for any run of characters
+    /// and `?` for any single one. Matching is case-sensitive, and a name that isn't valid UTF-8
+    /// never matches.
+    fn name_matches(&self, pattern: &str) -> bool {
+        self.name().to_str().is_some_and(|name| wildcard_match(pattern, name))
+    }
+
+    /// Returns whether the object is a file or a directory.
+    fn kind(&self) -> FsKind;
+    /// Whether the object is a file.
+    fn is_file(&self) -> bool { self.kind() == FsKind::File }
+    /// Whether the object is a directory.
+    fn is_dir(&self) -> bool { self.kind() == FsKind::Directory }
+
+    /// Checks whether the object exists in its backing storage.
+    fn exists(&self) -> bool;
+    /// Re-checks the object's kind against its backing storage and returns it. If the path was
+    /// replaced by the other kind since this object was created, errors with
+    /// [`FsError::NotAFile`] or [`FsError::NotADirectory`] naming what's there now; if it's gone,
+    /// with [`FsError::FileNotPresent`]. Backends whose contents can't change underneath them
+    /// always match.
+    fn revalidate(&self) -> FsResult<FsKind> { Ok(self.kind()) }
 
     /// If file, returns the size of the file in bytes. Else, errors.
     fn size(&self) -> FsResult<usize>;
-    
-    /// Retrieves the parent object of the object.
-    fn get_parent(&self) -> FilesystemObject;
+    /// If file, shrinks or extends it to `len` bytes, writing out pending writes first. The
+    /// position is kept, even if that leaves it past the new end. Else, errors.
+    fn truncate(&mut self, len: usize) -> FsResult;
+    /// If file, reads up to `len` bytes starting at `offset`, fewer if the file ends first, without
+    /// moving the object's own position. Else, errors.
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>>;
+    /// If file, returns the position the next read or write starts at, like
+    /// [`Seek::stream_position`] but without needing `&mut`. Else, errors.
+    fn position(&self) -> FsResult<u64>;
+    /// If file, returns how many bytes are left between the position and the end of the file; 0
+    /// if the position is past the end. Else, errors.
+    fn remaining(&self) -> FsResult<u64> {
+        Ok((self.size()? as u64).saturating_sub(self.position()?))
+    }
+    /// Whether the file has nothing left to read from the position. False for directories, and
+    /// if the position or size can't be found.
+    fn is_eof(&self) -> bool {
+        self.remaining().is_ok_and(|remaining| remaining == 0)
+    }
+    /// Returns when the object's contents were last modified, as recorded by its backend.
+    fn modified(&self) -> FsResult<SystemTime>;
+    /// If file, returns its full path together with its full contents, read from the start, as one
+    /// unit to hand off elsewhere. The position is left at the end. Else, errors.
+    #[allow(clippy::wrong_self_convention)]
+    fn into_named_bytes(&mut self) -> FsResult<(PathBuf, Vec<u8>)> {
+        if !self.is_file() {
+            return Err(FsError::not_a_file(self.get_full_path().to_string_lossy().to_string(), self.kind()));
+        }
+
+        self.seek(SeekFrom::Start(0))?;
+        let mut contents = Vec::with_capacity(self.size()?);
+        self.read_to_end(&mut contents)?;
+
+        Ok((self.get_full_path(), contents))
+    }
+
+    /// Retrieves the parent object of the object, if it has one.
+    fn get_parent(&self) -> Option<FilesystemObject>;
     /// Returns the full path to the object.
     fn get_full_path(&self) -> PathBuf;
+    /// Returns a normalized form of [`FilesystemObjectRaw::get_full_path`], suitable for comparing
+    /// handles or deduplicating paths. By default `.` and `..` are resolved lexically; physical
+    /// objects resolve against the filesystem, following symlinks and making the path absolute.
+    fn canonicalize(&self) -> FsResult<PathBuf> {
+        Ok(normalize_lexically(&self.get_full_path()))
+    }
+    /// Identifies what the object refers to underneath, so aliases of it can be recognized.
+    fn identity(&self) -> FsResult<ObjectId>;
+    /// Whether `other` refers to the same file or directory as this object, including through a
+    /// hard link or a separately opened handle. Objects from different backends are never the
+    /// same.
+    fn same_file(&self, other: &FilesystemObject) -> FsResult<bool> {
+        // Locking `other` when it's this very object could deadlock.
+        if std::ptr::addr_eq(Arc::as_ptr(&self.get()), Arc::as_ptr(other)) {
+            return Ok(true);
+        }
+
+        Ok(self.identity()? == other.read().unwrap().identity()?)
+    }
+    /// Returns this object's full path relative to `base`'s, by comparing the paths component by
+    /// component without touching the backing storage. Errors if the object isn't `base` or
+    /// somewhere under it.
+    fn relative_path(&self, base: &FilesystemObject) -> FsResult<PathBuf> {
+        let path = self.get_full_path();
+        if std::ptr::addr_eq(Arc::as_ptr(&self.get()), Arc::as_ptr(base)) {
+            return Ok(PathBuf::new());
+        }
+
+        let base_path = base.read().unwrap().get_full_path();
+        match path.strip_prefix(&base_path) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => Err(FsError::generic(format!("'{}' is not under '{}'", path.display(), base_path.display()))),
+        }
+    }
+
+    /// Whether the object rejects mutation. Writes, flushes of pending data, creating, renaming,
+    /// moving and deleting children fail with [`FsError::ReadOnly`] instead.
+    fn readonly(&self) -> bool;
+    /// Marks the object read-only, or writable again. Directories pass the flag on to their cached
+    /// children, and children created later inherit it. Files flush pending writes before
+    /// becoming read-only. Backends without write support stay read-only regardless.
+    fn set_readonly(&mut self, readonly: bool) -> FsResult;
+    /// Reports what the object's backend supports, so callers can check before attempting an
+    /// operation rather than handling [`FsError::Unsupported`] after.
+    fn capabilities(&self) -> Capabilities;
+    /// Names the filesystem the object lives on, e.g. `ext4`, `apfs` or `nfs` for physical objects,
+    /// so behavior can be adapted to it. Other backends name themselves.
+    fn fs_type(&self) -> FsResult<String>;
+    /// Returns how many bytes can still be written where the object lives, as far as this process
+    /// is concerned. For physical objects that's the free space on their filesystem.
+    fn free_space(&self) -> FsResult<u64>;
+    /// Returns the total size in bytes of the storage the object lives on.
+    fn total_space(&self) -> FsResult<u64>;
 
     /// Moves an object from its current directory to the one provided.
     fn move_to(&mut self, new_dir: FilesystemObject) -> FsResult;
-    
+
     /// If file, opens the file, prepares it for read and write operations, and returns an I/O result.
     /// Else, errors.
     fn open(&mut self) -> std::io::Result<()>;
@@ -58,37 +295,544 @@ pub trait FilesystemObjectRaw: BufRead + Seek + Write {
     /// If file, closes the file, ensuring all changes are written and resources are released.
     /// Else, errors.
     fn close(&mut self);
+    /// If file, writes out pending bytes, then has the OS commit the file's data and metadata to
+    /// stable storage so they survive a crash or power loss. [`Write::flush`] only hands pending
+    /// bytes to the OS, which may keep them in memory for a while. Backends without storage of
+    /// their own to commit to fail with [`FsError::Unsupported`]. Else, errors.
+    fn sync_all(&mut self) -> FsResult;
+    /// Like [`FilesystemObjectRaw::sync_all`], but skips metadata that isn't needed to read the
+    /// data back, like the modification time, which can save a disk write.
+    fn sync_data(&mut self) -> FsResult;
+    /// If file, opens another handle onto the same contents with a buffer and position of its
+    /// own, starting at the beginning, so two readers neither contend for one lock nor share a
+    /// cursor. Nothing mutable is shared beyond the contents themselves, so bytes still pending
+    /// in one handle aren't seen through the other until they're flushed. Else, errors.
+    fn duplicate(&self) -> FsResult<FilesystemObject>;
+    /// If physical file, lends out the underlying [`std::fs::File`] for APIs that need one (mmap,
+    /// `fcntl`, `sendfile`, ...), opening it if needed. Pending writes are flushed and buffered
+    /// reads dropped first, so the handle's cursor is at this object's position. Once anything
+    /// reads, writes or seeks through the handle, this object's position and buffered data are
+    /// undefined until it's seeked again. Returns `None` for other backends and directories, or if
+    /// flushing or opening fails.
+    fn as_std_file(&mut self) -> Option<&std::fs::File> {
+        None
+    }
 
-    /// If directory, retrieves a list of children (files and directories) within this directory.
-    /// Uses cached results of [`Directory::scan`] if they exist.
+    /// If physical, the object's path on the real filesystem, which other physical objects may be
+    /// renamed into or linked to directly. `None` for other backends, wrappers over physical
+    /// objects included, since going straight to the path would skip their checks.
+    fn physical_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// If directory, retrieves a list of children (files and directories) within this directory,
+    /// sorted by name. Names compare byte-wise and case-sensitively, so the order is the same on
+    /// every platform and every run. Uses cached results of [`FilesystemObjectRaw::scan`] if they
+    /// exist.
     /// Else (not directory), then error.
-    fn get_children(&self) -> FsResult<Vec<Box<dyn FilesystemObjectRaw>>>;
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>>;
+    /// If directory, returns how many children [`FilesystemObjectRaw::get_children`] would
+    /// return, without building the list where the backend can avoid it. Else, errors.
+    fn count_children(&self) -> FsResult<usize> {
+        Ok(self.get_children()?.len())
+    }
+    /// If directory, lists the name, kind and size of each child, in the same order as
+    /// [`FilesystemObjectRaw::get_children`] but without building objects for them where the
+    /// backend can avoid it, so [`FilesystemObjectRaw::get_child`] can be left to the entries
+    /// actually used. Else, errors.
+    fn list(&self) -> FsResult<Vec<DirEntry>> {
+        self.get_children()?.iter()
+            .map(|child| {
+                let child = child.read().unwrap();
+                let size_hint = match child.kind() {
+                    FsKind::File => child.size().ok().map(|size| size as u64),
+                    FsKind::Directory => None,
+                };
+                Ok(DirEntry{ name: child.name().to_string_lossy().to_string(), kind: child.kind(), size_hint })
+            })
+            .collect()
+    }
+    /// If directory, returns a point-in-time copy of the children list, taken under the cache's
+    /// lock. Adding or removing children afterwards, even concurrently, doesn't affect the returned
+    /// vector, so it's safe to iterate while mutating the directory.
+    fn listing_snapshot(&self) -> FsResult<Vec<FilesystemObject>> {
+        self.get_children()
+    }
     /// Retrieves a specific child (file or directory) by name.
-    fn get_child(&self, name: &str) -> FsResult<Box<dyn FilesystemObjectRaw>>;
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject>;
+    /// Retrieves a specific child (file or directory) by name, returning `Ok(None)` if there is no
+    /// child with that name. Only errors for genuine failures (not a directory, I/O, ...).
+    fn try_get_child(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        match self.get_child(name) {
+            Ok(child) => Ok(Some(child)),
+            Err(FsError::FileNotPresent(..)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
     /// Checks if a child with the given name exists in the directory.
     fn has_child(&self, name: &str) -> FsResult<bool>;
 
     /// Renames the file to the specified new name.
     fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult;
+    /// Renames the object itself to `new_name` within its parent, keeping it usable under the new
+    /// name; the parent's cache drops the old name, so looking up the new one finds it. `new_name`
+    /// must be a single path segment. Fails with [`FsError::AlreadyExists`] if the name is taken,
+    /// and like [`FilesystemObjectRaw::child_rename`] where the backend can't rename.
+    fn rename(&mut self, new_name: &str) -> FsResult;
 
-    /// Creates a new file within the directory with the given name and buffer size.
+    /// Creates a new file within the directory with the given name and buffer size. Fails with
+    /// [`FsError::AlreadyExists`] if the name is taken, unless the backend was told to overwrite.
     fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject>;
-    /// Creates a new subdirectory within this directory.
+    /// Creates a new subdirectory within this directory. Fails with [`FsError::AlreadyExists`] like
+    /// [`FilesystemObjectRaw::new_file`].
     fn new_dir(&mut self, name: &str) -> FsResult<FilesystemObject>;
+    /// If directory, returns the child `name` if there is one, or else creates it as `kind`, with a
+    /// whole-file buffer if it's a file. Errors with [`FsError::NotAFile`] or
+    /// [`FsError::NotADirectory`] if the existing child is of the other kind. A child created by
+    /// someone else between the lookup and the creation is returned like one that was there all
+    /// along. Else, errors.
+    fn get_or_create_child(&mut self, name: &str, kind: FsKind) -> FsResult<FilesystemObject> {
+        let existing = match self.try_get_child(name)? {
+            Some(child) => child,
+            None => {
+                let created = match kind {
+                    FsKind::File => self.new_file(name, 0),
+                    FsKind::Directory => self.new_dir(name),
+                };
+                match created {
+                    Err(FsError::AlreadyExists(..)) => self.get_child(name)?,
+                    created => return created,
+                }
+            },
+        };
+
+        let found = existing.read().unwrap().kind();
+        if found != kind {
+            let path = existing.read().unwrap().get_full_path().to_string_lossy().to_string();
+            return Err(match kind {
+                FsKind::File => FsError::not_a_file(path, found),
+                FsKind::Directory => FsError::not_a_directory(path, found),
+            });
+        }
+
+        Ok(existing)
+    }
+    /// If directory, creates a new file named `prefix`, then a suffix unique to this process and
+    /// call, then `ext` (with or without its dot, or empty for none), and returns it. Else, errors.
+    /// The default goes through [`FilesystemObjectRaw::new_file`], moving on to another name if
+    /// one is taken.
+    fn create_temp_file(&mut self, prefix: &str, ext: &str) -> FsResult<FilesystemObject> {
+        create_temp_child(prefix, ext, |name| self.new_file(name, 0))
+    }
+    /// If directory, creates a new subdirectory named like [`FilesystemObjectRaw::create_temp_file`]
+    /// names files, without an extension, and returns it. Else, errors.
+    fn create_temp_dir(&mut self, prefix: &str) -> FsResult<FilesystemObject> {
+        create_temp_child(prefix, "", |name| self.new_dir(name))
+    }
+    /// Creates a new file at the relative multi-segment `path`, creating any missing intermediate
+    /// directories first (like `mkdir -p`). Directories created before a failure are kept; the
+    /// error names the segment that failed.
+    fn new_file_all(&mut self, path: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        let segments = path_segments(path)?;
+        let (name, dirs) = segments.split_last().unwrap();
+        if dirs.is_empty() {
+            return self.new_file(name, buffer_size);
+        }
+
+        let parent = self.new_dir_all(&dirs.join("/"))?;
+        let file = parent.write().unwrap().new_file(name, buffer_size);
+        file.map_err(|err| FsError::PathSegment(name.to_string(), Box::new(err)))
+    }
+    /// Creates the directory at the relative multi-segment `path` along with any missing
+    /// intermediate directories, returning the final one. Existing directories along the way are
+    /// reused. Directories created before a failure are kept; the error names the segment that
+    /// failed.
+    fn new_dir_all(&mut self, path: &str) -> FsResult<FilesystemObject> {
+        create_dir_segments(self, &path_segments(path)?)
+    }
 
     /// Invalidate (and drop) the cached info for a child, if applicable. Typically used for moving
     /// a child from one parent to another, or deleting a child
     fn drop_child(&mut self, name: &str) -> FsResult;
-    /// Sets the buffer size for file operations.
+    /// Caches `child` under `name`, for when a child renamed itself through
+    /// [`FilesystemObjectRaw::rename`] and stays the same object under its new name. Does nothing
+    /// by default, for backends that don't cache children.
+    fn cache_child(&mut self, _name: &str, _child: FilesystemObject) -> FsResult {
+        Ok(())
+    }
+    /// Sets the buffer size for file operations. It can be changed in the middle of reading: bytes
+    /// already buffered but not yet read are still returned next, none skipped or repeated.
     fn set_buffer_size(&mut self, size: usize);
 
-    /// Scans the directory contents and caches the results. Speeds up [`Directory::get_children`].
-    fn scan(&mut self) -> FsResult<()> { unimplemented!() }
+    /// Scans the directory contents and caches the results. Speeds up
+    /// [`FilesystemObjectRaw::get_children`].
+    fn scan(&mut self) -> FsResult<()>;
+    /// If directory, checks whether the cached children still match the backing storage. Else,
+    /// errors. The default has nothing to check, for backends whose contents can't change
+    /// underneath them.
+    fn validate_cache(&self) -> FsResult<bool> {
+        if !self.is_dir() {
+            return Err(FsError::not_a_directory(self.get_full_path().to_string_lossy().to_string(), self.kind()));
+        }
+
+        Ok(true)
+    }
+    /// If directory, rebuilds the children cache from the backing storage, dropping any cached
+    /// children that are stale. Else, errors. Defaults to [`FilesystemObjectRaw::scan`].
+    fn repair_cache(&mut self) -> FsResult {
+        self.scan()
+    }
 
-    /// Deletes the file. Unsure how to handle this since it should invalidate all active handles,
-    /// but that's not actually possible with existing types.
+    /// Deletes the object, failing with [`FsError::ReadOnly`] if it's read-only and
+    /// [`FsError::Unsupported`] if the backend can't delete. Other handles to the object aren't
+    /// invalidated, since that's not actually possible with existing types.
     /// TODO: Look into RwLock with an integrated Option?
-    fn delete(&mut self) -> FsResult { unimplemented!() }
+    fn delete(&mut self) -> FsResult;
+    /// If directory, deletes it along with everything under it, invalidating the cached handles
+    /// to anything in the tree. Else, errors. The default just calls
+    /// [`FilesystemObjectRaw::delete`], which suits backends that delete whole subtrees anyway or
+    /// can't delete at all.
+    fn delete_all(&mut self) -> FsResult {
+        if !self.is_dir() {
+            return Err(FsError::not_a_directory(self.get_full_path().to_string_lossy().to_string(), self.kind()));
+        }
+
+        self.delete()
+    }
+    /// Drops cached state (open handles, pending writes and cached children) without touching the
+    /// backing storage, for when the object was removed through one of its ancestors. Does nothing
+    /// by default.
+    fn invalidate(&mut self) { }
+
+    /// If directory, walks the tree and returns the `n` largest files with their paths relative to
+    /// this directory, largest first. Only `n` entries are held at any time, however big the tree.
+    /// Ties are broken by path.
+    fn top_files_by_size(&self, n: usize) -> FsResult<Vec<(PathBuf, u64)>> {
+        let mut heap = BinaryHeap::with_capacity(n + 1);
+        walk_files(self, Path::new(""), &mut |path, file| {
+            let size = file.read().unwrap().size()? as u64;
+            // Min-heap on size; among equal sizes the later path is evicted first.
+            heap.push(Reverse((size, Reverse(path))));
+            if heap.len() > n {
+                heap.pop();
+            }
+            Ok(())
+        })?;
+
+        Ok(heap.into_sorted_vec().into_iter()
+            .map(|Reverse((size, Reverse(path)))| (path, size))
+            .collect())
+    }
+
+    /// If directory, renders the hierarchy below it like the `tree` command, one name per line
+    /// with `├──`/`└──` connectors and children sorted by name. Directories deeper than
+    /// `max_depth` levels aren't listed at all. Else, errors.
+    fn tree_string(&self, max_depth: Option<usize>) -> FsResult<String> {
+        let mut out = format!("{}\n", self.name().display());
+        render_tree(self, "", max_depth, &mut out)?;
+        Ok(out)
+    }
+
+    /// If directory or archive, compares its files against those under `against` by relative path
+    /// and contents, reporting what's new, changed or removed relative to `against`. Works across
+    /// backends, so an archive can be checked against the directory it was built from.
+    fn archive_delta(&self, against: &dyn FilesystemObjectRaw) -> FsResult<Delta> {
+        delta::delta(self, against)
+    }
+
+    /// If directory, recursively copies `source`'s files and directories into this one, creating
+    /// what's missing. A file that already exists is handled according to `policy`. Works across
+    /// backends, so an archive can be merged into a physical directory.
+    fn merge_from(&mut self, source: &dyn FilesystemObjectRaw, policy: MergePolicy) -> FsResult<MergeReport> {
+        merge::merge(self, source, policy)
+    }
+
+    /// If file, hashes its full contents with `algo`, reading through the file's buffer. The file
+    /// is rewound first and left positioned at its end. Else, errors.
+    #[cfg(feature="hash")]
+    fn digest(&mut self, algo: DigestAlgo) -> FsResult<Vec<u8>> {
+        hash::digest(self, algo)
+    }
+    /// If file, reads its full contents while hashing them with `algo`, returning the bytes if the
+    /// digest matches `expected` and [`FsError::HashMismatch`] if it doesn't. Else, errors.
+    #[cfg(feature="hash")]
+    fn read_verified(&mut self, expected: &[u8], algo: DigestAlgo) -> FsResult<Vec<u8>> {
+        hash::read_verified(self, expected, algo)
+    }
+
+    /// If directory, walks the tree and returns groups of two or more files with identical
+    /// contents. Files are grouped by size first, so only files sharing a size get hashed.
+    /// Groups, and the files within them, are ordered by full path.
+    #[cfg(feature="hash")]
+    fn find_duplicates(&self) -> FsResult<Vec<Vec<FilesystemObject>>> {
+        let mut by_size: HashMap<usize, Vec<FilesystemObject>> = HashMap::new();
+        walk_files(self, Path::new(""), &mut |_, file| {
+            let size = file.read().unwrap().size()?;
+            by_size.entry(size).or_default().push(file);
+            Ok(())
+        })?;
+
+        let mut groups = Vec::new();
+        for candidates in by_size.into_values().filter(|group| group.len() > 1) {
+            let mut by_hash: HashMap<[u8; 32], Vec<FilesystemObject>> = HashMap::new();
+            for file in candidates {
+                let digest = hash::content_hash(&mut *file.write().unwrap())?;
+                by_hash.entry(digest).or_default().push(file);
+            }
+            groups.extend(by_hash.into_values().filter(|group| group.len() > 1));
+        }
+
+        for group in groups.iter_mut() {
+            group.sort_by_cached_key(|file| file.read().unwrap().get_full_path());
+        }
+        groups.sort_by_cached_key(|group| group[0].read().unwrap().get_full_path());
+
+        Ok(groups)
+    }
+
+    /// If directory, hashes the tree's files by relative path and contents into a signature that
+    /// only changes when a file is added, removed, renamed or edited. Timestamps and permissions
+    /// are left out, so the signature suits cache keys. Empty directories don't count.
+    #[cfg(feature="hash")]
+    fn content_signature(&self) -> FsResult<[u8; 32]> {
+        hash::content_signature(self)
+    }
+}
+
+/// Recursively visits every file under `dir`, along with its path relative to `dir` joined onto
+/// `prefix`. Names are joined rather than split, so flat archive listings with `/` in entry names
+/// line up with nested directories.
+pub(crate) fn walk_files<T, F>(dir: &T, prefix: &Path, visit: &mut F) -> FsResult
+where
+    T: FilesystemObjectRaw + ?Sized,
+    F: FnMut(PathBuf, FilesystemObject) -> FsResult,
+{
+    for child in dir.get_children()? {
+        let guard = child.read().unwrap();
+        let path = prefix.join(guard.name());
+        if guard.is_dir() {
+            walk_files(&*guard, &path, visit)?;
+        } else {
+            drop(guard);
+            visit(path, child)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends the lines for `dir`'s children to `out`, each starting with `prefix`.
+fn render_tree<T: FilesystemObjectRaw + ?Sized>(dir: &T, prefix: &str, max_depth: Option<usize>, out: &mut String) -> FsResult {
+    if max_depth == Some(0) {
+        return Ok(());
+    }
+
+    let mut children: Vec<(PathBuf, bool, FilesystemObject)> = dir.get_children()?
+        .into_iter()
+        .map(|child| {
+            let guard = child.read().unwrap();
+            let (name, is_dir) = (guard.name().to_path_buf(), guard.is_dir());
+            drop(guard);
+            (name, is_dir, child)
+        })
+        .collect();
+    children.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+
+    let count = children.len();
+    for (i, (name, is_dir, child)) in children.into_iter().enumerate() {
+        let last = i + 1 == count;
+        out.push_str(prefix);
+        out.push_str(if last { "└── " } else { "├── " });
+        out.push_str(&name.to_string_lossy());
+        out.push('\n');
+
+        if is_dir {
+            let child_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            render_tree(&*child.read().unwrap(), &child_prefix, max_depth.map(|depth| depth - 1), out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolves `.` and `..` components without touching the filesystem. Leading `..`s of a relative
+/// path are kept, while `..` at the root is dropped.
+pub(crate) fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => { normalized.pop(); },
+                Some(Component::RootDir | Component::Prefix(_)) => {},
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Converts a calendar date and time of day, taken `offset` seconds ahead of UTC, to a
+/// [`SystemTime`]. Archive formats record timestamps like this rather than as an epoch count.
+#[cfg(any(feature="zip", feature="iso"))]
+pub(crate) fn civil_time(year: i64, month: u32, day: u32, hour: u32, minute: u32, second: u32, offset: i64) -> SystemTime {
+    // Days since the epoch, counting years from March so leap days come last.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds = days * 86_400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64 - offset;
+    match seconds {
+        0.. => SystemTime::UNIX_EPOCH + Duration::from_secs(seconds as u64),
+        _ => SystemTime::UNIX_EPOCH - Duration::from_secs(seconds.unsigned_abs()),
+    }
+}
+
+/// Splits a relative path into its named segments, rejecting absolute paths and `..`.
+pub(crate) fn path_segments(path: &str) -> FsResult<Vec<&str>> {
+    let mut segments = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(segment) => segments.push(segment.to_str().unwrap()),
+            Component::CurDir => {},
+            _ => return Err(FsError::generic(format!("'{path}' must be relative and not contain '..'"))),
+        }
+    }
+
+    if segments.is_empty() {
+        return Err(FsError::generic(format!("'{path}' does not name anything")));
+    }
+
+    Ok(segments)
+}
+
+/// Matches `name` against a pattern where `*` is any run of characters and `?` any one.
+pub(crate) fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and where in the name it's currently matched up to.
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            },
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            },
+            _ => match star {
+                // Let the last `*` swallow one more character and try again from there.
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Errors unless `name` is a single path segment.
+pub(crate) fn check_segment(name: &str) -> FsResult {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', std::path::MAIN_SEPARATOR]) {
+        return Err(FsError::generic(format!("'{name}' is not a single path segment")));
+    }
+
+    Ok(())
+}
+
+/// How many names [`create_temp_child`] and [`create_temp_sibling`] try before giving up.
+const TEMP_NAME_ATTEMPTS: usize = 1000;
+
+/// Makes a fresh temporary name out of `prefix`, the process ID, a per-process counter and `ext`.
+fn temp_name(prefix: &str, ext: &str) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let ext = ext.trim_start_matches('.');
+
+    let mut name = format!("{prefix}{:x}-{:x}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed));
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(ext);
+    }
+
+    name
+}
+
+/// Creates a child through `create` under a fresh name from [`temp_name`], trying the next name
+/// whenever one turns out to be taken.
+pub(crate) fn create_temp_child(
+    prefix: &str, ext: &str, mut create: impl FnMut(&str) -> FsResult<FilesystemObject>,
+) -> FsResult<FilesystemObject> {
+    for _ in 0..TEMP_NAME_ATTEMPTS {
+        match create(&temp_name(prefix, ext)) {
+            Err(FsError::AlreadyExists(..)) => continue,
+            result => return result,
+        }
+    }
+
+    Err(FsError::generic(format!("no unused name found for a temporary '{prefix}' child")))
+}
+
+/// Creates a hidden file next to `path`, named after it, to write a replacement in before renaming
+/// it over `path`. It's opened with `options` and `create_new`, under a fresh name from
+/// [`temp_name`] for every attempt, so writers replacing the same file never share one. Returns
+/// the new file's path along with it.
+pub(crate) fn create_temp_sibling(
+    path: &Path, options: &std::fs::OpenOptions,
+) -> std::io::Result<(PathBuf, std::fs::File)> {
+    let prefix = format!(".{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    for _ in 0..TEMP_NAME_ATTEMPTS {
+        let temp_path = path.with_file_name(temp_name(&prefix, "tmp"));
+        match options.clone().create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    }
+
+    let message = format!("no unused temporary name found next to '{}'", path.display());
+    Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, message))
+}
+
+/// Walks `segments` down from `dir`, creating each directory that isn't already a child.
+pub(crate) fn create_dir_segments<T: FilesystemObjectRaw + ?Sized>(dir: &mut T, segments: &[&str]) -> FsResult<FilesystemObject> {
+    fn step<T: FilesystemObjectRaw + ?Sized>(dir: &mut T, segment: &str) -> FsResult<FilesystemObject> {
+        match dir.try_get_child(segment)? {
+            Some(child) => Ok(child),
+            None => match dir.new_dir(segment) {
+                // It appeared after the children were cached; refresh them to pick it up.
+                Err(FsError::AlreadyExists(..)) => {
+                    dir.scan()?;
+                    dir.get_child(segment)
+                },
+                result => result,
+            },
+        }
+    }
+
+    let mut current: Option<FilesystemObject> = None;
+    for &segment in segments {
+        let next = match &current {
+            None => step(dir, segment),
+            Some(current) => step(&mut *current.write().unwrap(), segment),
+        };
+        current = Some(next.map_err(|err| FsError::PathSegment(segment.to_string(), Box::new(err)))?);
+    }
+
+    current.ok_or_else(|| FsError::generic("no path segments given".to_string()))
 }
 
 impl std::fmt::Display for dyn FilesystemObjectRaw {
@@ -96,3 +840,816 @@ impl std::fmt::Display for dyn FilesystemObjectRaw {
         write!(f, "{:?}", self.get_full_path())
     }
 }
+
+impl dyn FilesystemObjectRaw {
+    /// If file, opens it and returns a guard for reading it that closes it again when dropped.
+    /// Else, errors.
+    pub fn open_read(&mut self) -> FsResult<ReadGuard<'_>> {
+        ReadGuard::new(self)
+    }
+
+    /// If file, opens it and returns a guard for writing it that closes it again when dropped,
+    /// writing out pending bytes. A file that doesn't exist yet is created by the first flush.
+    /// Else, errors.
+    pub fn open_write(&mut self) -> FsResult<WriteGuard<'_>> {
+        WriteGuard::new(self)
+    }
+
+    /// Reads up to `n` bytes from the current position and seeks back to it, so the next read
+    /// starts where it would have anyway. Fewer bytes are returned only if the file ends first.
+    /// Handy for sniffing a file's type from its magic number before deciding how to parse it.
+    pub fn peek(&mut self, n: usize) -> FsResult<Vec<u8>> {
+        let position = self.stream_position()?;
+
+        let mut bytes = Vec::with_capacity(n);
+        let read = Read::take(&mut *self, n as u64).read_to_end(&mut bytes);
+        self.seek(SeekFrom::Start(position))?;
+        read?;
+
+        Ok(bytes)
+    }
+
+    /// If file, checks whether its contents match `other`'s, which may be from another backend.
+    /// Sizes are compared first; only if they match are both files rewound and read in lockstep,
+    /// stopping at the first difference, where they're left positioned. A file always matches
+    /// itself. Else, errors.
+    pub fn content_equals(&mut self, other: &FilesystemObject) -> FsResult<bool> {
+        if !self.is_file() {
+            return Err(FsError::not_a_file(self.get_full_path().to_string_lossy().to_string(), self.kind()));
+        }
+        // Locking `other` would deadlock if it's this very object.
+        if Arc::ptr_eq(&self.get(), other) {
+            return Ok(true);
+        }
+
+        let mut other = other.write().unwrap();
+        if !other.is_file() {
+            return Err(FsError::not_a_file(other.get_full_path().to_string_lossy().to_string(), other.kind()));
+        }
+        if self.size()? != other.size()? {
+            return Ok(false);
+        }
+
+        self.seek(SeekFrom::Start(0))?;
+        other.seek(SeekFrom::Start(0))?;
+        loop {
+            let ours = self.fill_buf()?;
+            if ours.is_empty() {
+                return Ok(other.fill_buf()?.is_empty());
+            }
+            let theirs = other.fill_buf()?;
+            if theirs.is_empty() {
+                return Ok(false);
+            }
+
+            let len = ours.len().min(theirs.len());
+            if ours[..len] != theirs[..len] {
+                return Ok(false);
+            }
+            self.consume(len);
+            other.consume(len);
+        }
+    }
+
+    /// Reads the next `size_of::<T>()` bytes from the current position and reinterprets them as a
+    /// `T`, erroring if the file ends first. The bytes are taken as they are in the file, so
+    /// converting multi-byte fields from the file's byte order is up to the caller (e.g. with
+    /// `u32::from_le`).
+    #[cfg(feature="bytemuck")]
+    pub fn read_pod<T: bytemuck::Pod>(&mut self) -> FsResult<T> {
+        let mut value = T::zeroed();
+        self.read_exact(bytemuck::bytes_of_mut(&mut value))?;
+
+        Ok(value)
+    }
+
+    /// Opens the file if needed, rewinds it and streams its lines, without the `\n` or `\r\n` that
+    /// ends them. A last line without a newline is still yielded. Lines that aren't valid UTF-8
+    /// yield an error, and reading carries on after them. Unlike [`BufRead::lines`], this works
+    /// through a trait object and starts from the beginning.
+    pub fn read_lines(&mut self) -> FsResult<impl Iterator<Item = FsResult<String>> + '_> {
+        if !self.is_open() {
+            self.open()?;
+        }
+        self.seek(SeekFrom::Start(0))?;
+
+        Ok(std::iter::from_fn(move || {
+            let mut line = String::new();
+            match self.read_line(&mut line) {
+                Ok(0) => None,
+                Ok(_) => {
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    Some(Ok(line))
+                },
+                Err(error) => Some(Err(error.into())),
+            }
+        }))
+    }
+
+    /// Streams the file as JSON Lines, deserializing each line as a `T`. Blank lines are skipped.
+    /// A line that fails to parse yields [`FsError::JsonLine`] and the stream carries on with the
+    /// next one.
+    #[cfg(feature="serde")]
+    pub fn json_lines<T: serde::de::DeserializeOwned>(&mut self) -> FsResult<impl Iterator<Item = FsResult<T>> + '_> {
+        let path = self.get_full_path().to_string_lossy().to_string();
+
+        Ok(self.read_lines()?.enumerate().filter_map(move |(index, line)| {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => return None,
+                Ok(line) => line,
+                Err(error) => return Some(Err(error)),
+            };
+
+            Some(serde_json::from_str(&line).map_err(|error| FsError::json_line(path.clone(), index + 1, error)))
+        }))
+    }
+
+    /// Counts the lines [`read_lines`](Self::read_lines) would yield, streaming rather than loading
+    /// the file. Only newlines are looked at, so lines that aren't valid UTF-8 count too.
+    pub fn line_count(&mut self) -> FsResult<usize> {
+        if !self.is_open() {
+            self.open()?;
+        }
+        self.seek(SeekFrom::Start(0))?;
+
+        let mut count = 0;
+        let mut unterminated = false;
+        loop {
+            let available = self.fill_buf()?;
+            let Some(&last) = available.last() else { break };
+
+            count += available.iter().filter(|byte| **byte == b'\n').count();
+            unterminated = last != b'\n';
+
+            let len = available.len();
+            self.consume(len);
+        }
+
+        Ok(count + unterminated as usize)
+    }
+
+    /// Opens the file if needed, rewinds it and streams it as chunks of `chunk_size` bytes, the
+    /// last one shorter if the size isn't a multiple of it. Each chunk is filled from however many
+    /// reads it takes, so chunk boundaries don't depend on the backend's buffer size.
+    pub fn iter_chunks(&mut self, chunk_size: usize) -> FsResult<impl Iterator<Item = FsResult<Vec<u8>>> + '_> {
+        if chunk_size == 0 {
+            return Err(FsError::generic("chunk size must be at least 1".to_string()));
+        }
+        if !self.is_open() {
+            self.open()?;
+        }
+        self.seek(SeekFrom::Start(0))?;
+
+        Ok(std::iter::from_fn(move || {
+            let mut chunk = Vec::with_capacity(chunk_size);
+            while chunk.len() < chunk_size {
+                let available = match self.fill_buf() {
+                    Ok(available) => available,
+                    Err(error) if error.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(error) => return Some(Err(error.into())),
+                };
+                if available.is_empty() {
+                    break;
+                }
+
+                let len = available.len().min(chunk_size - chunk.len());
+                chunk.extend_from_slice(&available[..len]);
+                self.consume(len);
+            }
+
+            (!chunk.is_empty()).then_some(Ok(chunk))
+        }))
+    }
+
+    /// If file, returns a writer that writes to it from the current position while tallying the
+    /// size, BLAKE3 hash and newline count of what goes through, so generated output doesn't need
+    /// a second pass to summarize. Else, errors.
+    #[cfg(feature="hash")]
+    pub fn write_with_summary(&mut self) -> FsResult<SummaryWriter<'_>> {
+        if !self.is_file() {
+            return Err(FsError::not_a_file(self.get_full_path().to_string_lossy().to_string(), self.kind()));
+        }
+
+        Ok(SummaryWriter::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn try_get_child_separates_missing_from_failing() {
+        let tmp = TempDir::new("try-get-child");
+        tmp.write("present.txt", "here");
+        let check = |dir: FilesystemObject| {
+            let dir = dir.read().unwrap();
+            let present = dir.try_get_child("present.txt").unwrap().unwrap();
+            assert_eq!(present.read().unwrap().name(), Path::new("present.txt"));
+            assert!(dir.try_get_child("missing.txt").unwrap().is_none());
+
+            let present = present.read().unwrap();
+            assert!(matches!(present.try_get_child("anything"), Err(FsError::NotADirectory(..))));
+        };
+
+        check(PhysicalDirectory::from_path(tmp.path()));
+
+        #[cfg(feature = "zip")]
+        {
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(tmp.join("archive.zip")).unwrap());
+            writer.start_file("present.txt", ::zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"here").unwrap();
+            writer.finish().unwrap();
+            check(ZipDirectory::new(PhysicalFile::from_path(tmp.join("archive.zip"), 0)).unwrap());
+        }
+    }
+
+    #[test]
+    fn listing_snapshot_is_unaffected_by_later_changes() {
+        let tmp = TempDir::new("snapshot");
+        fs::write(tmp.join("a.txt"), "a").unwrap();
+        let dir = PhysicalDirectory::from_path(tmp.path());
+
+        let snapshot = dir.read().unwrap().listing_snapshot().unwrap();
+        dir.write().unwrap().new_file("b.txt", 0).unwrap();
+        dir.write().unwrap().new_dir("c").unwrap();
+
+        let names: Vec<_> = snapshot.iter().map(|child| child.read().unwrap().name().to_path_buf()).collect();
+        assert_eq!(names, [Path::new("a.txt")]);
+        assert_eq!(dir.read().unwrap().listing_snapshot().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn canonicalize_resolves_parent_components() {
+        let tmp = TempDir::new("canonicalize");
+        fs::create_dir(tmp.join("sub")).unwrap();
+        fs::write(tmp.join("a.txt"), "a").unwrap();
+
+        let dir = PhysicalDirectory::from_path(tmp.path().join("sub").join(".."));
+        let file = dir.read().unwrap().get_child("a.txt").unwrap();
+        assert_eq!(file.read().unwrap().canonicalize().unwrap(), fs::canonicalize(tmp.join("a.txt")).unwrap());
+
+        assert_eq!(normalize_lexically(Path::new("a/./b/../c")), Path::new("a/c"));
+        assert_eq!(normalize_lexically(Path::new("../a/../../b")), Path::new("../../b"));
+        assert_eq!(normalize_lexically(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn canonicalize_makes_relative_roots_absolute() {
+        let dir = PhysicalDirectory::from_path("src/..");
+        assert!(dir.read().unwrap().get_full_path().is_relative());
+
+        let manifest = dir.read().unwrap().get_child("Cargo.toml").unwrap();
+        let expected = std::env::current_dir().unwrap().join("Cargo.toml");
+        assert_eq!(manifest.read().unwrap().canonicalize().unwrap(), fs::canonicalize(expected).unwrap());
+    }
+
+    #[test]
+    fn tree_string_renders_sorted_hierarchy() {
+        let tmp = TempDir::new("tree");
+        tmp.write("README.md", "");
+        tmp.write("src/lib.rs", "");
+        tmp.write("src/bin/main.rs", "");
+        tmp.write("Cargo.toml", "");
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let root = format!("{}\n", tmp.path().display());
+        assert_eq!(dir.read().unwrap().tree_string(None).unwrap(), root.clone() + "\
+├── Cargo.toml
+├── README.md
+└── src
+    ├── bin
+    │   └── main.rs
+    └── lib.rs
+");
+        assert_eq!(dir.read().unwrap().tree_string(Some(2)).unwrap(), root + "\
+├── Cargo.toml
+├── README.md
+└── src
+    ├── bin
+    └── lib.rs
+");
+    }
+
+    #[test]
+    fn top_files_by_size_keeps_the_largest() {
+        let tmp = TempDir::new("top");
+        tmp.write("small.txt", [0; 10]);
+        tmp.write("big.bin", [0; 500]);
+        tmp.write("nested/deep/huge.bin", [0; 1000]);
+        tmp.write("nested/mid-a.txt", [0; 200]);
+        tmp.write("nested/mid-b.txt", [0; 200]);
+        tmp.write("empty.txt", []);
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let top = dir.read().unwrap().top_files_by_size(4).unwrap();
+        assert_eq!(top, vec![
+            (PathBuf::from("nested/deep/huge.bin"), 1000),
+            (PathBuf::from("big.bin"), 500),
+            (PathBuf::from("nested/mid-a.txt"), 200),
+            (PathBuf::from("nested/mid-b.txt"), 200),
+        ]);
+        assert!(dir.read().unwrap().top_files_by_size(0).unwrap().is_empty());
+        assert_eq!(dir.read().unwrap().top_files_by_size(100).unwrap().len(), 6);
+    }
+
+    #[cfg(feature="bytemuck")]
+    #[test]
+    fn read_pod_reads_a_packed_header() {
+        #[derive(Clone, Copy)]
+        #[repr(C)]
+        struct Header {
+            magic: [u8; 4],
+            version: u16,
+            flags: u16,
+            length: u32,
+        }
+        // Safety: `repr(C)`, no padding, and every bit pattern is a valid `Header`.
+        unsafe impl bytemuck::Zeroable for Header {}
+        unsafe impl bytemuck::Pod for Header {}
+
+        let tmp = TempDir::new("pod");
+        let mut bytes = b"FSAH".to_vec();
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&0x8001u16.to_le_bytes());
+        bytes.extend_from_slice(&1234u32.to_le_bytes());
+        tmp.write("header.bin", &bytes);
+
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("header.bin"), 0);
+        let mut file = file.write().unwrap();
+        let header: Header = file.read_pod().unwrap();
+        assert_eq!(&header.magic, b"FSAH");
+        assert_eq!(u16::from_le(header.version), 2);
+        assert_eq!(u16::from_le(header.flags), 0x8001);
+        assert_eq!(u32::from_le(header.length), 1234);
+
+        // Nothing is left for a second header.
+        assert!(file.read_pod::<Header>().is_err());
+    }
+
+    #[test]
+    fn stem_and_ext_never_panic() {
+        let named = |name: &Path| PhysicalDirectory::from_path(name);
+
+        let dotfile = named(Path::new(".gitignore"));
+        assert_eq!(dotfile.read().unwrap().stem(), Some(".gitignore"));
+        assert_eq!(dotfile.read().unwrap().ext(), None);
+
+        let tarball = named(Path::new("archive.tar.gz"));
+        assert_eq!(tarball.read().unwrap().stem(), Some("archive.tar"));
+        assert_eq!(tarball.read().unwrap().ext(), Some("gz"));
+
+        let parent = named(Path::new(".."));
+        assert_eq!(parent.read().unwrap().stem(), None);
+        assert_eq!(parent.read().unwrap().ext(), None);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let invalid = named(Path::new(std::ffi::OsStr::from_bytes(b"bad\xff.t\xffxt")));
+            assert_eq!(invalid.read().unwrap().stem(), None);
+            assert_eq!(invalid.read().unwrap().ext(), None);
+        }
+    }
+
+    #[test]
+    fn read_lines_handles_mixed_endings_and_a_missing_newline() {
+        let tmp = TempDir::new("lines");
+        tmp.write("mixed.txt", "crlf\r\nlf\n\r\nlast");
+        tmp.write("empty.txt", "");
+
+        // A small buffer makes lines span several refills.
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("mixed.txt"), 3);
+        let mut file = file.write().unwrap();
+        let lines: Vec<String> = file.read_lines().unwrap().collect::<FsResult<_>>().unwrap();
+        assert_eq!(lines, ["crlf", "lf", "", "last"]);
+        assert_eq!(file.line_count().unwrap(), 4);
+        // Both start over from the beginning each time.
+        assert_eq!(file.read_lines().unwrap().count(), 4);
+
+        let empty: FilesystemObject = PhysicalFile::from_path(tmp.join("empty.txt"), 0);
+        assert_eq!(empty.write().unwrap().line_count().unwrap(), 0);
+        assert_eq!(empty.write().unwrap().read_lines().unwrap().count(), 0);
+    }
+
+    #[cfg(feature="serde")]
+    #[test]
+    fn json_lines_reports_bad_lines_and_carries_on() {
+        let tmp = TempDir::new("jsonl");
+        tmp.write("events.jsonl", "[\"start\", 1]\n\n[\"oops\", \n  \r\n[\"stop\", 2]\n");
+
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("events.jsonl"), 0);
+        let mut file = file.write().unwrap();
+        let results: Vec<FsResult<(String, u32)>> = file.json_lines().unwrap().collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &("start".to_string(), 1));
+        assert!(matches!(results[1], Err(FsError::JsonLine { line: 3, .. })), "{:?}", results[1]);
+        assert_eq!(results[2].as_ref().unwrap(), &("stop".to_string(), 2));
+    }
+
+    #[test]
+    fn same_file_recognizes_aliases() {
+        let tmp = TempDir::new("same-file");
+        tmp.write("a.txt", "same");
+        tmp.write("b.txt", "same");
+        fs::hard_link(tmp.join("a.txt"), tmp.join("link.txt")).unwrap();
+
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("a.txt"), 0);
+        let same = |other: FilesystemObject| file.read().unwrap().same_file(&other).unwrap();
+        assert!(same(file.clone()));
+        assert!(same(PhysicalFile::from_path(tmp.join("a.txt"), 0)));
+        assert!(same(PhysicalFile::from_path(tmp.join("link.txt"), 0)));
+        assert!(!same(PhysicalFile::from_path(tmp.join("b.txt"), 0)));
+        assert!(!same(PhysicalDirectory::from_path(tmp.path())));
+        assert!(!same(memory::shared_memory_file(b"same".to_vec())));
+    }
+
+    #[test]
+    fn into_named_bytes_bundles_path_and_contents() {
+        let tmp = TempDir::new("named-bytes");
+        tmp.write("report.csv", "a,b\n1,2\n");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+
+        let file = dir.read().unwrap().get_child("report.csv").unwrap();
+        let mut file = file.write().unwrap();
+        let mut head = [0; 2];
+        file.read_exact(&mut head).unwrap();
+        let (path, bytes) = file.into_named_bytes().unwrap();
+        assert_eq!(path, tmp.join("report.csv"));
+        assert_eq!(bytes, b"a,b\n1,2\n");
+
+        let memory = memory::shared_memory_file(b"in memory".to_vec());
+        assert_eq!(memory.write().unwrap().into_named_bytes().unwrap(), (PathBuf::from("memory"), b"in memory".to_vec()));
+
+        let result = dir.write().unwrap().into_named_bytes();
+        assert!(matches!(result, Err(FsError::NotAFile(..))), "{result:?}");
+    }
+
+    #[test]
+    fn count_children_matches_get_children() {
+        let tmp = TempDir::new("count");
+        tmp.write("base/a.txt", "");
+        tmp.write("base/b.txt", "");
+        tmp.write("base/sub/c.txt", "");
+        tmp.write("layer/a.txt", "");
+        tmp.write("layer/d.txt", "");
+        let counts = |dir: FilesystemObject| {
+            let dir = dir.read().unwrap();
+            (dir.count_children().unwrap(), dir.get_children().unwrap().len())
+        };
+
+        let base = PhysicalDirectory::from_path(tmp.join("base"));
+        assert_eq!(counts(base.clone()), (3, 3));
+        assert_eq!(counts(QuotaDirectory::new(base.clone(), 1000).unwrap()), (3, 3));
+        let layer = PhysicalDirectory::from_path(tmp.join("layer"));
+        assert_eq!(counts(OverlayDirectory::new(vec![layer, base]).unwrap()), (4, 4));
+
+        #[cfg(feature="zip")]
+        {
+            let path = tmp.join("archive.zip");
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            for name in ["a.txt", "sub/b.txt", "sub/deeper/c.txt", "d.txt"] {
+                writer.start_file(name, ::zip::write::SimpleFileOptions::default()).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(counts(ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap()), (4, 4));
+        }
+    }
+
+    #[test]
+    fn list_reports_names_kinds_and_sizes_in_order() {
+        let tmp = TempDir::new("list");
+        tmp.write("base/b.txt", "bb");
+        tmp.write("base/a.txt", "a");
+        tmp.write("base/c/inner.txt", "");
+        let names = |entries: &[DirEntry]| entries.iter().map(|entry| (entry.name.clone(), entry.kind)).collect::<Vec<_>>();
+        let expected = vec![
+            ("a.txt".to_string(), FsKind::File),
+            ("b.txt".to_string(), FsKind::File),
+            ("c".to_string(), FsKind::Directory),
+        ];
+
+        let base = PhysicalDirectory::from_path(tmp.join("base"));
+        let listed = base.read().unwrap().list().unwrap();
+        assert_eq!(names(&listed), expected);
+        assert_eq!(listed[2].size_hint, None);
+        #[cfg(windows)]
+        assert_eq!((listed[0].size_hint, listed[1].size_hint), (Some(1), Some(2)));
+
+        let chroot = ChrootDirectory::new(base.clone()).unwrap();
+        assert_eq!(chroot.read().unwrap().list().unwrap(), listed);
+        let quota = QuotaDirectory::new(base.clone(), 1000).unwrap();
+        assert_eq!(quota.read().unwrap().list().unwrap(), listed);
+        assert!(PhysicalDirectory::from_path(tmp.join("missing")).read().unwrap().list().unwrap().is_empty());
+
+        #[cfg(feature="zip")]
+        {
+            let path = tmp.join("archive.zip");
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            writer.add_directory("empty/", ::zip::write::SimpleFileOptions::default()).unwrap();
+            for (name, contents) in [("sub/deeper/c.txt", "ccc"), ("b.txt", ""), ("sub/a.txt", "aa")] {
+                writer.start_file(name, ::zip::write::SimpleFileOptions::default()).unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+
+            let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+            let listed = zip.read().unwrap().list().unwrap();
+            let listed = listed.iter().map(|entry| (entry.name.as_str(), entry.kind, entry.size_hint)).collect::<Vec<_>>();
+            assert_eq!(listed, vec![
+                ("b.txt", FsKind::File, Some(0)),
+                ("sub/a.txt", FsKind::File, Some(2)),
+                ("sub/deeper/c.txt", FsKind::File, Some(3)),
+            ]);
+        }
+    }
+
+    #[test]
+    fn relative_path_strips_the_ancestor() {
+        let tmp = TempDir::new("relative");
+        tmp.write("a/b/c/file.txt", "");
+        tmp.write("other/file.txt", "");
+        let root: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+        let a = root.read().unwrap().get_child("a").unwrap();
+        let c = a.read().unwrap().get_child("b").unwrap().read().unwrap().get_child("c").unwrap();
+        let file = c.read().unwrap().get_child("file.txt").unwrap();
+        let other = root.read().unwrap().get_child("other").unwrap();
+
+        assert_eq!(file.read().unwrap().relative_path(&root).unwrap(), Path::new("a/b/c/file.txt"));
+        assert_eq!(file.read().unwrap().relative_path(&a).unwrap(), Path::new("b/c/file.txt"));
+        assert_eq!(c.read().unwrap().relative_path(&c).unwrap(), Path::new(""));
+        assert!(file.read().unwrap().relative_path(&other).is_err());
+        assert!(root.read().unwrap().relative_path(&a).is_err());
+
+        // Prefixes only count at component boundaries.
+        let sibling: FilesystemObject = PhysicalDirectory::from_path(tmp.join("a/b/c2"));
+        assert!(sibling.read().unwrap().relative_path(&c).is_err());
+    }
+
+    #[test]
+    fn peek_leaves_the_position_alone() {
+        let tmp = TempDir::new("peek");
+        tmp.write("image.png", b"\x89PNG\r\n\x1a\nrest of the file");
+        let file: FilesystemObject = PhysicalFile::from_path(tmp.join("image.png"), 3);
+        let mut file = file.write().unwrap();
+
+        assert_eq!(file.peek(4).unwrap(), b"\x89PNG");
+        let mut all = Vec::new();
+        file.read_to_end(&mut all).unwrap();
+        assert_eq!(all, b"\x89PNG\r\n\x1a\nrest of the file");
+
+        file.seek(SeekFrom::Start(8)).unwrap();
+        assert_eq!(file.peek(4).unwrap(), b"rest");
+        assert_eq!(file.peek(100).unwrap(), b"rest of the file");
+        assert_eq!(file.stream_position().unwrap(), 8);
+    }
+
+    #[test]
+    fn remaining_reaches_zero_at_the_end() {
+        let tmp = TempDir::new("remaining");
+        let contents = "0123456789".repeat(10);
+        tmp.write("data.txt", &contents);
+        let check = |file: FilesystemObject| {
+            let mut file = file.write().unwrap();
+            assert_eq!(file.remaining().unwrap(), 100);
+            assert!(!file.is_eof());
+
+            let mut start = [0; 30];
+            file.read_exact(&mut start).unwrap();
+            assert_eq!(file.remaining().unwrap(), 70);
+            assert!(!file.is_eof());
+
+            let mut rest = Vec::new();
+            file.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest.len(), 70);
+            assert_eq!(file.remaining().unwrap(), 0);
+            assert!(file.is_eof());
+
+            file.seek(SeekFrom::Start(95)).unwrap();
+            assert_eq!(file.remaining().unwrap(), 5);
+            file.seek(SeekFrom::Start(200)).unwrap();
+            assert_eq!(file.remaining().unwrap(), 0);
+            assert!(file.is_eof());
+        };
+
+        check(PhysicalFile::from_path(tmp.join("data.txt"), 16));
+
+        // Zip entries decompress ahead of the position, which must still count what was consumed.
+        #[cfg(feature = "zip")]
+        {
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(tmp.join("archive.zip")).unwrap());
+            writer.start_file("data.txt", ::zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let archive = ZipDirectory::new(PhysicalFile::from_path(tmp.join("archive.zip"), 0)).unwrap();
+            let entry = archive.read().unwrap().get_child("data.txt").unwrap();
+            entry.write().unwrap().set_buffer_size(16);
+            check(entry);
+        }
+
+        let dir: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+        assert!(!dir.read().unwrap().is_eof());
+        assert!(matches!(dir.read().unwrap().remaining(), Err(FsError::NotAFile(..))));
+    }
+
+    #[test]
+    fn rename_resolves_under_the_new_name() {
+        let tmp = TempDir::new("rename-self");
+        tmp.write("old.txt", "contents");
+        tmp.write("taken.txt", "");
+        tmp.write("sub/inner.txt", "inner");
+        let dir: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+
+        let file = dir.read().unwrap().get_child("old.txt").unwrap();
+        file.write().unwrap().rename("new.txt").unwrap();
+        assert_eq!(file.read().unwrap().name(), Path::new("new.txt"));
+        assert!(!dir.read().unwrap().has_child("old.txt").unwrap());
+        let found = dir.read().unwrap().get_child("new.txt").unwrap();
+        assert!(Arc::ptr_eq(&found, &file));
+        assert_eq!(fs::read_to_string(found.read().unwrap().get_full_path()).unwrap(), "contents");
+
+        let sub = dir.read().unwrap().get_child("sub").unwrap();
+        sub.write().unwrap().rename("renamed").unwrap();
+        let inner = dir.read().unwrap().get_child("renamed").unwrap().read().unwrap().get_child("inner.txt").unwrap();
+        assert_eq!(inner.read().unwrap().get_full_path(), tmp.join("renamed").join("inner.txt"));
+
+        for bad in ["a/b", "..", ""] {
+            assert!(matches!(file.write().unwrap().rename(bad), Err(FsError::Generic(..))), "{bad:?}");
+        }
+        assert!(matches!(file.write().unwrap().rename("taken.txt"), Err(FsError::AlreadyExists(..))));
+        assert_eq!(fs::read_to_string(tmp.join("new.txt")).unwrap(), "contents");
+    }
+
+    #[test]
+    fn content_equals_compares_sizes_then_bytes() {
+        let tmp = TempDir::new("content-equals");
+        let contents = "0123456789".repeat(100);
+        tmp.write("a.txt", &contents);
+        tmp.write("b.txt", &contents);
+        tmp.write("same-size.txt", format!("{}x", &contents[..999]));
+        tmp.write("shorter.txt", &contents[..999]);
+        let file = |name: &str, buffer_size| -> FilesystemObject { PhysicalFile::from_path(tmp.join(name), buffer_size) };
+
+        let a = file("a.txt", 7);
+        let mut a = a.write().unwrap();
+        assert!(a.content_equals(&file("b.txt", 64)).unwrap());
+        assert!(!a.content_equals(&file("same-size.txt", 3)).unwrap());
+        assert!(!a.content_equals(&file("shorter.txt", 0)).unwrap());
+        let itself = a.get();
+        assert!(a.content_equals(&itself).unwrap());
+
+        let dir: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+        assert!(matches!(a.content_equals(&dir), Err(FsError::NotAFile(..))));
+
+        #[cfg(feature = "zip")]
+        {
+            use std::io::Write;
+
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(tmp.join("archive.zip")).unwrap());
+            writer.start_file("a.txt", ::zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let archive = ZipDirectory::new(PhysicalFile::from_path(tmp.join("archive.zip"), 0)).unwrap();
+            let entry = archive.read().unwrap().get_child("a.txt").unwrap();
+            assert!(a.content_equals(&entry).unwrap());
+            assert!(!entry.write().unwrap().content_equals(&file("same-size.txt", 5)).unwrap());
+        }
+    }
+
+    #[test]
+    fn iter_chunks_ends_with_a_short_chunk() {
+        let tmp = TempDir::new("iter-chunks");
+        let contents: Vec<u8> = (0..95).collect();
+        tmp.write("data.bin", &contents);
+        let check = |file: FilesystemObject| {
+            let mut file = file.write().unwrap();
+            let chunks: Vec<_> = file.iter_chunks(10).unwrap().map(Result::unwrap).collect();
+            assert_eq!(chunks.len(), 10);
+            assert!(chunks[..9].iter().all(|chunk| chunk.len() == 10));
+            assert_eq!(chunks[9], &contents[90..]);
+            assert_eq!(chunks.concat(), contents);
+
+            assert!(matches!(file.iter_chunks(0), Err(FsError::Generic(..))));
+        };
+
+        check(PhysicalFile::from_path(tmp.join("data.bin"), 7));
+        check(PhysicalFile::from_path(tmp.join("data.bin"), 0));
+
+        #[cfg(feature = "zip")]
+        {
+            use std::io::Write;
+
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(tmp.join("archive.zip")).unwrap());
+            writer.start_file("data.bin", ::zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(&contents).unwrap();
+            writer.finish().unwrap();
+
+            let archive = ZipDirectory::new(PhysicalFile::from_path(tmp.join("archive.zip"), 0)).unwrap();
+            check(archive.read().unwrap().get_child("data.bin").unwrap());
+        }
+    }
+
+    #[test]
+    fn extension_and_name_predicates() {
+        let named = |name: &str| MemoryFile::from_bytes(name, b"");
+        let archive = named("archive.tar.gz");
+        let archive = archive.read().unwrap();
+        for ext in ["GZ", "gz", ".gz", "tar.gz", ".TAR.GZ"] {
+            assert!(archive.has_extension(ext), "{ext}");
+        }
+        for ext in ["", ".", "z", "tar", "archive.tar.gz", "xarchive.tar.gz"] {
+            assert!(!archive.has_extension(ext), "{ext}");
+        }
+        assert!(!named("Makefile").read().unwrap().has_extension("Makefile"));
+        assert!(!named(".gz").read().unwrap().has_extension("gz"));
+
+        assert!(archive.name_matches("*.gz"));
+        assert!(archive.name_matches("archive.???.gz"));
+        assert!(archive.name_matches("*"));
+        assert!(archive.name_matches("a*.*.*z"));
+        assert!(!archive.name_matches("*.GZ"));
+        assert!(!archive.name_matches("*.tar"));
+        assert!(!archive.name_matches("archive.??.gz"));
+    }
+
+    #[test]
+    fn duplicates_read_different_regions_concurrently() {
+        let tmp = TempDir::new("duplicate");
+        let contents: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        tmp.write("data.bin", &contents);
+        let check = |file: FilesystemObject| {
+            // Holding the original's lock shows the duplicates don't need it.
+            let original = file.write().unwrap();
+            let readers: Vec<_> = [(0, 100), (5000, 3000), (9990, 10)].into_iter()
+                .map(|(start, len)| {
+                    let duplicate = original.duplicate().unwrap();
+                    std::thread::spawn(move || {
+                        let mut duplicate = duplicate.write().unwrap();
+                        duplicate.seek(SeekFrom::Start(start)).unwrap();
+                        let mut region = vec![0; len];
+                        duplicate.read_exact(&mut region).unwrap();
+                        (start as usize, region)
+                    })
+                })
+                .collect();
+
+            for reader in readers {
+                let (start, region) = reader.join().unwrap();
+                assert_eq!(region, &contents[start..start + region.len()]);
+            }
+        };
+
+        check(PhysicalFile::from_path(tmp.join("data.bin"), 64));
+
+        #[cfg(feature = "zip")]
+        {
+            let mut writer = ::zip::ZipWriter::new(fs::File::create(tmp.join("archive.zip")).unwrap());
+            writer.start_file("data.bin", ::zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(&contents).unwrap();
+            writer.finish().unwrap();
+
+            let archive = ZipDirectory::new(PhysicalFile::from_path(tmp.join("archive.zip"), 0)).unwrap();
+            check(archive.read().unwrap().get_child("data.bin").unwrap());
+        }
+    }
+
+    #[test]
+    fn get_or_create_child_reuses_or_creates() {
+        let tmp = TempDir::new("get-or-create");
+        tmp.write("existing.txt", "made elsewhere");
+        let dir: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+
+        let sub = dir.get_or_create_child("sub", FsKind::Directory).unwrap();
+        assert!(tmp.join("sub").is_dir());
+        let file = dir.get_or_create_child("file.txt", FsKind::File).unwrap();
+        assert!(file.read().unwrap().is_file());
+
+        assert!(Arc::ptr_eq(&dir.get_or_create_child("sub", FsKind::Directory).unwrap(), &sub));
+        assert!(Arc::ptr_eq(&dir.get_or_create_child("file.txt", FsKind::File).unwrap(), &file));
+        let existing = dir.get_or_create_child("existing.txt", FsKind::File).unwrap();
+        assert_eq!(fs::read_to_string(existing.read().unwrap().get_full_path()).unwrap(), "made elsewhere");
+
+        assert_eq!(
+            dir.get_or_create_child("sub", FsKind::File).unwrap_err(),
+            FsError::not_a_file(tmp.join("sub").to_string_lossy().to_string(), FsKind::Directory),
+        );
+        assert_eq!(
+            dir.get_or_create_child("existing.txt", FsKind::Directory).unwrap_err(),
+            FsError::not_a_directory(tmp.join("existing.txt").to_string_lossy().to_string(), FsKind::File),
+        );
+
+        let memory = MemoryFile::from_bytes("memory.bin", b"");
+        assert!(matches!(memory.write().unwrap().get_or_create_child("x", FsKind::File), Err(FsError::NotADirectory(..))));
+    }
+}
@@ -0,0 +1,405 @@
+//! The [memory](`self`) module provides [`MemoryFile`], a file living entirely in memory.
+//!
+//! A file's contents are an `Arc<RwLock<Vec<u8>>>` that any number of handles can share, each
+//! with its own cursor. Writes through one handle are visible to reads through the others as soon
+//! as they return, so one handle can produce data while another consumes the growing buffer.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+use std::path::{PathBuf, Path};
+use std::time::SystemTime;
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind,
+    error::read_only_io,
+};
+
+/// How many bytes a read copies out of the shared buffer at a time, unless set otherwise.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+pub struct MemoryFile {
+    name: PathBuf,
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+
+    data: Arc<RwLock<Vec<u8>>>,
+    /// Offset in `data` of `buffer[0]`; the handle's position is this plus `cursor`.
+    position: usize,
+    /// Bytes copied out of `data` for `BufRead`, which can't lend a slice out of the lock.
+    buffer: Vec<u8>,
+    buffer_size: usize,
+    cursor: usize,
+    readonly: bool,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+/// Creates a standalone in-memory file named `memory` holding `initial`. Further handles onto the
+/// same contents come from [`MemoryFile::new_handle`].
+pub fn shared_memory_file(initial: Vec<u8>) -> Arc<RwLock<MemoryFile>> {
+    MemoryFile::new(Path::new("memory"), PathBuf::from("memory"), None, Arc::new(RwLock::new(initial)))
+}
+
+impl MemoryFile {
+    fn new(name: &Path, path: PathBuf, parent: Option<FilesystemObject>, data: Arc<RwLock<Vec<u8>>>) -> Arc<RwLock<Self>> {
+        let new = Self{
+            name: name.to_path_buf(),
+            path,
+            parent,
+
+            data,
+            position: 0,
+            buffer: Vec::new(),
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            cursor: 0,
+            readonly: false,
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    /// Creates a standalone in-memory file named `name` holding a copy of `bytes`, ready to read
+    /// from the start. Handy for feeding `include_bytes!` assets to code that takes a
+    /// [`FilesystemObject`]. Writes extend the copy, never `bytes`.
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Arc<RwLock<Self>> {
+        Self::new(Path::new(name), PathBuf::from(name), None, Arc::new(RwLock::new(bytes.to_vec())))
+    }
+
+    /// Opens another handle onto the same contents. It has its own cursor, starting at the
+    /// beginning, and starts out with this handle's read-only flag.
+    pub fn new_handle(&self) -> Arc<RwLock<Self>> {
+        let handle = Self::new(&self.name, self.path.clone(), self.parent.clone(), self.data.clone());
+        handle.write().unwrap().readonly = self.readonly;
+
+        handle
+    }
+
+    pub fn get_buffer(&self) -> Arc<RwLock<Vec<u8>>> {
+        self.data.clone()
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Drops the bytes copied out for reading, which may be stale after a write or seek.
+    fn discard_buffer(&mut self) {
+        self.position += self.cursor;
+        self.buffer.clear();
+        self.cursor = 0;
+    }
+}
+
+impl fmt::Debug for MemoryFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("len", &self.data.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Read for MemoryFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let available = self.fill_buf()?;
+        let byte_count = available.len().min(buf.len());
+
+        buf[..byte_count].copy_from_slice(&available[..byte_count]);
+        self.consume(byte_count);
+
+        Ok(byte_count)
+    }
+}
+
+impl BufRead for MemoryFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.cursor >= self.buffer.len() {
+            self.discard_buffer();
+
+            let data = self.data.read().unwrap();
+            let start = self.position.min(data.len());
+            let end = match self.buffer_size {
+                0 => data.len(),
+                size => (start + size).min(data.len()),
+            };
+            self.buffer.extend_from_slice(&data[start..end]);
+        }
+
+        Ok(&self.buffer[self.cursor..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.cursor = (self.cursor + amt).min(self.buffer.len());
+    }
+}
+
+impl Seek for MemoryFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.discard_buffer();
+
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos as i64, 0),
+            SeekFrom::End(offset) => (self.data.read().unwrap().len() as i64, offset),
+            SeekFrom::Current(offset) => (self.position as i64, offset),
+        };
+
+        let position = base.checked_add(offset)
+            .filter(|position| *position >= 0)
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        self.position = position as usize;
+
+        Ok(self.position as u64)
+    }
+}
+
+impl Write for MemoryFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        if self.readonly {
+            return Err(read_only_io(self.display_path()));
+        }
+        self.discard_buffer();
+
+        let mut data = self.data.write().unwrap();
+        let end = self.position + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[self.position..end].copy_from_slice(buf);
+        self.position = end;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for MemoryFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        true
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Ok(self.data.read().unwrap().len())
+    }
+
+    fn truncate(&mut self, len: usize) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        self.discard_buffer();
+        self.data.write().unwrap().resize(len, 0);
+
+        Ok(())
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Ok((self.position + self.cursor) as u64)
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        let data = self.data.read().unwrap();
+        let start = (offset.min(data.len() as u64)) as usize;
+        let end = start + len.min(data.len() - start);
+
+        Ok(data[start..end].to_vec())
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Err(FsError::unsupported("memory", "modified"))
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Ok(ObjectId::Memory(Arc::as_ptr(&self.data) as *const () as usize))
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.readonly = readonly;
+        Ok(())
+    }
+
+    /// Memory files have no timestamps or permissions, and no directory to be created, renamed or
+    /// deleted in.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities{
+            writable: !self.readonly,
+            ..Capabilities::default()
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("memory".to_string())
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("memory", "free_space"))
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("memory", "total_space"))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("memory", "move_to"))
+    }
+
+    /// Memory files are always open.
+    fn open(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::unsupported("memory", "sync_all"))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::unsupported("memory", "sync_data"))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Ok(self.new_handle())
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::unsupported("memory", "rename"))
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn set_buffer_size(&mut self, size: usize) {
+        self.buffer_size = size;
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::unsupported("memory", "delete"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_through_one_handle_reach_the_others() {
+        let writer = shared_memory_file(b"head:".to_vec());
+        let reader = writer.read().unwrap().new_handle();
+        let mut line = String::new();
+
+        writer.write().unwrap().seek(SeekFrom::End(0)).unwrap();
+        writer.write().unwrap().write_all(b"first\n").unwrap();
+        reader.write().unwrap().read_line(&mut line).unwrap();
+        assert_eq!(line, "head:first\n");
+
+        // The reader picks up where it left off, independently of the writer's cursor.
+        writer.write().unwrap().write_all(b"second\n").unwrap();
+        line.clear();
+        reader.write().unwrap().read_line(&mut line).unwrap();
+        assert_eq!(line, "second\n");
+        assert_eq!(reader.write().unwrap().stream_position().unwrap(), 18);
+        assert_eq!(&*writer.read().unwrap().get_buffer().read().unwrap(), b"head:first\nsecond\n");
+    }
+
+    #[test]
+    fn capabilities_follow_the_readonly_flag() {
+        let file = MemoryFile::from_bytes("asset.txt", b"");
+        assert_eq!(file.read().unwrap().capabilities(), Capabilities{ writable: true, ..Capabilities::default() });
+        file.write().unwrap().set_readonly(true).unwrap();
+        assert_eq!(file.read().unwrap().capabilities(), Capabilities::default());
+    }
+
+    #[test]
+    fn from_bytes_reads_seeks_and_extends_a_copy() {
+        const ASSET: &[u8] = b"line one\nline two\n";
+        let file = MemoryFile::from_bytes("asset.txt", ASSET);
+        let mut file = file.write().unwrap();
+        assert_eq!(file.name(), Path::new("asset.txt"));
+        assert_eq!(file.size().unwrap(), ASSET.len());
+
+        let mut line = String::new();
+        file.read_line(&mut line).unwrap();
+        assert_eq!(line, "line one\n");
+        file.seek(SeekFrom::Start(5)).unwrap();
+        let mut word = [0; 3];
+        file.read_exact(&mut word).unwrap();
+        assert_eq!(&word, b"one");
+
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"line three\n").unwrap();
+        assert_eq!(file.size().unwrap(), ASSET.len() + 11);
+        assert!(file.get_buffer().read().unwrap().ends_with(b"two\nline three\n"));
+    }
+}
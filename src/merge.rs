@@ -0,0 +1,246 @@
+//! Deep merging of one tree into another, e.g. layering a patch directory onto a base, and the
+//! copying behind moves that can't be done as a rename.
+
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+use super::{FilesystemObject, FilesystemObjectRaw, FsError, FsResult, FsKind, create_dir_segments};
+
+/// What [`FilesystemObjectRaw::merge_from`] does with a source file whose path already holds a
+/// file in the target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Replace the target's contents with the source's.
+    Overwrite,
+    /// Keep the target's file.
+    Skip,
+    /// Replace the target's file only if the source's was modified more recently.
+    KeepNewer,
+    /// Fail with [`FsError::AlreadyExists`] before anything is written.
+    Error,
+}
+
+/// What a merge did with each source file. Paths are relative to the merged roots, in the order
+/// the files were visited.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Files that didn't exist in the target and were copied in.
+    pub added: Vec<PathBuf>,
+    /// Files that existed in the target and had their contents replaced.
+    pub overwritten: Vec<PathBuf>,
+    /// Files that existed in the target and were left alone.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Copies a file's full contents over another's, from the start. `to` is emptied and rewound first
+/// unless it's `fresh`, i.e. just created and not on disk yet.
+fn copy_contents(from: &mut dyn FilesystemObjectRaw, to: &mut dyn FilesystemObjectRaw, fresh: bool) -> FsResult {
+    from.seek(SeekFrom::Start(0))?;
+    if !fresh {
+        to.truncate(0)?;
+        to.seek(SeekFrom::Start(0))?;
+    }
+    loop {
+        let chunk = from.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let len = chunk.len();
+        to.write_all(chunk)?;
+        from.consume(len);
+    }
+
+    Ok(to.flush()?)
+}
+
+/// Merges `source`'s children into `target`, recording outcomes under `prefix`. With `apply`
+/// false nothing is written; conflicts are only looked for, so [`MergePolicy::Error`] can fail
+/// before the first write.
+fn merge_dir<T: FilesystemObjectRaw + ?Sized>(
+    target: &mut T, source: &dyn FilesystemObjectRaw, prefix: &Path,
+    policy: MergePolicy, apply: bool, report: &mut MergeReport,
+) -> FsResult {
+    for object in source.get_children()? {
+        let child = object.read().unwrap();
+        let name = child.name().to_string_lossy().to_string();
+        let path = prefix.join(&name);
+        let existing = target.try_get_child(&name)?;
+
+        if child.is_dir() {
+            let dir = match (existing, apply) {
+                (Some(existing), _) => existing,
+                (None, true) => create_dir_segments(target, &[&name])?,
+                // Nothing exists under a directory the target doesn't have yet.
+                (None, false) => continue,
+            };
+            if dir.read().unwrap().is_file() {
+                return Err(FsError::not_a_directory(dir.read().unwrap().get_full_path().to_string_lossy().to_string(), FsKind::File));
+            }
+
+            merge_dir(&mut *dir.write().unwrap(), &*child, &path, policy, apply, report)?;
+            continue;
+        }
+
+        let Some(existing) = existing else {
+            if apply {
+                let file = target.new_file(&name, 0)?;
+                drop(child);
+                copy_contents(&mut *object.write().unwrap(), &mut *file.write().unwrap(), true)?;
+                report.added.push(path);
+            }
+            continue;
+        };
+
+        let mut existing = existing.write().unwrap();
+        if existing.is_dir() {
+            return Err(FsError::not_a_file(existing.get_full_path().to_string_lossy().to_string(), FsKind::Directory));
+        }
+
+        let overwrite = match policy {
+            MergePolicy::Overwrite => true,
+            MergePolicy::Skip => false,
+            MergePolicy::KeepNewer => child.modified()? > existing.modified()?,
+            MergePolicy::Error => return Err(FsError::already_exists(existing.get_full_path().to_string_lossy().to_string())),
+        };
+        if !apply {
+            continue;
+        }
+
+        if overwrite {
+            drop(child);
+            copy_contents(&mut *object.write().unwrap(), &mut *existing, false)?;
+            report.overwritten.push(path);
+        } else {
+            report.skipped.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn merge<T: FilesystemObjectRaw + ?Sized>(target: &mut T, source: &dyn FilesystemObjectRaw, policy: MergePolicy) -> FsResult<MergeReport> {
+    let mut report = MergeReport::default();
+    if policy == MergePolicy::Error {
+        merge_dir(target, source, Path::new(""), policy, false, &mut report)?;
+    }
+    merge_dir(target, source, Path::new(""), policy, true, &mut report)?;
+
+    Ok(report)
+}
+
+/// Moves `source` into `new_dir` by copying it there, a whole tree for directories, and then
+/// deleting the original. For moves between backends, or devices, that can't rename into each
+/// other.
+pub(crate) fn move_by_copy(source: &mut dyn FilesystemObjectRaw, new_dir: &FilesystemObject) -> FsResult {
+    let name = source.name().to_string_lossy().to_string();
+    let (from, to) = (source.get_full_path(), new_dir.read().unwrap().get_full_path().join(&name));
+
+    let deleted = if source.is_dir() {
+        let copy = new_dir.write().unwrap().new_dir(&name)?;
+        merge(&mut *copy.write().unwrap(), source, MergePolicy::Error)?;
+        source.delete_all()
+    } else {
+        let copy = new_dir.write().unwrap().new_file(&name, 0)?;
+        copy_contents(source, &mut *copy.write().unwrap(), true)?;
+        source.delete()
+    };
+
+    deleted.map_err(|error| FsError::MoveIncomplete {
+        from: from.to_string_lossy().to_string(),
+        to: to.to_string_lossy().to_string(),
+        error: Box::new(error),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::PhysicalDirectory;
+    use crate::test_util::TempDir;
+
+    fn set_modified(path: PathBuf, time: SystemTime) {
+        fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    /// Merges a patch tree into a base tree under `policy`. `stale.txt` is newer in the patch and
+    /// `edited.txt` newer in the base. Returns the report, or the error, and the base tree.
+    fn merge_trees(policy: MergePolicy) -> (FsResult<MergeReport>, TempDir) {
+        let (base, patch) = (TempDir::new("merge-base"), TempDir::new("merge-patch"));
+        let (old, new) = (SystemTime::UNIX_EPOCH + Duration::from_secs(1000), SystemTime::now());
+        for (tree, contents) in [(&base, "base"), (&patch, "patch")] {
+            tree.write("stale.txt", contents);
+            tree.write("edited.txt", contents);
+        }
+        base.write("base-only.txt", "base");
+        patch.write("sub/added.txt", "added");
+        set_modified(base.join("stale.txt"), old);
+        set_modified(patch.join("stale.txt"), new);
+        set_modified(base.join("edited.txt"), new);
+        set_modified(patch.join("edited.txt"), old);
+
+        let source = PhysicalDirectory::from_path(patch.path());
+        let target = PhysicalDirectory::from_path(base.path());
+        let report = target.write().unwrap().merge_from(&*source.read().unwrap(), policy);
+
+        (report, base)
+    }
+
+    fn sorted(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn merge_policies_settle_conflicts() {
+        let read = |tree: &TempDir, path: &str| fs::read_to_string(tree.join(path)).unwrap();
+        let added = vec![Path::new("sub").join("added.txt")];
+
+        let (report, base) = merge_trees(MergePolicy::Overwrite);
+        let report = report.unwrap();
+        assert_eq!(report.added, added);
+        assert_eq!(sorted(report.overwritten), [Path::new("edited.txt"), Path::new("stale.txt")]);
+        assert!(report.skipped.is_empty());
+        assert_eq!((read(&base, "stale.txt"), read(&base, "edited.txt")), ("patch".into(), "patch".into()));
+        assert_eq!(read(&base, "sub/added.txt"), "added");
+        assert_eq!(read(&base, "base-only.txt"), "base");
+
+        let (report, base) = merge_trees(MergePolicy::Skip);
+        let report = report.unwrap();
+        assert_eq!(report.added, added);
+        assert_eq!(sorted(report.skipped), [Path::new("edited.txt"), Path::new("stale.txt")]);
+        assert_eq!((read(&base, "stale.txt"), read(&base, "edited.txt")), ("base".into(), "base".into()));
+
+        let (report, base) = merge_trees(MergePolicy::KeepNewer);
+        let report = report.unwrap();
+        assert_eq!(report.overwritten, [Path::new("stale.txt")]);
+        assert_eq!(report.skipped, [Path::new("edited.txt")]);
+        assert_eq!((read(&base, "stale.txt"), read(&base, "edited.txt")), ("patch".into(), "base".into()));
+
+        // Conflicts are found before anything is written.
+        let (report, base) = merge_trees(MergePolicy::Error);
+        assert!(matches!(report, Err(FsError::AlreadyExists(..))), "{report:?}");
+        assert!(!base.join("sub").exists());
+        assert_eq!(read(&base, "stale.txt"), "base");
+    }
+
+    #[test]
+    fn moves_whose_source_cant_be_deleted_are_incomplete() {
+        let tmp = TempDir::new("move-incomplete");
+        let dest: FilesystemObject = PhysicalDirectory::from_path(tmp.path());
+        // Memory files can be read from but not deleted.
+        let source = crate::MemoryFile::from_bytes("kept.txt", b"both");
+
+        let result = move_by_copy(&mut *source.write().unwrap(), &dest);
+        let Err(FsError::MoveIncomplete { from, to, error, .. }) = result else {
+            panic!("expected MoveIncomplete, got {result:?}");
+        };
+        assert_eq!((from.as_str(), Path::new(&to)), ("kept.txt", tmp.join("kept.txt").as_path()));
+        assert!(matches!(*error, FsError::Unsupported { .. }), "{error:?}");
+        assert_eq!(fs::read_to_string(tmp.join("kept.txt")).unwrap(), "both");
+        assert_eq!(&*source.read().unwrap().get_buffer().read().unwrap(), b"both");
+    }
+}
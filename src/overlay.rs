@@ -0,0 +1,1091 @@
+//! The [overlay](`self`) module provides [`OverlayDirectory`], a union mount merging several
+//! directories into one view, like OverlayFS.
+//!
+//! Layers are ordered topmost first. Lookups and listings merge the layers by name, with upper
+//! layers shadowing lower ones: a file hides everything below it, while directories present in
+//! several layers are merged in turn. Mutations go to the topmost writable layer; a directory that
+//! only exists in lower layers is first created there ("copied up"). Files are handed out as
+//! [`OverlayFile`]s, which copy a file from a lower layer up on its first write.
+//! Deleting an entry removes it from the writable layer and records a whiteout that hides it from
+//! the merged view. Whiteouts live in the overlay itself, so they last as long as it does and
+//! aren't written to any layer.
+//!
+//! Layers whose entries have `/`-separated names rather than directories of their own, like zip
+//! archives, are merged as if each name's leading segments were directories.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+use std::path::{PathBuf, Path};
+use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind,
+};
+
+pub struct OverlayDirectory {
+    name: PathBuf,
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+
+    /// The root of every layer, topmost first.
+    roots: Arc<[FilesystemObject]>,
+    /// Path of this directory relative to the roots.
+    rel: PathBuf,
+    /// This directory in each layer, aligned with `roots`. `None` where the layer doesn't have it
+    /// or it's shadowed by a file further up.
+    layers: Vec<Option<Layer>>,
+
+    whiteouts: RwLock<HashSet<String>>,
+    /// Merged subdirectories, kept so their whiteouts and copied-up layers persist.
+    children: RwLock<HashMap<String, Arc<RwLock<OverlayDirectory>>>>,
+    readonly: bool,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+/// A directory within one layer.
+#[derive(Clone)]
+struct Layer {
+    /// The directory holding the entries.
+    dir: FilesystemObject,
+    /// The `/`-terminated name prefix shared by the entries, for a directory only implied by the
+    /// names in `dir`. Empty for `dir` itself.
+    prefix: String,
+}
+
+/// What a name resolves to within one layer.
+enum LayerEntry {
+    File(FilesystemObject),
+    Dir(Layer),
+}
+
+/// A file of the overlay. One from a layer below the writable one is read from that layer until
+/// the first write or truncation, which copies the file up to the writable layer and carries on
+/// there, keeping the position. Writes check whether the directory holding the file is read-only,
+/// so it mustn't be locked by the writing thread.
+pub struct OverlayFile {
+    inner: FilesystemObject,
+    name: PathBuf,
+    path: PathBuf,
+    parent: Arc<RwLock<OverlayDirectory>>,
+    /// Whether `inner` is the copy in the writable layer.
+    copied_up: bool,
+    /// Copy of the inner file's buffered bytes, for `BufRead`.
+    buffer: Vec<u8>,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+impl OverlayDirectory {
+    /// Creates an overlay of the given directories, topmost first. The overlay takes its name and
+    /// path from the topmost layer.
+    pub fn new(layers: Vec<FilesystemObject>) -> FsResult<Arc<RwLock<Self>>> {
+        let top = layers.first()
+            .ok_or_else(|| FsError::generic("an overlay needs at least one layer".to_string()))?;
+        let (name, path) = {
+            let top = top.read().unwrap();
+            (top.name().to_path_buf(), top.get_full_path())
+        };
+
+        let roots: Arc<[FilesystemObject]> = layers.into();
+        let layers = roots.iter().cloned().map(|root| Some(Layer::new(root))).collect();
+
+        Ok(Self::new_node(name, path, None, roots, PathBuf::new(), layers))
+    }
+
+    fn new_node(
+        name: PathBuf, path: PathBuf, parent: Option<FilesystemObject>,
+        roots: Arc<[FilesystemObject]>, rel: PathBuf, layers: Vec<Option<Layer>>,
+    ) -> Arc<RwLock<Self>> {
+        let new = Self{
+            name,
+            path,
+            parent,
+
+            roots,
+            rel,
+            layers,
+
+            whiteouts: RwLock::new(HashSet::new()),
+            children: RwLock::new(HashMap::new()),
+            readonly: false,
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    fn get_node(&self) -> Arc<RwLock<Self>> {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    /// Deletes `name` from the writable layer, along with everything under it, and hides it from
+    /// the merged view, so copies in lower layers don't show through. Creating `name` again
+    /// brings it back.
+    pub fn delete_child(&mut self, name: &str) -> FsResult {
+        if self.readonly() {
+            return Err(FsError::read_only(self.display_path()));
+        }
+        if !self.has_child(name)? {
+            return Err(FsError::file_not_present(self.display_path(), name.to_string()));
+        }
+
+        let index = self.upper_index()?;
+        let upper = self.layers[index].as_ref()
+            .map(|layer| layer.child(name))
+            .transpose()?
+            .flatten();
+        match upper {
+            Some(LayerEntry::File(file)) => file.write().unwrap().delete()?,
+            Some(LayerEntry::Dir(dir)) if dir.prefix.is_empty() => dir.dir.write().unwrap().delete_all()?,
+            _ => (),
+        }
+
+        self.whiteouts.write().unwrap().insert(name.to_string());
+        self.children.write().unwrap().remove(name);
+
+        Ok(())
+    }
+
+    /// Whether `name` is hidden by a whiteout.
+    pub fn is_whited_out(&self, name: &str) -> bool {
+        self.whiteouts.read().unwrap().contains(name)
+    }
+
+    /// Resolves `name` across the layers: the topmost file, wrapped to be copied up when written if
+    /// it's below the writable layer, or a merged directory.
+    fn resolve(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        if self.is_whited_out(name) {
+            return Ok(None);
+        }
+        if let Some(child) = self.children.read().unwrap().get(name) {
+            return Ok(Some(child.clone()));
+        }
+
+        let mut dirs = vec![None; self.layers.len()];
+        let mut found = false;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let Some(layer) = layer else { continue };
+            let Some(entry) = layer.child(name)? else { continue };
+
+            match (found, entry) {
+                (false, LayerEntry::File(child)) => {
+                    let copied_up = self.writable_index() == Some(i);
+                    return Ok(Some(OverlayFile::new(child, PathBuf::from(name), self.path.join(name), self.get_node(), copied_up)));
+                },
+                (true, LayerEntry::File(_)) => break,
+                (_, LayerEntry::Dir(dir)) => {
+                    dirs[i] = Some(dir);
+                    found = true;
+                },
+            }
+        }
+
+        if !found {
+            return Ok(None);
+        }
+
+        let dir = Self::new_node(
+            PathBuf::from(name), self.path.join(name), Some(self.get()),
+            self.roots.clone(), self.rel.join(name), dirs,
+        );
+        dir.write().unwrap().readonly = self.readonly;
+        self.children.write().unwrap().insert(name.to_string(), dir.clone());
+
+        Ok(Some(dir))
+    }
+
+    /// This directory in the topmost layer that has it. A directory only implied by entry names is
+    /// stood in for by the directory holding the entries.
+    fn top_layer(&self) -> FsResult<FilesystemObject> {
+        self.layers.iter().flatten().next().map(|layer| layer.dir.clone()).ok_or_else(|| {
+            let parent = self.path.parent().unwrap_or(Path::new("")).to_string_lossy().to_string();
+            FsError::file_not_present(parent, self.name.to_string_lossy().to_string())
+        })
+    }
+
+    /// Index of the topmost writable layer, even while the overlay itself is read-only.
+    fn writable_index(&self) -> Option<usize> {
+        self.roots.iter().position(|root| !root.read().unwrap().readonly())
+    }
+
+    /// Index of the topmost writable layer.
+    fn upper_index(&self) -> FsResult<usize> {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        self.writable_index().ok_or_else(|| FsError::read_only(self.display_path()))
+    }
+
+    /// The root of the topmost writable layer.
+    fn upper_root(&self) -> FsResult<FilesystemObject> {
+        Ok(self.roots[self.upper_index()?].clone())
+    }
+
+    /// Returns this directory in the topmost writable layer, creating it there if it only exists
+    /// further down.
+    fn upper(&mut self) -> FsResult<FilesystemObject> {
+        let index = self.upper_index()?;
+        if let Some(layer) = &self.layers[index] && layer.prefix.is_empty() {
+            return Ok(layer.dir.clone());
+        }
+
+        let rel = self.rel.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+        let dir = self.roots[index].write().unwrap().new_dir_all(&rel)?;
+        self.layers[index] = Some(Layer::new(dir.clone()));
+
+        Ok(dir)
+    }
+
+    /// Makes a freshly created child visible, dropping any whiteout and stale merge for it.
+    fn revive(&self, name: &str) {
+        self.whiteouts.write().unwrap().remove(name);
+        self.children.write().unwrap().remove(name);
+    }
+
+    /// Wraps `file`, just created in the writable layer.
+    fn wrap_created(&self, file: FilesystemObject) -> FilesystemObject {
+        let name = file.read().unwrap().name().to_path_buf();
+        let path = self.path.join(&name);
+        OverlayFile::new(file, name, path, self.get_node(), true)
+    }
+}
+
+impl Layer {
+    fn new(dir: FilesystemObject) -> Self {
+        Self{ dir, prefix: String::new() }
+    }
+
+    /// Looks `name` up in this directory. With no entry by that name, entries named with it as
+    /// their leading segment imply a directory.
+    fn child(&self, name: &str) -> FsResult<Option<LayerEntry>> {
+        let full = format!("{}{name}", self.prefix);
+        let dir = self.dir.read().unwrap();
+        if let Some(child) = dir.try_get_child(&full)? {
+            let is_dir = child.read().unwrap().is_dir();
+            return Ok(Some(match is_dir {
+                true => LayerEntry::Dir(Layer::new(child)),
+                false => LayerEntry::File(child),
+            }));
+        }
+
+        let prefix = format!("{full}/");
+        if !dir.list()?.iter().any(|entry| entry.name.starts_with(&prefix)) {
+            return Ok(None);
+        }
+
+        Ok(Some(LayerEntry::Dir(Layer{ dir: self.dir.clone(), prefix })))
+    }
+
+    /// The names of this directory's children, including the directories implied by entry names.
+    fn names(&self) -> FsResult<Vec<String>> {
+        let entries = self.dir.read().unwrap().list()?;
+
+        Ok(entries.iter()
+            .filter_map(|entry| entry.name.strip_prefix(&self.prefix))
+            .filter_map(|rest| rest.split('/').next())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+}
+
+impl OverlayFile {
+    fn new(
+        inner: FilesystemObject, name: PathBuf, path: PathBuf, parent: Arc<RwLock<OverlayDirectory>>, copied_up: bool,
+    ) -> Arc<RwLock<Self>> {
+        let new = Self{
+            inner,
+            name,
+            path,
+            parent,
+            copied_up,
+            buffer: Vec::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Whether the file has been copied up to the writable layer yet.
+    pub fn is_copied_up(&self) -> bool {
+        self.copied_up
+    }
+
+    fn check_writable(&self) -> FsResult {
+        if self.readonly() {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        Ok(())
+    }
+
+    /// Copies the file up to the writable layer unless it's there already, moving this handle
+    /// over to the copy at the same position. A copy made through another handle is reused.
+    fn copy_up(&mut self) -> FsResult {
+        self.check_writable()?;
+        if self.copied_up {
+            return Ok(());
+        }
+
+        let upper = self.parent.write().unwrap().upper()?;
+        let name = self.name.to_string_lossy().to_string();
+        let existing = upper.read().unwrap().try_get_child(&name)?;
+        let copy = match existing {
+            Some(copy) => copy,
+            None => {
+                let contents = {
+                    let inner = self.inner.read().unwrap();
+                    inner.read_at(0, inner.size()?)?
+                };
+                let copy = upper.write().unwrap().new_file(&name, 0)?;
+                {
+                    let mut copy = copy.write().unwrap();
+                    copy.write_all(&contents)?;
+                    copy.flush()?;
+                }
+                copy
+            },
+        };
+
+        let position = self.inner.write().unwrap().stream_position()?;
+        copy.write().unwrap().seek(SeekFrom::Start(position))?;
+        self.inner.write().unwrap().close();
+        self.inner = copy;
+        self.copied_up = true;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for OverlayDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OverlayDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("layers", &self.layers.iter().filter(|layer| layer.is_some()).count())
+            .field("whiteouts", &self.whiteouts.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl fmt::Debug for OverlayFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OverlayFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("copied_up", &self.copied_up)
+            .finish()
+    }
+}
+
+impl Read for OverlayDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for OverlayDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for OverlayDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for OverlayDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for OverlayDirectory {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn exists(&self) -> bool {
+        self.layers.iter().flatten().any(|layer| layer.dir.read().unwrap().exists())
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    /// The time recorded by the topmost layer that has this directory.
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.top_layer()?.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// The identity of the topmost layer that has this directory.
+    fn identity(&self) -> FsResult<ObjectId> {
+        self.top_layer()?.read().unwrap().identity()
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly || self.roots.iter().all(|root| root.read().unwrap().readonly())
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.readonly = readonly;
+        for child in self.children.read().unwrap().values() {
+            child.write().unwrap().set_readonly(readonly)?;
+        }
+
+        Ok(())
+    }
+
+    /// Timestamps and permissions are whatever the topmost layer keeps.
+    fn capabilities(&self) -> Capabilities {
+        let top = self.roots[0].read().unwrap().capabilities();
+        let writable = !self.readonly();
+        Capabilities{
+            writable,
+            can_create: writable,
+            can_delete: writable,
+            can_rename: writable,
+            has_timestamps: top.has_timestamps,
+            has_permissions: top.has_permissions,
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("overlay".to_string())
+    }
+
+    /// The space of the topmost writable layer, where writes go.
+    fn free_space(&self) -> FsResult<u64> {
+        self.upper_root()?.read().unwrap().free_space()
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        self.upper_root()?.read().unwrap().total_space()
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("overlay", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        let mut names = Vec::new();
+        let mut seen = HashSet::new();
+        for layer in self.layers.iter().flatten() {
+            for name in layer.names()? {
+                if seen.insert(name.clone()) {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort_unstable();
+
+        let mut children = Vec::with_capacity(names.len());
+        for name in names {
+            children.extend(self.resolve(&name)?);
+        }
+
+        Ok(children)
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        self.resolve(name)?
+            .ok_or_else(|| FsError::file_not_present(self.display_path(), name.to_string()))
+    }
+
+    fn try_get_child(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        self.resolve(name)
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        Ok(self.resolve(name)?.is_some())
+    }
+
+    /// Renames an entry of the writable layer. Entries that only exist in lower layers can't be
+    /// renamed.
+    fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult {
+        if !self.has_child(name)? {
+            return Err(FsError::file_not_present(self.display_path(), name.to_string()));
+        }
+
+        let upper = self.upper()?;
+        if !upper.read().unwrap().has_child(name)? {
+            return Err(FsError::unsupported("overlay", "child_rename of lower layer entries"));
+        }
+        upper.write().unwrap().child_rename(name, new_name)?;
+
+        self.revive(new_name);
+        self.children.write().unwrap().remove(name);
+        // A lower layer may still provide the old name.
+        if self.resolve(name)?.is_some() {
+            self.whiteouts.write().unwrap().insert(name.to_string());
+            self.children.write().unwrap().remove(name);
+        }
+
+        Ok(())
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::unsupported("overlay", "rename"))
+    }
+
+    fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        let upper = self.upper()?;
+        let file = upper.write().unwrap().new_file(name, buffer_size)?;
+        self.revive(name);
+
+        Ok(self.wrap_created(file))
+    }
+
+    fn new_dir(&mut self, name: &str) -> FsResult<FilesystemObject> {
+        let upper = self.upper()?;
+        upper.write().unwrap().new_dir(name)?;
+        self.revive(name);
+
+        self.get_child(name)
+    }
+
+    fn create_temp_file(&mut self, prefix: &str, ext: &str) -> FsResult<FilesystemObject> {
+        let upper = self.upper()?;
+        let file = upper.write().unwrap().create_temp_file(prefix, ext)?;
+        self.revive(&file.read().unwrap().name().to_string_lossy());
+
+        Ok(self.wrap_created(file))
+    }
+
+    fn create_temp_dir(&mut self, prefix: &str) -> FsResult<FilesystemObject> {
+        let upper = self.upper()?;
+        let name = upper.write().unwrap().create_temp_dir(prefix)?.read().unwrap().name().to_string_lossy().to_string();
+        self.revive(&name);
+
+        self.get_child(&name)
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        for layer in self.layers.iter().flatten() {
+            layer.dir.write().unwrap().scan()?;
+        }
+        self.children.write().unwrap().clear();
+
+        Ok(())
+    }
+
+    /// The merged children are rebuilt on demand, so only the layers' caches can be stale.
+    fn validate_cache(&self) -> FsResult<bool> {
+        for layer in self.layers.iter().flatten() {
+            if !layer.dir.read().unwrap().validate_cache()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn repair_cache(&mut self) -> FsResult {
+        for layer in self.layers.iter().flatten() {
+            layer.dir.write().unwrap().repair_cache()?;
+        }
+        self.children.write().unwrap().clear();
+
+        Ok(())
+    }
+
+    /// Use [`OverlayDirectory::delete_child`] on the parent instead, which hides the entry in
+    /// every layer.
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::unsupported("overlay", "delete"))
+    }
+}
+
+impl Read for OverlayFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.write().unwrap().read(buf)
+    }
+}
+
+impl BufRead for OverlayFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        // The inner file's buffer can't be lent out past its lock guard, so it's copied.
+        let mut inner = self.inner.write().unwrap();
+        let available = inner.fill_buf()?;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(available);
+
+        Ok(&self.buffer)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.write().unwrap().consume(amt);
+    }
+}
+
+impl Seek for OverlayFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.inner.write().unwrap().seek(pos)
+    }
+}
+
+impl Write for OverlayFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.check_writable()?;
+        self.copy_up()?;
+        self.inner.write().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().flush()
+    }
+}
+
+impl FilesystemObjectRaw for OverlayFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.read().unwrap().exists()
+    }
+
+    fn revalidate(&self) -> FsResult<FsKind> {
+        self.inner.read().unwrap().revalidate()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        self.inner.read().unwrap().size()
+    }
+
+    fn truncate(&mut self, len: usize) -> FsResult {
+        self.check_writable()?;
+        self.copy_up()?;
+        self.inner.write().unwrap().truncate(len)
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().position()
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        self.inner.read().unwrap().read_at(offset, len)
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.inner.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        self.inner.read().unwrap().identity()
+    }
+
+    /// Writable whenever the overlay is, since writes are copied up.
+    fn readonly(&self) -> bool {
+        self.parent.read().unwrap().readonly()
+    }
+
+    fn set_readonly(&mut self, _readonly: bool) -> FsResult {
+        Err(FsError::unsupported("overlay", "set_readonly on files"))
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.parent.read().unwrap().capabilities()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        self.inner.read().unwrap().fs_type()
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().free_space()
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().total_space()
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("overlay", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().open()
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.read().unwrap().is_open()
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_all()
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_data()
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let inner = self.inner.read().unwrap().duplicate()?;
+        Ok(OverlayFile::new(inner, self.name.clone(), self.path.clone(), self.parent.clone(), self.copied_up))
+    }
+
+    fn close(&mut self) {
+        self.inner.write().unwrap().close();
+    }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(FsError::unsupported("overlay", "rename"))
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn set_buffer_size(&mut self, size: usize) {
+        self.inner.write().unwrap().set_buffer_size(size);
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Use [`OverlayDirectory::delete_child`] on the parent instead, which hides the file in every
+    /// layer.
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::unsupported("overlay", "delete"))
+    }
+
+    fn invalidate(&mut self) {
+        self.inner.write().unwrap().invalidate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::PhysicalDirectory;
+    use crate::test_util::TempDir;
+
+    /// An overlay of a writable `upper` over a read-only `lower`, both under `tmp`.
+    fn overlay(tmp: &TempDir) -> Arc<RwLock<OverlayDirectory>> {
+        fs::create_dir_all(tmp.join("upper")).unwrap();
+        let upper = PhysicalDirectory::from_path(tmp.join("upper"));
+        let lower = PhysicalDirectory::from_path(tmp.join("lower"));
+        lower.write().unwrap().set_readonly(true).unwrap();
+
+        OverlayDirectory::new(vec![upper, lower]).unwrap()
+    }
+
+    fn names(dir: &FilesystemObject) -> Vec<String> {
+        dir.read().unwrap().get_children().unwrap()
+            .iter()
+            .map(|child| child.read().unwrap().name().to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn contents(file: &FilesystemObject) -> String {
+        let file = file.read().unwrap();
+        String::from_utf8(file.read_at(0, file.size().unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn upper_layers_shadow_lower_ones() {
+        let tmp = TempDir::new("overlay-shadow");
+        tmp.write("lower/both.txt", "lower");
+        tmp.write("lower/only-lower.txt", "lower");
+        tmp.write("lower/dir/from-lower.txt", "lower");
+        tmp.write("upper/both.txt", "upper");
+        tmp.write("upper/dir/from-upper.txt", "upper");
+        let root: FilesystemObject = overlay(&tmp);
+
+        assert_eq!(names(&root), ["both.txt", "dir", "only-lower.txt"]);
+        assert_eq!(contents(&root.read().unwrap().get_child("both.txt").unwrap()), "upper");
+        assert_eq!(contents(&root.read().unwrap().get_child("only-lower.txt").unwrap()), "lower");
+
+        let dir = root.read().unwrap().get_child("dir").unwrap();
+        assert_eq!(names(&dir), ["from-lower.txt", "from-upper.txt"]);
+    }
+
+    #[test]
+    fn read_only_overlays_keep_the_top_layers_capabilities() {
+        let tmp = TempDir::new("overlay-capabilities");
+        tmp.write("lower/a.txt", "lower");
+        let root = overlay(&tmp);
+        let file = root.read().unwrap().get_child("a.txt").unwrap();
+        let writable = Capabilities{
+            writable: true,
+            can_create: true,
+            can_delete: true,
+            can_rename: true,
+            has_timestamps: true,
+            has_permissions: true,
+        };
+        assert_eq!(root.read().unwrap().capabilities(), writable);
+        assert_eq!(file.read().unwrap().capabilities(), writable);
+
+        root.write().unwrap().set_readonly(true).unwrap();
+        let read_only = Capabilities{ has_timestamps: true, has_permissions: true, ..Capabilities::default() };
+        assert_eq!(root.read().unwrap().capabilities(), read_only);
+        assert_eq!(file.read().unwrap().capabilities(), read_only);
+
+        #[cfg(feature = "zip")]
+        {
+            let path = tmp.join("top.zip");
+            let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+            writer.start_file("b.txt", zip::write::SimpleFileOptions::default()).unwrap();
+            writer.finish().unwrap();
+            let top = crate::ZipDirectory::new(crate::PhysicalFile::from_path(&path, 0)).unwrap();
+            let root = OverlayDirectory::new(vec![top, PhysicalDirectory::from_path(tmp.join("lower"))]).unwrap();
+            root.write().unwrap().set_readonly(true).unwrap();
+            // Zip archives keep no permissions, so neither does an overlay topped by one.
+            assert_eq!(root.read().unwrap().capabilities(), Capabilities{ has_timestamps: true, ..Capabilities::default() });
+        }
+    }
+
+    #[test]
+    fn deleting_whites_out_and_recreating_revives() {
+        let tmp = TempDir::new("overlay-whiteout");
+        tmp.write("lower/lower.txt", "lower");
+        tmp.write("lower/both.txt", "lower");
+        tmp.write("upper/both.txt", "upper");
+        tmp.write("upper/new.txt", "upper");
+        let overlay = overlay(&tmp);
+        let mut root = overlay.write().unwrap();
+
+        root.delete_child("lower.txt").unwrap();
+        assert!(root.is_whited_out("lower.txt"));
+        assert!(matches!(root.get_child("lower.txt"), Err(FsError::FileNotPresent(..))));
+        assert!(tmp.join("lower").join("lower.txt").exists());
+
+        root.delete_child("both.txt").unwrap();
+        assert!(!tmp.join("upper").join("both.txt").exists());
+        assert!(!root.has_child("both.txt").unwrap());
+
+        root.delete_child("new.txt").unwrap();
+        assert!(!tmp.join("upper").join("new.txt").exists());
+        let new = root.new_file("new.txt", 0).unwrap();
+        // Writes check whether the overlay is read-only, so it can't be locked meanwhile.
+        drop(root);
+        new.write().unwrap().write_all(b"again").unwrap();
+        new.write().unwrap().flush().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("new.txt")).unwrap(), "again");
+        let mut root = overlay.write().unwrap();
+
+        root.new_file("lower.txt", 0).unwrap();
+        assert!(!root.is_whited_out("lower.txt"));
+        assert!(matches!(root.delete_child("missing.txt"), Err(FsError::FileNotPresent(..))));
+        drop(root);
+        assert_eq!(names(&(overlay as FilesystemObject)), ["lower.txt", "new.txt"]);
+    }
+
+    #[test]
+    fn writes_to_lower_files_are_copied_up() {
+        let tmp = TempDir::new("overlay-copy-up");
+        tmp.write("lower/notes.txt", "0123456789");
+        tmp.write("lower/deep/nested.txt", "nested");
+        tmp.write("lower/locked.txt", "locked");
+        tmp.write("upper/own.txt", "own");
+        let root = overlay(&tmp);
+
+        let file = root.read().unwrap().get_child("notes.txt").unwrap();
+        {
+            let mut file = file.write().unwrap();
+            let mut start = [0; 4];
+            file.read_exact(&mut start).unwrap();
+            assert_eq!(&start, b"0123");
+            assert!(!file.readonly());
+
+            file.write_all(b"abc").unwrap();
+            file.flush().unwrap();
+            let mut rest = String::new();
+            file.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "789");
+        }
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("notes.txt")).unwrap(), "0123abc789");
+        assert_eq!(fs::read_to_string(tmp.join("lower").join("notes.txt")).unwrap(), "0123456789");
+        assert_eq!(contents(&root.read().unwrap().get_child("notes.txt").unwrap()), "0123abc789");
+
+        let deep = root.read().unwrap().get_child("deep").unwrap();
+        let nested = deep.read().unwrap().get_child("nested.txt").unwrap();
+        nested.write().unwrap().truncate(3).unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("deep").join("nested.txt")).unwrap(), "nes");
+        assert_eq!(fs::read_to_string(tmp.join("lower").join("deep").join("nested.txt")).unwrap(), "nested");
+
+        let own = root.read().unwrap().get_child("own.txt").unwrap();
+        own.write().unwrap().write_all(b"n").unwrap();
+        own.write().unwrap().flush().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("own.txt")).unwrap(), "nwn");
+
+        root.write().unwrap().set_readonly(true).unwrap();
+        let locked = root.read().unwrap().get_child("locked.txt").unwrap();
+        assert!(locked.write().unwrap().write_all(b"no").is_err());
+        assert!(!tmp.join("upper").join("locked.txt").exists());
+        assert!(matches!(own.write().unwrap().truncate(0), Err(FsError::ReadOnly(..))));
+        let own = root.read().unwrap().get_child("own.txt").unwrap();
+        assert!(own.write().unwrap().write_all(b"no").is_err());
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("own.txt")).unwrap(), "nwn");
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn zip_layers_merge_by_entry_names() {
+        let tmp = TempDir::new("overlay-zip");
+        tmp.write("upper/docs/a.txt", "upper");
+        let path = tmp.join("lower.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        for (name, contents) in [("top.txt", "top"), ("docs/b.txt", "0123"), ("docs/deep/c.txt", "deep")] {
+            writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let upper = PhysicalDirectory::from_path(tmp.join("upper"));
+        let lower = crate::ZipDirectory::new(crate::PhysicalFile::from_path(&path, 0)).unwrap();
+        lower.write().unwrap().set_readonly(true).unwrap();
+        let root: FilesystemObject = OverlayDirectory::new(vec![upper, lower]).unwrap();
+
+        assert_eq!(names(&root), ["docs", "top.txt"]);
+        let docs = root.read().unwrap().get_child("docs").unwrap();
+        assert_eq!(names(&docs), ["a.txt", "b.txt", "deep"]);
+        let deep = docs.read().unwrap().get_child("deep").unwrap();
+        assert_eq!(names(&deep), ["c.txt"]);
+        assert_eq!(contents(&deep.read().unwrap().get_child("c.txt").unwrap()), "deep");
+
+        let file = docs.read().unwrap().get_child("b.txt").unwrap();
+        assert_eq!(file.read().unwrap().name(), Path::new("b.txt"));
+        file.write().unwrap().write_all(b"ab").unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("upper").join("docs").join("b.txt")).unwrap(), "ab23");
+        let reopened = crate::ZipDirectory::new(crate::PhysicalFile::from_path(&path, 0)).unwrap();
+        assert_eq!(contents(&reopened.read().unwrap().get_child("docs/b.txt").unwrap()), "0123");
+    }
+}
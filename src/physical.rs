@@ -1,47 +1,261 @@
-use std::io::{BufRead, Write, Read, Seek};
-use std::cell::{OnceCell, RefCell};
-use std::sync::{RwLock, Weak, Arc};
-use std::path::{PathBuf, Path};
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
+#[cfg(any(feature="watch", feature="mmap"))]
+use std::sync::Mutex;
+#[cfg(feature="watch")]
+use std::sync::mpsc::Receiver;
+use std::path::{Component, PathBuf, Path};
 use std::collections::HashMap;
+use std::time::SystemTime;
 use std::fs;
+use std::fmt;
 
 use super::{
-    FilesystemObject, Directory, File,
-    FsError, Result,
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind, DirEntry,
+    error::read_only_io,
+    path_segments, check_segment, create_dir_segments, create_temp_child, create_temp_sibling,
+    merge::move_by_copy,
+    text::{TextNormalizer, TextPolicy},
+    sys::{self, AlignedBuffer, DIRECT_IO_ALIGNMENT},
+    retry::RetryPolicy,
 };
+#[cfg(feature="watch")]
+use super::watch::{self, FsEvent};
+
+/// Permission bits new files are created with unless set otherwise, less the umask, as `touch` does.
+const DEFAULT_FILE_MODE: u32 = 0o666;
+/// Permission bits new directories are created with unless set otherwise, less the umask, as
+/// `mkdir` does.
+const DEFAULT_DIR_MODE: u32 = 0o777;
+
+/// Pending writes spill to disk past this many bytes when the buffer size is 0 (whole file).
+const WRITE_HIGH_WATER: usize = 8 * 1024 * 1024;
+
+/// One window of a file mapped into memory by [`PhysicalFile::mmap_windows`]. Derefs to the
+/// mapped bytes; the mapping is released when the window is dropped.
+#[cfg(feature="mmap")]
+pub struct MappedWindow {
+    offset: u64,
+    map: memmap2::Mmap,
+}
+
+#[cfg(feature="mmap")]
+impl MappedWindow {
+    /// Where in the file the window starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+#[cfg(feature="mmap")]
+impl std::ops::Deref for MappedWindow {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+/// Maps the whole file at `path` read-only, through a handle of its own.
+#[cfg(feature="mmap")]
+fn map_file(path: &Path) -> IoResult<memmap2::Mmap> {
+    let file = fs::File::open(path)?;
+    // Safety: see `PhysicalFile::mmap`, the caller promises not to truncate the file while mapped.
+    unsafe { memmap2::Mmap::map(&file) }
+}
+
+/// How a [`PhysicalFile`]'s handle is opened.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Read only.
+    #[default]
+    Read,
+    /// Write only, creating the file or truncating it.
+    Write,
+    /// Write only at the end of the file, creating it if needed.
+    Append,
+    /// Read and write, creating the file if needed.
+    ReadWrite,
+}
+
+impl OpenMode {
+    pub(crate) fn options(self) -> fs::OpenOptions {
+        let mut options = fs::OpenOptions::new();
+        match self {
+            OpenMode::Read => options.read(true),
+            OpenMode::Write => options.write(true).create(true).truncate(true),
+            OpenMode::Append => options.append(true).create(true),
+            OpenMode::ReadWrite => options.read(true).write(true).create(true).truncate(false),
+        };
+
+        options
+    }
+}
+
+/// What kind of link [`PhysicalDirectory::link_child`] creates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    /// Another name for the same file, which must be on the same device.
+    Hard,
+    /// A link storing the target's absolute path, followed when opened.
+    Symbolic,
+}
+
+/// How a [`PhysicalFile`] is about to be read, for [`PhysicalFile::advise`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessHint {
+    /// Read from start to end, so reading further ahead pays off.
+    Sequential,
+    /// Read in no particular order, so reading ahead is wasted.
+    Random,
+    /// Read soon, so it's worth loading into the page cache now.
+    WillNeed,
+    /// Not read again soon, so its cached pages can go.
+    DontNeed,
+}
+
+/// An advisory lock on a [`PhysicalFile`], from [`PhysicalFile::lock_exclusive`] or
+/// [`PhysicalFile::lock_shared`]. Dropping it releases the lock.
+#[derive(Debug)]
+pub struct FileLock {
+    /// A duplicate of the locked handle, which shares its lock.
+    file: fs::File,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+/// Checks what's at `path` now against the `expected` kind of the object cached for it.
+fn revalidate_kind(path: &Path, expected: FsKind) -> FsResult<FsKind> {
+    let display = || path.to_string_lossy().to_string();
+    let metadata = match fs::metadata(path) {
+        Err(error) if error.kind() == IoErrorKind::NotFound => {
+            let parent = path.parent().unwrap_or(Path::new("")).to_string_lossy().to_string();
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            return Err(FsError::file_not_present(parent, name));
+        },
+        metadata => metadata?,
+    };
+
+    let actual = if metadata.is_dir() { FsKind::Directory } else { FsKind::File };
+    match (expected, actual) {
+        (FsKind::File, FsKind::Directory) => Err(FsError::not_a_file(display(), actual)),
+        (FsKind::Directory, FsKind::File) => Err(FsError::not_a_directory(display(), actual)),
+        _ => Ok(actual),
+    }
+}
 
 pub struct PhysicalDirectory {
     name: PathBuf,
-    parent: Option<Arc<RwLock<dyn Directory>>>,
+    /// Full path, fixed when the object is created so it can be read without locking any
+    /// ancestors.
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
 
-    children: RefCell<HashMap<String, FilesystemObject>>,
-    scanned: RefCell<bool>,
+    children: RwLock<HashMap<String, FilesystemObject>>,
+    scanned: AtomicBool,
+    readonly: bool,
+    /// Whether `new_file` and `new_dir` may replace an existing child.
+    overwrite: bool,
+    /// Whether `new_dir` creates this directory and its missing ancestors on disk as well.
+    create_parents: bool,
+    /// Whether single-child lookups stat just that entry instead of scanning the directory.
+    lazy: bool,
+    /// Whether single-child lookups fall back to matching names regardless of case.
+    case_insensitive: bool,
+    /// How listing the directory is retried, passed on to children.
+    retry: RetryPolicy,
+    /// Permission bits for files and directories created in here, passed on to children.
+    file_mode: u32,
+    dir_mode: u32,
+    /// The directory's own handle, opened on first use by [`Self::open_child_at`].
+    #[cfg(unix)]
+    dir_handle: OnceLock<fs::File>,
+    /// Watcher started by [`Self::watch`], kept alive for as long as the directory is.
+    #[cfg(feature="watch")]
+    watcher: Mutex<Option<notify::RecommendedWatcher>>,
 
-    handle: OnceCell<Weak<RwLock<Self>>>,
+    handle: OnceLock<Weak<RwLock<Self>>>,
 }
 
 pub struct PhysicalFile {
     name: PathBuf,
-    parent: Arc<RwLock<dyn Directory>>,
+    path: PathBuf,
+    parent: FilesystemObject,
 
     file: Option<fs::File>,
     buffer: Vec<u8>,
+    buffer_size: usize,
     buf_filled: usize,
     cursor: usize,
 
-    handle: OnceCell<Weak<RwLock<Self>>>,
+    /// Whether `buffer` currently holds pending writes (starting at `write_pos`) rather than read
+    /// data.
+    dirty: bool,
+    write_pos: u64,
+    text: TextNormalizer,
+    atomic: bool,
+    /// Whether dropping the object closes it, writing out pending bytes.
+    flush_on_drop: bool,
+    /// How many pending bytes trigger writing them out, overriding the default.
+    flush_threshold: Option<usize>,
+    /// How opening, reading and flushing are retried.
+    retry: RetryPolicy,
+    open_mode: OpenMode,
+    /// Staging buffer for direct I/O, present while direct I/O is enabled.
+    direct: Option<AlignedBuffer>,
+    readonly: bool,
+    /// Permission bits the file is created with, if opening it creates it.
+    mode: u32,
+    /// Set once the file is deleted through this object or one of its ancestors, after which the
+    /// object can't be used again.
+    deleted: bool,
+    /// Set once [`is_open`](FilesystemObjectRaw::is_open) finds the open handle's file removed
+    /// underneath it, so the next read, seek or write errors without checking again itself.
+    unlinked: AtomicBool,
+    /// Whether reads are served from `mapping`, see [`Self::set_mmap_reads`].
+    #[cfg(feature="mmap")]
+    mmap_reads: bool,
+    /// Mapping of the whole file, made on the first mapped read and dropped whenever the file may
+    /// have changed length under it.
+    #[cfg(feature="mmap")]
+    mapping: Mutex<Option<memmap2::Mmap>>,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
 }
 
 impl PhysicalDirectory {
-    fn new(name: &Path, parent: Option<Arc<RwLock<dyn Directory>>>) -> Arc<RwLock<Self>> {
+    fn new(name: &Path, path: PathBuf, parent: Option<FilesystemObject>, readonly: bool) -> Arc<RwLock<Self>> {
         let new = Self{
             name: name.to_path_buf(),
+            path,
             parent,
 
-            children: RefCell::new(HashMap::new()),
-            scanned: RefCell::new(false),
+            children: RwLock::new(HashMap::new()),
+            scanned: AtomicBool::new(false),
+            readonly,
+            overwrite: false,
+            create_parents: false,
+            lazy: false,
+            case_insensitive: cfg!(any(windows, target_os="macos")),
+            retry: RetryPolicy::default(),
+            file_mode: DEFAULT_FILE_MODE,
+            dir_mode: DEFAULT_DIR_MODE,
+            #[cfg(unix)]
+            dir_handle: OnceLock::new(),
+            #[cfg(feature="watch")]
+            watcher: Mutex::new(None),
 
-            handle: OnceCell::new(),
+            handle: OnceLock::new(),
         };
 
         let arc = Arc::new(RwLock::new(new));
@@ -50,44 +264,448 @@ impl PhysicalDirectory {
         arc
     }
 
-    fn scan(&self) -> Result<()> {
-        if !*self.scanned.borrow() && self.exists() {
-            let handle = self.get();
-            let mut children = self.children.borrow_mut();
-            
+    /// Creates a root directory object for the given path. The path is used as-is as the
+    /// directory's name, so relative paths stay relative.
+    pub fn from_path(path: impl AsRef<Path>) -> Arc<RwLock<Self>> {
+        Self::new(path.as_ref(), path.as_ref().to_path_buf(), None, false)
+    }
+
+    /// Opens the file `name` relative to this directory's own handle (`openat` on Unix), so the
+    /// child is resolved and opened in one race-free step and a symlink swapped in for it is
+    /// refused. The directory handle is opened on first use and reused afterwards, so it keeps
+    /// referring to the same directory even if its path is renamed. Other platforms open by path.
+    /// `name` must be a single path segment.
+    pub fn open_child_at(&self, name: &str, mode: OpenMode) -> FsResult<FilesystemObject> {
+        check_segment(name)?;
+        if mode != OpenMode::Read {
+            self.check_writable()?;
+        }
+
+        let file = self.open_child_handle(name, mode)?;
+        let child = self.adopt_child(name, file, mode)?;
+        self.children.write().unwrap().insert(name.to_string(), child.clone());
+
+        Ok(child)
+    }
+
+    /// Opens the file `name` for reading and writing, creating it if needed, and takes an
+    /// exclusive advisory lock on it before returning, as for a PID or single-instance lock file.
+    /// Errors with [`FsError::Locked`] straight away, rather than waiting, if another handle holds
+    /// a lock on it, whether in this process or another. The lock lasts as long as the returned
+    /// object's handle, so closing or dropping it releases the lock. The object isn't cached in
+    /// this directory for that reason, and looking `name` up gives a separate object that holds no
+    /// lock. Like [`Self::open_child_at`], `name` must be a single path segment.
+    pub fn open_locked_exclusive(&mut self, name: &str) -> FsResult<FilesystemObject> {
+        check_segment(name)?;
+        self.check_writable()?;
+
+        let file = self.open_child_handle(name, OpenMode::ReadWrite)?;
+        match file.try_lock() {
+            Ok(()) => {},
+            Err(fs::TryLockError::WouldBlock) => {
+                return Err(FsError::locked(self.path.join(name).to_string_lossy().to_string()));
+            },
+            Err(fs::TryLockError::Error(error)) => return Err(error.into()),
+        }
+
+        Ok(self.adopt_child(name, file, OpenMode::ReadWrite)?)
+    }
+
+    /// Makes a child object for `name` around an already opened `file`, without caching it. As when
+    /// a file opens itself, an append-mode handle starts at the end.
+    fn adopt_child(&self, name: &str, mut file: fs::File, mode: OpenMode) -> FsResult<Arc<RwLock<PhysicalFile>>> {
+        if mode == OpenMode::Append {
+            file.seek(SeekFrom::End(0))?;
+        }
+
+        let child = self.new_child_file(Path::new(name), 0, self.readonly);
+        {
+            let mut child = child.write().unwrap();
+            child.open_mode = mode;
+            child.file = Some(file);
+        }
+
+        Ok(child)
+    }
+
+    /// Returns the children like [`FilesystemObjectRaw::get_children`], each paired with its inode
+    /// on Unix or its file index on Windows. Hard links to the same file share the number, so
+    /// backup tools can group them and store the contents once.
+    pub fn children_with_inodes(&self) -> FsResult<Vec<(FilesystemObject, u64)>> {
+        self.get_children()?
+            .into_iter()
+            .map(|child| {
+                let (_, inode) = sys::file_id(&child.read().unwrap().get_full_path())?;
+                Ok((child, inode))
+            })
+            .collect()
+    }
+
+    /// Returns the target of the symlink `name` in this directory, as stored in the link.
+    pub fn read_link(&self, name: &str) -> FsResult<PathBuf> {
+        check_segment(name)?;
+        Ok(fs::read_link(self.path.join(name))?)
+    }
+
+    /// Follows the symlink `name` in this directory and returns a root object for its target, so
+    /// navigation can carry on from there. Relative targets resolve against this directory. Errors
+    /// with [`FsError::FileNotPresent`] if the link is broken. The object shares this directory's
+    /// read-only flag.
+    pub fn resolve_link(&self, name: &str) -> FsResult<FilesystemObject> {
+        let target = self.path.join(self.read_link(name)?);
+
+        let object: FilesystemObject = match fs::metadata(&target) {
+            Ok(metadata) if metadata.is_dir() => PhysicalDirectory::from_path(&target),
+            Ok(_) => PhysicalFile::from_path(&target, 0),
+            Err(error) if error.kind() == IoErrorKind::NotFound => {
+                return Err(FsError::file_not_present(self.display_path(), name.to_string()));
+            },
+            Err(error) => return Err(error.into()),
+        };
+        object.write().unwrap().set_readonly(self.readonly)?;
+
+        Ok(object)
+    }
+
+    /// Creates `name` in this directory as a link to `target`, which must be a physical object too,
+    /// and returns it. Hard links can only point at files on the same device; anything else errors
+    /// with [`FsError::Unsupported`], as does a `target` from another backend. Symbolic links store
+    /// `target`'s path made absolute, so they keep pointing at it wherever they're read from.
+    /// `name` must be a single path segment.
+    pub fn link_child(&mut self, name: &str, target: &FilesystemObject, kind: LinkKind) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+        check_segment(name)?;
+        self.check_vacant(name)?;
+        let (target_path, is_dir) = {
+            let target = target.read().unwrap();
+            match target.physical_path() {
+                Some(path) => (path, target.is_dir()),
+                None => return Err(FsError::unsupported("physical", "link_child to another backend")),
+            }
+        };
+        let path = self.path.join(name);
+        match kind {
+            LinkKind::Hard => match fs::hard_link(&target_path, &path) {
+                Err(error) if error.kind() == IoErrorKind::CrossesDevices => {
+                    return Err(FsError::unsupported("physical", "hard links across devices"));
+                },
+                result => result?,
+            },
+            LinkKind::Symbolic => sys::symlink(&std::path::absolute(&target_path)?, &path, is_dir)?,
+        }
+
+        let child = self.new_child(Path::new(name), is_dir);
+        self.children.write().unwrap().insert(name.to_string(), child.clone());
+
+        Ok(child)
+    }
+
+    /// Writes each `(name, contents)` pair to a file in this directory, replacing any existing
+    /// contents, and fsyncs every file as it goes. The directory itself is fsynced once at the end
+    /// rather than per file, which is what makes the new entries durable, so the whole batch costs
+    /// a single directory sync. Names must be single path segments. Cached objects for the written
+    /// files are replaced, as their buffers would be stale.
+    pub fn write_batch_durable(&mut self, files: &[(&str, &[u8])]) -> FsResult {
+        self.check_writable()?;
+        for (name, _) in files {
+            check_segment(name)?;
+        }
+
+        for &(name, contents) in files {
+            let mut file = self.open_child_handle(name, OpenMode::Write)?;
+            file.write_all(contents)?;
+            file.sync_all()?;
+
+            let child = self.new_child(Path::new(name), false);
+            self.children.write().unwrap().insert(name.to_string(), child);
+        }
+
+        // Windows can't sync a directory, but NTFS journals its metadata anyway.
+        #[cfg(unix)]
+        self.dir_handle()?.sync_all()?;
+
+        Ok(())
+    }
+
+    /// This directory's own handle, opened on first use.
+    #[cfg(unix)]
+    fn dir_handle(&self) -> IoResult<&fs::File> {
+        if let Some(dir) = self.dir_handle.get() {
+            return Ok(dir);
+        }
+
+        // Losing a race here just drops the extra handle.
+        let _ = self.dir_handle.set(sys::open_dir(&self.path)?);
+        Ok(self.dir_handle.get().unwrap())
+    }
+
+    /// Opens the child file `name` relative to [`Self::dir_handle`] on Unix, by path elsewhere.
+    fn open_child_handle(&self, name: &str, mode: OpenMode) -> IoResult<fs::File> {
+        #[cfg(unix)]
+        return sys::open_at(self.dir_handle()?, name, mode, self.file_mode);
+        #[cfg(not(unix))]
+        return mode.options().open(self.path.join(name));
+    }
+
+    /// Lets [`FilesystemObjectRaw::new_file`] and [`FilesystemObjectRaw::new_dir`] replace an
+    /// existing child instead of failing with [`FsError::AlreadyExists`]. A new file replaces the
+    /// old one's contents once written; a new directory over an existing one keeps its contents.
+    pub fn set_overwrite(&mut self, overwrite: bool) {
+        self.overwrite = overwrite;
+    }
+
+    /// Starts watching the entries directly inside this directory, returning a channel of the
+    /// changes. Each change also invalidates the cached children, so the next listing re-reads
+    /// them. Watching again replaces the previous watch, whose channel then disconnects.
+    #[cfg(feature="watch")]
+    pub fn watch(&self) -> FsResult<Receiver<FsEvent>> {
+        let dir = self.handle.get().unwrap().clone();
+        let (watcher, receiver) = watch::watch_dir(&self.path, move || {
+            if let Some(dir) = dir.upgrade() {
+                dir.read().unwrap().scanned.store(false, Ordering::Release);
+            }
+        })?;
+        *self.watcher.lock().unwrap() = Some(watcher);
+
+        Ok(receiver)
+    }
+
+    /// Sets the permission bits files created in this directory get on Unix, or goes back to the
+    /// default `0o666` with `None`. Either way the process umask is applied on top, as `touch`
+    /// does. Children cached from now on inherit the setting. Ignored on other platforms.
+    pub fn set_file_mode(&mut self, mode: Option<u32>) {
+        self.file_mode = mode.unwrap_or(DEFAULT_FILE_MODE);
+    }
+
+    /// Sets the permission bits directories created in this directory get on Unix, or goes back to
+    /// the default `0o777` with `None`, less the umask as with `mkdir`. Like
+    /// [`Self::set_file_mode`], it's inherited by children cached from now on.
+    pub fn set_dir_mode(&mut self, mode: Option<u32>) {
+        self.dir_mode = mode.unwrap_or(DEFAULT_DIR_MODE);
+    }
+
+    /// Makes [`FilesystemObjectRaw::new_dir`] create this directory and any missing ancestors on
+    /// disk along with the new one, like `create_dir_all`, instead of failing when they're missing.
+    pub fn set_create_parents(&mut self, create_parents: bool) {
+        self.create_parents = create_parents;
+    }
+
+    /// Makes looking up a single child (`get_child`, `try_get_child` and `has_child`) stat just
+    /// that entry instead of scanning the whole directory first, for huge directories where only a
+    /// few names are wanted. Only [`FilesystemObjectRaw::get_children`] and
+    /// [`FilesystemObjectRaw::scan`] read the directory then. Inherited by child directories
+    /// cached from now on.
+    pub fn set_lazy(&mut self, lazy: bool) {
+        self.lazy = lazy;
+    }
+
+    /// Makes looking up a single child (`get_child`, `try_get_child` and `has_child`) match names
+    /// regardless of case when there's no exact match, as Windows and macOS do by default; it's on
+    /// by default there and off elsewhere. A name matching several entries that differ only in
+    /// case, none of them exactly, is an error rather than a guess. Inherited by child
+    /// directories cached from now on.
+    pub fn set_case_insensitive(&mut self, case_insensitive: bool) {
+        self.case_insensitive = case_insensitive;
+    }
+
+    /// Sets how listing this directory is retried after a transient error, as network mounts
+    /// sometimes report. Inherited by children cached from now on, so their files retry opening,
+    /// reading and flushing the same way.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Errors with [`FsError::AlreadyExists`] if `name` is cached or on disk, unless overwriting is
+    /// allowed.
+    fn check_vacant(&self, name: &str) -> FsResult {
+        let path = self.path.join(name);
+        if !self.overwrite && (self.children.read().unwrap().contains_key(name) || fs::symlink_metadata(&path).is_ok()) {
+            return Err(FsError::already_exists(path.to_string_lossy().to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn new_child(&self, name: &Path, is_dir: bool) -> FilesystemObject {
+        if is_dir {
+            self.new_child_dir(name, self.readonly)
+        } else {
+            self.new_child_file(name, 0, self.readonly)
+        }
+    }
+
+    /// A child directory object, passing on the creation modes.
+    fn new_child_dir(&self, name: &Path, readonly: bool) -> Arc<RwLock<PhysicalDirectory>> {
+        let dir = PhysicalDirectory::new(name, self.path.join(name), Some(self.get()), readonly);
+        {
+            let mut dir = dir.write().unwrap();
+            dir.file_mode = self.file_mode;
+            dir.dir_mode = self.dir_mode;
+            dir.lazy = self.lazy;
+            dir.case_insensitive = self.case_insensitive;
+            dir.retry = self.retry.clone();
+        }
+
+        dir
+    }
+
+    /// A child file object, created with this directory's file mode if it doesn't exist yet.
+    fn new_child_file(&self, name: &Path, buffer_size: usize, readonly: bool) -> Arc<RwLock<PhysicalFile>> {
+        let file = PhysicalFile::new(name, self.path.join(name), self.get(), buffer_size, readonly);
+        {
+            let mut file = file.write().unwrap();
+            file.mode = self.file_mode;
+            file.retry = self.retry.clone();
+        }
+
+        file
+    }
+
+    fn check_writable(&self) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        Ok(())
+    }
+
+    /// Finds the child `name`, falling back to a match regardless of case if that's enabled.
+    fn lookup(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        let found = self.lookup_exact(name)?;
+        if found.is_some() || !self.case_insensitive {
+            return Ok(found);
+        }
+
+        self.lookup_folded(name)
+    }
+
+    /// Finds the one child whose name matches `name` regardless of case, scanning first.
+    fn lookup_folded(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        let folded = name.to_lowercase();
+        let children = self.children.read().unwrap();
+        let mut matches: Vec<_> = children.iter()
+            .filter(|(child, _)| child.to_lowercase() == folded)
+            .collect();
+        matches.sort_unstable_by_key(|(child, _)| *child);
+
+        match matches.as_slice() {
+            [] => Ok(None),
+            [(_, child)] => Ok(Some((*child).clone())),
+            [(first, _), (second, _), ..] => Err(FsError::generic(format!(
+                "'{name}' matches more than one entry in '{}' ignoring case, such as '{first}' and '{second}'",
+                self.display_path(),
+            ))),
+        }
+    }
+
+    /// Finds the child `name` in the cache, scanning first unless the directory is lazy, in which
+    /// case just that entry is looked up on disk and cached.
+    fn lookup_exact(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        if !self.lazy || self.scanned.load(Ordering::Acquire) {
+            self.scan_if_needed()?;
+            return Ok(self.children.read().unwrap().get(name).cloned());
+        }
+
+        if let Some(child) = self.children.read().unwrap().get(name) {
+            return Ok(Some(child.clone()));
+        }
+
+        // Scanning only ever finds plain names, so neither should this.
+        let mut components = Path::new(name).components();
+        if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+            return Ok(None);
+        }
+
+        let metadata = match fs::symlink_metadata(self.path.join(name)) {
+            Ok(metadata) => metadata,
+            Err(error) if error.kind() == IoErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        if !metadata.is_dir() && !metadata.is_file() {
+            return Ok(None);
+        }
+
+        let child = self.new_child(Path::new(name), metadata.is_dir());
+        Ok(Some(self.children.write().unwrap().entry(name.to_string()).or_insert(child).clone()))
+    }
+
+    /// Populates the children cache if it hasn't been already. Children that are already cached
+    /// keep their existing handles.
+    fn scan_if_needed(&self) -> FsResult {
+        if self.scanned.load(Ordering::Acquire) || !self.exists() {
+            return Ok(());
+        }
+
+        let entries = self.retry.run(|| {
+            let mut entries = Vec::new();
             for item in fs::read_dir(self.get_full_path())? {
                 let item = item?;
                 let file_type: fs::FileType = item.file_type()?;
-                if file_type.is_dir() {
-                    let child_name = item.file_name();
-                    let child = PhysicalDirectory::new(Path::new(&child_name), Some(handle.clone()));
-                    children.insert(child_name.into_string().unwrap(), FilesystemObject::Directory(child));
-                } else if file_type.is_file() {
-                    let child_name = item.file_name();
-                    let child = PhysicalFile::new(Path::new(&child_name), handle.clone(), 0);
-                    children.insert(child_name.into_string().unwrap(), FilesystemObject::File(child));
+                if file_type.is_dir() || file_type.is_file() {
+                    entries.push((item.file_name(), file_type));
                 }
             }
 
-            *self.scanned.borrow_mut() = true;
+            Ok(entries)
+        })?;
+
+        let mut children = self.children.write().unwrap();
+        let mut found = HashMap::with_capacity(children.len());
+
+        for (child_name, file_type) in entries {
+            let key = child_name.to_string_lossy().to_string();
+            let child = match children.remove(&key) {
+                Some(child) => child,
+                None => self.new_child(Path::new(&child_name), file_type.is_dir()),
+            };
+            found.insert(key, child);
         }
 
+        *children = found;
+        self.scanned.store(true, Ordering::Release);
+
         Ok(())
     }
 }
 
 impl PhysicalFile {
-    fn new(name: &Path, parent: Arc<RwLock<dyn Directory>>, buffer_size: usize) -> Arc<RwLock<Self>> {
+    fn new(name: &Path, path: PathBuf, parent: FilesystemObject, buffer_size: usize, readonly: bool) -> Arc<RwLock<Self>> {
         let new = Self{
             name: name.to_path_buf(),
+            path,
             parent,
 
             file: None,
-            buffer: Vec::with_capacity(buffer_size),
+            buffer: Vec::new(),
+            buffer_size,
             buf_filled: 0,
             cursor: 0,
 
-            handle: OnceCell::new(),
+            dirty: false,
+            write_pos: 0,
+            text: TextNormalizer::default(),
+            atomic: false,
+            flush_on_drop: true,
+            flush_threshold: None,
+            retry: RetryPolicy::default(),
+            open_mode: OpenMode::Read,
+            direct: None,
+            readonly,
+            mode: DEFAULT_FILE_MODE,
+            deleted: false,
+            unlinked: AtomicBool::new(false),
+            #[cfg(feature="mmap")]
+            mmap_reads: false,
+            #[cfg(feature="mmap")]
+            mapping: Mutex::new(None),
+
+            handle: OnceLock::new(),
         };
 
         let arc = Arc::new(RwLock::new(new));
@@ -96,6 +714,16 @@ impl PhysicalFile {
         arc
     }
 
+    /// Creates a file object for the given path, along with a root [`PhysicalDirectory`] for its
+    /// parent path. A `buffer_size` of 0 buffers the whole file.
+    pub fn from_path(path: impl AsRef<Path>, buffer_size: usize) -> Arc<RwLock<Self>> {
+        let path = path.as_ref();
+        let parent = PhysicalDirectory::from_path(path.parent().unwrap_or(Path::new("")));
+        let name = path.file_name().map(Path::new).unwrap_or(path);
+
+        Self::new(name, path.to_path_buf(), parent, buffer_size, false)
+    }
+
     pub fn get_handle(&self) -> Option<&fs::File> {
         self.file.as_ref()
     }
@@ -104,202 +732,2648 @@ impl PhysicalFile {
         self.file.take()
     }
 
-    fn fill_buffer(&mut self) -> std::io::Result<usize> {
-        if !self.is_open() {
-            self.open()?;
-            if self.buffer.capacity() == 0 {
-                let file_size = self.file.as_ref().unwrap().metadata().unwrap().len() as usize;
-                self.buffer.resize(file_size, 0);
-            }
-        }
-
-        self.cursor = 0;
-        self.buf_filled = self.file.as_ref().unwrap().read(&mut self.buffer)?;
-
-        Ok(self.buf_filled)
+    /// Opens the file if needed and waits until this handle holds an exclusive lock on it, held
+    /// until the returned guard is dropped. Locks are advisory (`flock` on Unix, `LockFileEx` on
+    /// Windows): they only hold off other handles that lock the file too, in this process or
+    /// another, and never stop plain reads or writes. The guard keeps a duplicate of the handle,
+    /// so the lock outlives closing the file until the guard goes.
+    pub fn lock_exclusive(&mut self) -> FsResult<FileLock> {
+        self.lock(false)
     }
-}
 
-impl Directory for PhysicalDirectory {
-    fn get(&self) -> Arc<RwLock<dyn Directory>> {
-        self.handle.get().unwrap().upgrade().unwrap()
+    /// Like [`Self::lock_exclusive`], but takes a shared lock, which any number of handles can
+    /// hold at once while no exclusive lock is held.
+    pub fn lock_shared(&mut self) -> FsResult<FileLock> {
+        self.lock(true)
     }
 
-    fn name(&self) -> String {
-        self.name.to_string_lossy().to_string()
+    /// Releases the lock held through this file's handle, whichever guard took it. Errors with
+    /// [`FsError::FileNotOpen`] if the file isn't open.
+    pub fn unlock(&self) -> FsResult {
+        let file = self.file.as_ref().ok_or_else(|| FsError::file_not_open(self.display_path()))?;
+        file.unlock()?;
+
+        Ok(())
     }
 
-    fn exists(&self) -> bool {
-        let path = self.get_full_path();
-        path.exists() && path.is_dir()
+    /// Tells the OS how the whole file is about to be read, so it can tune read-ahead and
+    /// caching: `posix_fadvise` on Linux, Android and FreeBSD, and read-ahead on or off for the
+    /// sequential and random hints on macOS. Elsewhere, and for hints a platform has no
+    /// equivalent of, it does nothing. Errors with [`FsError::FileNotOpen`] if the file isn't
+    /// open.
+    pub fn advise(&self, hint: AccessHint) -> FsResult {
+        let file = self.file.as_ref().ok_or_else(|| FsError::file_not_open(self.display_path()))?;
+        sys::advise(file, hint)?;
+
+        Ok(())
     }
 
-    fn get_parent(&self) -> Option<Arc<RwLock<dyn Directory>>> {
-        self.parent.as_ref().map(|item| item.clone())
+    /// Flushes, then commits the file to stable storage, just the data if `data_only`.
+    fn sync(&mut self, data_only: bool) -> FsResult {
+        self.flush()?;
+
+        // A read-only handle can't be synced on every platform, so one that can write is opened.
+        let opened;
+        let handle = match self.file.as_ref() {
+            Some(file) if self.open_mode != OpenMode::Read => file,
+            _ => {
+                opened = fs::OpenOptions::new().write(true).open(self.get_full_path())?;
+                &opened
+            },
+        };
+        self.retry.run(|| match data_only {
+            true => handle.sync_data(),
+            false => handle.sync_all(),
+        })?;
+
+        Ok(())
     }
-    
-    fn get_full_path(&self) -> PathBuf {
-        match &self.parent {
-            Some(parent)
-                => parent.read().unwrap().get_full_path().join(&self.name),
-            None => self.name.clone().into(),
+
+    fn lock(&mut self, shared: bool) -> FsResult<FileLock> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        let file = self.file.as_ref().unwrap().try_clone()?;
+        match shared {
+            true => file.lock_shared()?,
+            false => file.lock()?,
         }
+
+        Ok(FileLock{ file })
     }
 
-    fn get_children(&self) -> Result<Vec<FilesystemObject>> {
-        self.scan()?;
+    /// Maps the file into memory `window` bytes at a time, yielding each window in turn. Each
+    /// window starts `window - overlap` bytes after the previous one, so consecutive windows share
+    /// `overlap` bytes for matching across the boundary; the last may be shorter. A window is only
+    /// mapped when the iterator reaches it, so dropping each before taking the next keeps one
+    /// mapping alive at a time, however large the file. Pending writes are flushed first.
+    ///
+    /// The file is mapped through a handle of its own. Like any mapping, a window's contents
+    /// change if the file is modified while it's mapped, and touching it after the file is
+    /// truncated underneath can crash the process.
+    #[cfg(feature="mmap")]
+    pub fn mmap_windows(&mut self, window: usize, overlap: usize) -> FsResult<impl Iterator<Item = FsResult<MappedWindow>> + use<>> {
+        if window == 0 || overlap >= window {
+            return Err(FsError::generic(format!("invalid mmap window of {window} bytes overlapping by {overlap}")));
+        }
 
-        let children: Vec<FilesystemObject> = self.children.borrow().values()
-            .map(|child| child.clone())
-            .collect();
+        self.flush()?;
+        let file = fs::File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        let step = (window - overlap) as u64;
 
-        Ok(children)
-    }
+        let mut offset = 0;
+        Ok(std::iter::from_fn(move || {
+            if offset >= len {
+                return None;
+            }
 
-    fn get_child(&self, name: &str) -> Result<FilesystemObject> {
-        let children = self.children.borrow();
-        let lookup_result = children.get(name)
-            .ok_or_else(|| FsError::FileNotPresent(self.get_full_path().to_string_lossy().to_string(), name.to_string()))?;
+            let map_len = (len - offset).min(window as u64) as usize;
+            let map = unsafe { memmap2::MmapOptions::new().offset(offset).len(map_len).map(&file) };
+            let window = map.map(|map| MappedWindow { offset, map }).map_err(FsError::from);
 
-        Ok(lookup_result.clone())
+            // Once a window reaches the end, the next would only repeat its overlap.
+            offset = if offset + map_len as u64 >= len { len } else { offset + step };
+            Some(window)
+        }))
     }
-    
-    fn has_child(&self, name: &str) -> Result<bool> {
-        Ok(self.children.borrow().contains_key(name))
-    }
-    
-    fn new_file(&mut self, name: String, buffer_size: usize) -> Result<Arc<RwLock<dyn File>>> {
-        let file = PhysicalFile::new(Path::new(&name), self.get(), buffer_size);
-        self.children.borrow_mut().insert(name, FilesystemObject::File(file.clone()));
-        Ok(file)
-    }
-    
-    fn new_dir(&mut self, name: String) -> Result<Arc<RwLock<dyn Directory>>> {
-        let file = PhysicalDirectory::new(Path::new(&name), Some(self.get()));
-        self.children.borrow_mut().insert(name, FilesystemObject::Directory(file.clone()));
-        Ok(file)
+
+    /// Maps the whole file into memory read-only, through a handle of its own. Pending writes are
+    /// not flushed, so only what's on disk shows up.
+    ///
+    /// # Safety caveat
+    /// The mapping reflects later changes to the file, and if the file is truncated underneath it
+    /// (by this process or any other), touching the bytes past the new end can crash the process
+    /// with `SIGBUS`. Only map files nothing else shrinks while the mapping is alive.
+    #[cfg(feature="mmap")]
+    pub fn mmap(&self) -> FsResult<memmap2::Mmap> {
+        Ok(map_file(&self.path)?)
     }
-}
 
-impl Read for PhysicalFile {
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        if self.cursor >= self.buf_filled {
-            self.fill_buffer()?;
+    /// Serves [`Read`], [`BufRead`] and [`FilesystemObjectRaw::read_at`] from a mapping of the file
+    /// instead of the read buffer, sparing a syscall and a copy per buffer fill, which pays off
+    /// for random access into large files. Off by default.
+    ///
+    /// The mapping is made on the first read and dropped when the file is written, truncated,
+    /// reopened or closed, so it never outlives a length change made through this object. Growth
+    /// through other handles only shows up once it's remapped, and the caveat on [`Self::mmap`]
+    /// applies: the file must not be truncated externally while mapped reads are on.
+    #[cfg(feature="mmap")]
+    pub fn set_mmap_reads(&mut self, enabled: bool) -> FsResult {
+        // Put the OS cursor back at the logical position, mapped reads don't use the buffer.
+        if self.file.is_some() {
+            self.stream_position()?;
         }
 
-        let byte_count = (self.buf_filled - self.cursor).min(buf.len());
-        let end = self.cursor + byte_count;
-
-        buf[..byte_count].copy_from_slice(&self.buffer[self.cursor..end]);
-        self.cursor = end;
+        self.mmap_reads = enabled;
+        self.drop_mapping();
 
-        Ok(byte_count)
+        Ok(())
     }
-}
 
-impl BufRead for PhysicalFile {
-    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
-        if self.cursor >= self.buf_filled {
-            self.fill_buffer()?;
-        }
+    /// Sets how text written to this file is normalized. Takes effect for subsequent writes; the
+    /// final line is completed when the file is closed. Flushing or seeking writes out the current
+    /// line as it stands, so trailing whitespace before a flush is kept.
+    pub fn set_text_normalization(&mut self, policy: TextPolicy) {
+        self.text.set_policy(policy);
+    }
 
-        Ok(&self.buffer[self.cursor..self.buf_filled])
+    /// Enables atomic flushing: pending writes are applied to a sibling temp file which is then
+    /// renamed over the destination, so a crash mid-write leaves either the old or the new
+    /// content, never a mix. Off by default.
+    pub fn set_atomic(&mut self, atomic: bool) {
+        self.atomic = atomic;
     }
 
-    fn consume(&mut self, amt: usize) {
-        self.cursor = (self.cursor + amt).min(self.buf_filled);
+    /// Sets whether dropping the object closes it like [`FilesystemObjectRaw::close`], writing out
+    /// pending bytes. On by default; turn it off where drop-time I/O is unwanted, and the pending
+    /// bytes are discarded instead. Errors can't surface from a drop, so call `flush` or `close`
+    /// to see them.
+    ///
+    /// Files reached through a directory stay in its cache, which keeps them alive after the
+    /// caller's handle is dropped, so flush or close those explicitly.
+    pub fn set_flush_on_drop(&mut self, flush_on_drop: bool) {
+        self.flush_on_drop = flush_on_drop;
     }
-}
 
-impl Seek for PhysicalFile {
-    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
-        self.file.as_ref().unwrap().seek(pos)
+    /// Sets how many bytes writes may leave pending before they're written out on their own, so
+    /// any amount can be streamed through `write_all` with memory staying bounded. `None` restores
+    /// the default: the buffer size, or 8 MiB when the whole file is buffered. Atomic files hold
+    /// everything until they're flushed regardless, and with direct I/O only whole buffers are
+    /// written out early.
+    pub fn set_flush_threshold(&mut self, threshold: Option<usize>) {
+        self.flush_threshold = threshold;
     }
-}
 
-impl Write for PhysicalFile {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.buf_filled = 0;
-        self.cursor = 0;
+    /// Sets how opening, reading and flushing in place are retried after a transient error, as
+    /// network mounts sometimes report. A retried flush writes all its pending bytes again at the
+    /// same offset, so a partial write is simply overwritten.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
 
-        let byte_count = self.buffer.len().min(buf.len());
-        self.buffer[..byte_count].copy_from_slice(&buf[..byte_count]);
-        
-        Ok(byte_count)
+    /// Sets how [`FilesystemObjectRaw::open`] opens the file, taking effect the next time it's
+    /// opened. In the default [`OpenMode::Read`] the handle is read-only and each flush opens the
+    /// file separately for writing. The other modes write through the handle itself; with
+    /// [`OpenMode::Append`] writes always land at the end, preserving existing content, and
+    /// [`OpenMode::Write`] truncates the file when it's opened.
+    pub fn set_open_mode(&mut self, mode: OpenMode) {
+        self.open_mode = mode;
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        self.open()?;
+    /// Opens the file with direct I/O, bypassing the OS page cache (`O_DIRECT` on Linux,
+    /// `F_NOCACHE` on macOS, `FILE_FLAG_NO_BUFFERING` on Windows). Meant for applications that do
+    /// their own caching.
+    ///
+    /// Direct I/O stays enabled across reopens and imposes alignment constraints: the buffer size
+    /// must be a non-zero multiple of [`DIRECT_IO_ALIGNMENT`], and so must the offset of every
+    /// read and the offset and length of every flushed write. Misaligned operations fail with
+    /// [`std::io::ErrorKind::InvalidInput`] instead of silently falling back; only the final read
+    /// at the end of the file may come up short. Atomic flushes (see [`Self::set_atomic`]) go
+    /// through the page cache regardless.
+    pub fn open_direct(&mut self) -> FsResult<()> {
+        if self.buffer_size == 0 || !self.buffer_size.is_multiple_of(DIRECT_IO_ALIGNMENT) {
+            return Err(FsError::generic(format!(
+                "direct I/O on '{}' needs a buffer size that is a non-zero multiple of {DIRECT_IO_ALIGNMENT} bytes (got {}); call set_buffer_size first",
+                self.display_path(), self.buffer_size,
+            )));
+        }
 
-        let mut handle = self.file.as_ref().unwrap();
-        handle.write(&self.buffer)?;
-        handle.flush()?;
+        self.direct = Some(AlignedBuffer::new(self.buffer_size));
+        if let Err(err) = self.open() {
+            self.direct = None;
+            return Err(err.into());
+        }
 
         Ok(())
     }
-}
 
-impl File for PhysicalFile {
-    fn get(&self) -> Arc<RwLock<dyn File>> {
-        self.handle.get().unwrap().upgrade().unwrap()
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
     }
 
-    fn name(&self) -> String {
-        self.name.to_string_lossy().to_string()
+    /// Options for opening the file to write without truncating it, creating it with `self.mode`.
+    fn write_options(&self) -> fs::OpenOptions {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(false);
+        sys::set_mode(&mut options, self.mode);
+
+        options
     }
-    
-    fn stem(&self) -> String {
-        self.name.file_stem().unwrap().to_string_lossy().to_string()
+
+    /// Writes `self.buffer` at `self.write_pos` with direct I/O, staged through `staging`, opening
+    /// the file with `options`.
+    fn flush_direct(mut options: fs::OpenOptions, path: &Path, write_pos: u64, buffer: &[u8], staging: &mut AlignedBuffer) -> IoResult<()> {
+        sys::check_direct_alignment(write_pos, buffer.len())?;
+
+        sys::set_direct(&mut options);
+        let mut handle = options.open(path)?;
+        sys::after_direct_open(&handle)?;
+
+        if staging.len() < buffer.len() {
+            *staging = AlignedBuffer::new(buffer.len());
+        }
+        staging.as_mut_slice()[..buffer.len()].copy_from_slice(buffer);
+
+        handle.seek(SeekFrom::Start(write_pos))?;
+        handle.write_all(&staging.as_slice()[..buffer.len()])
     }
-    
-    fn ext(&self) -> String {
-        self.name.extension().unwrap().to_string_lossy().to_string()
+
+    /// Writes `self.buffer` at `self.write_pos` into a copy of the file, then renames the copy over
+    /// the original. Every flush copies to a temp file of its own, so handles flushing the same
+    /// file at once don't write into each other's copies.
+    fn flush_atomic(&self, path: &Path) -> IoResult<()> {
+        let (temp_path, mut handle) = create_temp_sibling(path, &self.write_options())?;
+
+        let result = (|| {
+            if path.exists() {
+                fs::copy(path, &temp_path)?;
+            }
+
+            handle.seek(SeekFrom::Start(self.write_pos))?;
+            handle.write_all(&self.buffer)?;
+            handle.sync_all()?;
+
+            fs::rename(&temp_path, path)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
     }
-    
-    fn size(&self) -> Result<usize> {
-        todo!()
+
+    /// How many pending bytes may build up before writes start spilling them to disk. Atomic
+    /// flushes have to apply everything at once, so they never spill.
+    fn write_high_water(&self) -> usize {
+        match (self.atomic, self.flush_threshold, self.buffer_size) {
+            (true, _, _) => usize::MAX,
+            (false, Some(threshold), _) => threshold,
+            (false, None, 0) => WRITE_HIGH_WATER,
+            (false, None, size) => size,
+        }
     }
 
-    fn exists(&self) -> bool {
+    /// Writes the first `len` pending bytes at `write_pos` and drops them from the buffer.
+    fn write_through(&mut self, len: usize) -> IoResult<()> {
+        if self.open_mode != OpenMode::Read && self.direct.is_none() && self.file.is_none() {
+            self.open_handle(false)?;
+        }
+
         let path = self.get_full_path();
-        path.exists() && path.is_file()
-    }
+        let options = self.write_options();
+        let pending = &self.buffer[..len];
+        // Every attempt writes all of `pending` from `write_pos`, so retrying is safe.
+        if let Some(staging) = self.direct.as_mut() {
+            self.retry.run(|| Self::flush_direct(options.clone(), &path, self.write_pos, pending, staging))?;
+        } else if self.open_mode != OpenMode::Read {
+            let handle = self.file.as_mut().unwrap();
+            self.retry.run(|| {
+                handle.seek(SeekFrom::Start(self.write_pos))?;
+                handle.write_all(pending)?;
+                handle.flush()
+            })?;
+        } else {
+            self.retry.run(|| {
+                let mut handle = options.open(&path)?;
+                handle.seek(SeekFrom::Start(self.write_pos))?;
+                handle.write_all(pending)?;
+                handle.flush()
+            })?;
+        }
 
-    fn rename(&self, name: &str) -> Result<()> {
-        let parent_dir = self.parent.read().unwrap().get_full_path();
-        fs::rename(&parent_dir.join(Path::new(&self.name)), &parent_dir.join(name))?;
+        self.write_pos += len as u64;
+        self.buffer.drain(..len);
 
         Ok(())
     }
 
-    fn get_parent(&self) -> Arc<RwLock<dyn Directory>> {
-        self.parent.clone()
-    }
+    /// Writes pending bytes out early, keeping memory bounded for large writes. With direct I/O
+    /// only whole buffers are spilled, so every write stays aligned.
+    fn spill(&mut self) -> IoResult<()> {
+        let len = match self.direct {
+            Some(_) => self.buffer.len() / self.buffer_size * self.buffer_size,
+            None => self.buffer.len(),
+        };
 
-    fn get_full_path(&self) -> PathBuf {
-        self.parent.read().unwrap().get_full_path().join(&self.name)
-    }
-    
-    fn set_buffer_size(&mut self, size: usize) {
-        self.buffer.resize(size, 0);
-    }
-    
-    fn open(&mut self) -> std::io::Result<()> {
-        if self.file.is_some() {
-            self.close();
+        if len > 0 {
+            self.write_through(len)?;
         }
 
-        self.file = Some(fs::File::open(self.get_full_path())?);
         Ok(())
     }
 
-    fn is_open(&self) -> bool {
-        self.file.is_some() && self.file.as_ref().unwrap().metadata().is_ok()
-    }
-    
-    fn close(&mut self) {
-        self.file = None;
-        self.buf_filled = 0;
-        self.cursor = 0;
+    /// Opens the handle according to the open mode. Reopening after a flush passes `truncate` as
+    /// false so [`OpenMode::Write`] doesn't wipe what was just written.
+    fn open_handle(&mut self, truncate: bool) -> IoResult<()> {
+        let mut options = self.open_mode.options();
+        sys::set_mode(&mut options, self.mode);
+        if !truncate {
+            options.truncate(false);
+        }
+        if self.direct.is_some() {
+            sys::set_direct(&mut options);
+        }
+
+        let path = self.get_full_path();
+        let mut file = self.retry.run(|| options.open(&path))?;
+        if self.direct.is_some() {
+            sys::after_direct_open(&file)?;
+        }
+        if self.open_mode == OpenMode::Append {
+            file.seek(SeekFrom::End(0))?;
+        }
+
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Switches the buffer over to holding pending writes, starting at the current position.
+    fn begin_write(&mut self) -> IoResult<()> {
+        if self.deleted {
+            return Err(self.deleted_error());
+        }
+
+        if !self.dirty {
+            // Open first so appends start at the end, and truncation happens before writing.
+            if self.open_mode != OpenMode::Read && self.file.is_none() {
+                self.open()?;
+            }
+
+            self.write_pos = self.logical_position()?;
+            self.buffer.clear();
+            self.buf_filled = 0;
+            self.cursor = 0;
+            self.dirty = true;
+            self.drop_mapping();
+        }
+
+        Ok(())
+    }
+
+    /// The position the caller has logically read or written up to, accounting for buffered
+    /// state.
+    fn logical_position(&self) -> IoResult<u64> {
+        if self.dirty {
+            return Ok(self.write_pos + self.buffer.len() as u64);
+        }
+
+        match self.file.as_ref() {
+            // `Seek` is implemented for `&File` too, which finds the position without `&mut self`.
+            Some(mut file) => Ok(file.stream_position()? - (self.buf_filled - self.cursor) as u64),
+            None => Ok(0),
+        }
+    }
+
+    fn deleted_error(&self) -> IoError {
+        FsError::deleted(self.display_path()).into()
+    }
+
+    /// Errors if [`is_open`](FilesystemObjectRaw::is_open) found the open handle's file removed
+    /// underneath it, dropping the handle along with any buffered and pending bytes. Reopening the
+    /// path instead would silently pick up whatever replaced the file, if anything. This only
+    /// reads the flag, so it's cheap enough for every read.
+    fn check_handle(&mut self) -> IoResult<()> {
+        if self.file.is_some() && *self.unlinked.get_mut() {
+            self.release();
+            return Err(self.deleted_error());
+        }
+
+        Ok(())
+    }
+
+    /// Drops the open handle and everything buffered, pending writes included.
+    fn release(&mut self) {
+        self.file = None;
+        *self.unlinked.get_mut() = false;
+        self.buffer.clear();
+        self.buf_filled = 0;
+        self.cursor = 0;
+        self.dirty = false;
+        self.drop_mapping();
+    }
+
+    /// Forgets the mapping mapped reads are served from, to be remapped on the next read.
+    fn drop_mapping(&mut self) {
+        #[cfg(feature="mmap")]
+        {
+            *self.mapping.get_mut().unwrap() = None;
+        }
+    }
+
+    /// The mapped bytes from the current position on, mapping the file first if needed.
+    #[cfg(feature="mmap")]
+    fn mapped(&mut self) -> IoResult<&[u8]> {
+        self.check_handle()?;
+        if self.dirty {
+            self.flush()?;
+        }
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        let position = self.file.as_mut().unwrap().stream_position()?;
+        let mapping = self.mapping.get_mut().unwrap();
+        if mapping.is_none() {
+            *mapping = Some(map_file(&self.path)?);
+        }
+
+        let map = mapping.as_ref().unwrap();
+        Ok(&map[(position as usize).min(map.len())..])
+    }
+
+    fn fill_buffer(&mut self) -> IoResult<usize> {
+        self.check_handle()?;
+        if self.dirty {
+            self.flush()?;
+        }
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        // Also picks up a buffer size changed since the last fill.
+        let size = match self.buffer_size {
+            0 => self.file.as_ref().unwrap().metadata()?.len() as usize,
+            size => size,
+        };
+        if self.buffer.len() != size {
+            self.buffer.resize(size, 0);
+        }
+
+        self.cursor = 0;
+
+        let file = self.file.as_mut().unwrap();
+        self.buf_filled = match self.direct.as_mut() {
+            Some(staging) => {
+                sys::check_direct_alignment(file.stream_position()?, self.buffer.len())?;
+                if staging.len() != self.buffer.len() {
+                    *staging = AlignedBuffer::new(self.buffer.len());
+                }
+
+                let count = self.retry.run(|| file.read(staging.as_mut_slice()))?;
+                self.buffer[..count].copy_from_slice(&staging.as_slice()[..count]);
+                count
+            },
+            None => self.retry.run(|| file.read(&mut self.buffer))?,
+        };
+
+        Ok(self.buf_filled)
+    }
+}
+
+impl fmt::Debug for PhysicalDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Children are only counted, printing them would recurse through the whole tree.
+        f.debug_struct("PhysicalDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("scanned", &self.scanned.load(Ordering::Acquire))
+            .field("lazy", &self.lazy)
+            .field("case_insensitive", &self.case_insensitive)
+            .field("cached_children", &self.children.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Read for PhysicalDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for PhysicalDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for PhysicalDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for PhysicalDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for PhysicalDirectory {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn revalidate(&self) -> FsResult<FsKind> {
+        revalidate_kind(&self.path, self.kind())
+    }
+
+    fn exists(&self) -> bool {
+        self.get_full_path().is_dir()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(fs::metadata(&self.path)?.modified()?)
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn canonicalize(&self) -> FsResult<PathBuf> {
+        Ok(fs::canonicalize(&self.path)?)
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        let (device, inode) = sys::file_id(&self.path)?;
+        Ok(ObjectId::Physical { device, inode })
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.readonly = readonly;
+        for child in self.children.read().unwrap().values() {
+            child.write().unwrap().set_readonly(readonly)?;
+        }
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let writable = !self.readonly;
+        Capabilities{
+            writable,
+            can_create: writable,
+            can_delete: writable,
+            can_rename: writable,
+            has_timestamps: true,
+            has_permissions: true,
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok(sys::fs_type(&self.path)?)
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Ok(sys::disk_space(&self.path)?.0)
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Ok(sys::disk_space(&self.path)?.1)
+    }
+
+    fn physical_path(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn move_to(&mut self, new_dir: FilesystemObject) -> FsResult {
+        self.check_writable()?;
+        if std::ptr::addr_eq(Arc::as_ptr(&new_dir), Arc::as_ptr(&self.get())) {
+            return Err(FsError::generic(format!("cannot move '{}' into itself", self.display_path())));
+        }
+
+        let dir_path = {
+            let new_dir = new_dir.read().unwrap();
+            if new_dir.readonly() {
+                return Err(FsError::read_only(new_dir.get_full_path().to_string_lossy().to_string()));
+            }
+            new_dir.physical_path()
+        };
+        // Only a physical directory can take a plain rename; wrappers get their checks through a copy.
+        let Some(dir_path) = dir_path else {
+            return move_by_copy(self, &new_dir);
+        };
+
+        let new_path = dir_path.join(&self.name);
+        match fs::rename(&self.path, &new_path) {
+            Err(error) if error.kind() == IoErrorKind::CrossesDevices => return move_by_copy(self, &new_dir),
+            result => result?,
+        }
+        self.path = new_path;
+
+        // Cached children still carry the old path.
+        self.children.write().unwrap().clear();
+        self.scanned.store(false, Ordering::Release);
+
+        if let Some(parent) = self.parent.replace(new_dir.clone()) {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+        new_dir.write().unwrap().scan()?;
+
+        Ok(())
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        let children = self.children.read().unwrap();
+        let mut entries: Vec<_> = children.iter().collect();
+        entries.sort_unstable_by_key(|(name, _)| *name);
+
+        Ok(entries.into_iter().map(|(_, child)| child.clone()).collect())
+    }
+
+    fn count_children(&self) -> FsResult<usize> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().len())
+    }
+
+    /// Reads the directory afresh, without touching the children cache.
+    fn list(&self) -> FsResult<Vec<DirEntry>> {
+        if !self.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for item in fs::read_dir(&self.path)? {
+            let item = item?;
+            let file_type = item.file_type()?;
+            let (kind, size_hint) = match (file_type.is_dir(), file_type.is_file()) {
+                (true, _) => (FsKind::Directory, None),
+                // Only Windows hands out sizes along with the listing, elsewhere they'd take a
+                // stat per entry.
+                (_, true) if cfg!(windows) => (FsKind::File, item.metadata().ok().map(|metadata| metadata.len())),
+                (_, true) => (FsKind::File, None),
+                _ => continue,
+            };
+
+            entries.push(DirEntry{ name: item.file_name().to_string_lossy().to_string(), kind, size_hint });
+        }
+        entries.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(entries)
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        self.lookup(name)?
+            .ok_or_else(|| FsError::file_not_present(self.display_path(), name.to_string()))
+    }
+
+    fn try_get_child(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        self.lookup(name)
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        Ok(self.lookup(name)?.is_some())
+    }
+
+    fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult {
+        self.check_writable()?;
+        check_segment(name)?;
+        check_segment(new_name)?;
+
+        let path = self.get_full_path();
+        let new_path = path.join(new_name);
+        fs::rename(path.join(name), &new_path)?;
+
+        let mut children = self.children.write().unwrap();
+        if children.remove(name).is_some() {
+            let child = self.new_child(Path::new(new_name), new_path.is_dir());
+            children.insert(new_name.to_string(), child);
+        }
+
+        Ok(())
+    }
+
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        self.check_writable()?;
+        check_segment(new_name)?;
+
+        let new_path = self.path.with_file_name(new_name);
+        if fs::symlink_metadata(&new_path).is_ok() {
+            return Err(FsError::already_exists(new_path.to_string_lossy().to_string()));
+        }
+        fs::rename(&self.path, &new_path)?;
+
+        // A root directory is named by its whole path.
+        let new_name = match self.parent {
+            Some(_) => PathBuf::from(new_name),
+            None => new_path.clone(),
+        };
+        let old_name = std::mem::replace(&mut self.name, new_name);
+        self.path = new_path;
+
+        // Cached children still carry the old path.
+        self.children.write().unwrap().clear();
+        self.scanned.store(false, Ordering::Release);
+
+        if let Some(parent) = self.parent.as_ref() {
+            let mut parent = parent.write().unwrap();
+            parent.drop_child(&old_name.to_string_lossy())?;
+            parent.cache_child(&self.name.to_string_lossy(), self.get())?;
+        }
+
+        Ok(())
+    }
+
+    fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+        check_segment(name)?;
+        self.check_vacant(name)?;
+
+        let file = self.new_child_file(Path::new(name), buffer_size, false);
+        self.children.write().unwrap().insert(name.to_string(), file.clone());
+        Ok(file)
+    }
+
+    fn new_dir(&mut self, name: &str) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+        check_segment(name)?;
+        self.check_vacant(name)?;
+
+        let path = self.path.join(name);
+        match sys::create_dir(&path, self.create_parents, self.dir_mode) {
+            Err(err) if err.kind() == IoErrorKind::AlreadyExists && path.is_dir() => {},
+            result => result?,
+        }
+
+        let dir = self.new_child_dir(Path::new(name), false);
+        self.children.write().unwrap().insert(name.to_string(), dir.clone());
+        Ok(dir)
+    }
+
+    /// Creates the file on disk straight away with `O_EXCL` semantics, so a name taken in the
+    /// meantime, even by another process, is never reused.
+    fn create_temp_file(&mut self, prefix: &str, ext: &str) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+
+        create_temp_child(prefix, ext, |name| {
+            check_segment(name)?;
+            let mut options = fs::OpenOptions::new();
+            options.read(true).write(true).create_new(true);
+            sys::set_mode(&mut options, self.file_mode);
+            let file = options.open(self.path.join(name))?;
+            let child = self.adopt_child(name, file, OpenMode::ReadWrite)?;
+            self.children.write().unwrap().insert(name.to_string(), child.clone());
+
+            Ok(child)
+        })
+    }
+
+    fn create_temp_dir(&mut self, prefix: &str) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+
+        create_temp_child(prefix, "", |name| {
+            check_segment(name)?;
+            sys::create_dir(&self.path.join(name), false, self.dir_mode)?;
+
+            let dir = self.new_child_dir(Path::new(name), false);
+            self.children.write().unwrap().insert(name.to_string(), dir.clone());
+            Ok(dir)
+        })
+    }
+
+    fn new_dir_all(&mut self, path: &str) -> FsResult<FilesystemObject> {
+        self.check_writable()?;
+        let segments = path_segments(path)?;
+
+        let mut full_path = self.get_full_path();
+        for segment in &segments {
+            full_path.push(segment);
+            match sys::create_dir(&full_path, false, self.dir_mode) {
+                Err(err) if err.kind() == IoErrorKind::AlreadyExists && full_path.is_dir() => {},
+                result => result.map_err(|err| FsError::PathSegment(segment.to_string(), Box::new(err.into())))?,
+            }
+        }
+
+        create_dir_segments(self, &segments)
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.write().unwrap().remove(name);
+        Ok(())
+    }
+
+    fn cache_child(&mut self, name: &str, child: FilesystemObject) -> FsResult {
+        self.children.write().unwrap().insert(name.to_string(), child);
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        self.scanned.store(false, Ordering::Release);
+        self.scan_if_needed()
+    }
+
+    /// Checks that the cached children are exactly the files and directories on disk, each of the
+    /// right kind. Files created but not written yet aren't on disk, so they count as stale.
+    fn validate_cache(&self) -> FsResult<bool> {
+        if !self.scanned.load(Ordering::Acquire) {
+            return Ok(true);
+        }
+
+        let children = self.children.read().unwrap();
+        if !self.exists() {
+            return Ok(children.is_empty());
+        }
+
+        let mut on_disk = 0;
+        for item in fs::read_dir(&self.path)? {
+            let item = item?;
+            let file_type = item.file_type()?;
+            if !file_type.is_dir() && !file_type.is_file() {
+                continue;
+            }
+
+            on_disk += 1;
+            match children.get(&*item.file_name().to_string_lossy()) {
+                Some(child) if child.read().unwrap().is_dir() == file_type.is_dir() => {},
+                _ => return Ok(false),
+            }
+        }
+
+        Ok(on_disk == children.len())
+    }
+
+    /// Drops cached children whose kind no longer matches what's on disk, then rescans. The
+    /// remaining children keep their handles.
+    fn repair_cache(&mut self) -> FsResult {
+        self.children.write().unwrap().retain(|name, child| {
+            match fs::symlink_metadata(self.path.join(name)) {
+                Ok(metadata) => child.read().unwrap().is_dir() == metadata.is_dir(),
+                Err(_) => false,
+            }
+        });
+
+        self.scan()
+    }
+
+    /// Removes the directory, which has to be empty.
+    fn delete(&mut self) -> FsResult {
+        self.check_writable()?;
+
+        fs::remove_dir(&self.path)?;
+        self.children.write().unwrap().clear();
+        if let Some(parent) = self.parent.as_ref() {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the directory and everything under it. Pending writes to files in the tree are
+    /// discarded.
+    fn delete_all(&mut self) -> FsResult {
+        self.check_writable()?;
+
+        let result = fs::remove_dir_all(&self.path);
+        // Even a partial removal leaves the cached handles stale.
+        self.invalidate();
+        result?;
+
+        if let Some(parent) = self.parent.as_ref() {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        let children = std::mem::take(&mut *self.children.write().unwrap());
+        self.scanned.store(false, Ordering::Release);
+        for child in children.into_values() {
+            child.write().unwrap().invalidate();
+        }
+    }
+}
+
+impl fmt::Debug for PhysicalFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhysicalFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("open", &self.is_open())
+            .finish()
+    }
+}
+
+impl Drop for PhysicalFile {
+    fn drop(&mut self) {
+        // `close` swallows errors, since `drop` can't report them either.
+        if self.flush_on_drop {
+            self.close();
+        }
+    }
+}
+
+impl Read for PhysicalFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        #[cfg(feature="mmap")]
+        if self.mmap_reads {
+            let available = self.mapped()?;
+            let byte_count = available.len().min(buf.len());
+            buf[..byte_count].copy_from_slice(&available[..byte_count]);
+            self.consume(byte_count);
+
+            return Ok(byte_count);
+        }
+
+        if self.dirty || self.cursor >= self.buf_filled {
+            self.fill_buffer()?;
+        }
+
+        let byte_count = (self.buf_filled - self.cursor).min(buf.len());
+        let end = self.cursor + byte_count;
+
+        buf[..byte_count].copy_from_slice(&self.buffer[self.cursor..end]);
+        self.cursor = end;
+
+        Ok(byte_count)
+    }
+}
+
+impl BufRead for PhysicalFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        #[cfg(feature="mmap")]
+        if self.mmap_reads {
+            return self.mapped();
+        }
+
+        if self.dirty || self.cursor >= self.buf_filled {
+            self.fill_buffer()?;
+        }
+
+        Ok(&self.buffer[self.cursor..self.buf_filled])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Mapped reads leave the buffer empty and advance the OS cursor instead.
+        #[cfg(feature="mmap")]
+        if self.mmap_reads {
+            if let Some(file) = self.file.as_mut() {
+                let _ = file.seek(SeekFrom::Current(amt as i64));
+            }
+            return;
+        }
+
+        self.cursor = (self.cursor + amt).min(self.buf_filled);
+    }
+}
+
+impl Seek for PhysicalFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.check_handle()?;
+        self.flush()?;
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+
+        // The OS cursor sits past whatever is still unread in the buffer.
+        let pos = match pos {
+            SeekFrom::Current(offset) => SeekFrom::Current(offset - (self.buf_filled - self.cursor) as i64),
+            pos => pos,
+        };
+
+        self.buf_filled = 0;
+        self.cursor = 0;
+
+        self.file.as_mut().unwrap().seek(pos)
+    }
+}
+
+impl Write for PhysicalFile {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        if self.readonly {
+            return Err(read_only_io(self.display_path()));
+        }
+
+        self.begin_write()?;
+        // Spill before taking on `buf`, so an error means none of it was accepted.
+        if self.buffer.len() + buf.len() > self.write_high_water() {
+            self.spill()?;
+        }
+        self.text.push(buf, &mut self.buffer);
+
+        Ok(buf.len())
+    }
+
+    /// Also writes out whatever text normalization is holding back, so it lands where it was
+    /// written even if the file is seeked next.
+    fn flush(&mut self) -> IoResult<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if self.readonly {
+            return Err(read_only_io(self.display_path()));
+        }
+
+        self.text.release(&mut self.buffer);
+        if self.atomic {
+            self.flush_atomic(&self.get_full_path())?;
+            // Any open handle still refers to the file that was just replaced.
+            self.file = None;
+            self.write_pos += self.buffer.len() as u64;
+            self.buffer.clear();
+        } else {
+            self.write_through(self.buffer.len())?;
+        }
+
+        let end = self.write_pos;
+        self.dirty = false;
+
+        if self.file.is_none() {
+            self.open_handle(false)?;
+        }
+        self.file.as_mut().unwrap().seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for PhysicalFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn revalidate(&self) -> FsResult<FsKind> {
+        revalidate_kind(&self.path, self.kind())
+    }
+
+    /// Always false once the file was deleted through this object, even if something has since
+    /// created another file at the path.
+    fn exists(&self) -> bool {
+        !self.deleted && self.get_full_path().is_file()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Ok(fs::metadata(self.get_full_path())?.len() as usize)
+    }
+
+    fn truncate(&mut self, len: usize) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+        if self.deleted {
+            return Err(FsError::deleted(self.display_path()));
+        }
+
+        self.flush()?;
+        let position = self.logical_position()?;
+        match self.file.as_ref() {
+            Some(file) if self.open_mode != OpenMode::Read => file.set_len(len as u64)?,
+            _ => fs::OpenOptions::new().write(true).open(self.get_full_path())?.set_len(len as u64)?,
+        }
+
+        // Drop buffered bytes that may no longer exist, and put the handle back where the caller
+        // was.
+        self.buf_filled = 0;
+        self.cursor = 0;
+        self.drop_mapping();
+        if let Some(file) = self.file.as_mut() {
+            file.seek(SeekFrom::Start(position))?;
+        }
+
+        Ok(())
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Ok(self.logical_position()?)
+    }
+
+    /// Reads what's on disk, so pending writes only show up once flushed.
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        if self.deleted {
+            return Err(FsError::deleted(self.display_path()));
+        }
+
+        #[cfg(feature="mmap")]
+        if self.mmap_reads {
+            let mut mapping = self.mapping.lock().unwrap();
+            if mapping.is_none() {
+                *mapping = Some(map_file(&self.path)?);
+            }
+
+            let map = mapping.as_ref().unwrap();
+            let start = offset.min(map.len() as u64) as usize;
+            return Ok(map[start..start + len.min(map.len() - start)].to_vec());
+        }
+
+        // Direct I/O handles only take aligned reads, and positional reads move the cursor on
+        // Windows, so those go through a handle of their own.
+        let own;
+        let file = match self.file.as_ref() {
+            Some(file) if cfg!(unix) && self.direct.is_none() && matches!(self.open_mode, OpenMode::Read | OpenMode::ReadWrite) => file,
+            _ => {
+                own = fs::File::open(&self.path)?;
+                &own
+            },
+        };
+
+        Ok(sys::read_at(file, offset, len)?)
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(fs::metadata(&self.path)?.modified()?)
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn canonicalize(&self) -> FsResult<PathBuf> {
+        Ok(fs::canonicalize(&self.path)?)
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        let (device, inode) = sys::file_id(&self.path)?;
+        Ok(ObjectId::Physical { device, inode })
+    }
+
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        if readonly {
+            self.flush()?;
+        }
+        self.readonly = readonly;
+
+        Ok(())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let writable = !self.readonly;
+        Capabilities{
+            writable,
+            can_create: writable,
+            can_delete: writable,
+            can_rename: writable,
+            has_timestamps: true,
+            has_permissions: true,
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok(sys::fs_type(&self.path)?)
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Ok(sys::disk_space(&self.path)?.0)
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Ok(sys::disk_space(&self.path)?.1)
+    }
+
+    fn physical_path(&self) -> Option<PathBuf> {
+        Some(self.path.clone())
+    }
+
+    fn move_to(&mut self, new_dir: FilesystemObject) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+        self.flush()?;
+
+        let dir_path = {
+            let new_dir = new_dir.read().unwrap();
+            if new_dir.readonly() {
+                return Err(FsError::read_only(new_dir.get_full_path().to_string_lossy().to_string()));
+            }
+            new_dir.physical_path()
+        };
+        // Only a physical directory can take a plain rename; wrappers get their checks through a copy.
+        let Some(dir_path) = dir_path else {
+            return move_by_copy(self, &new_dir);
+        };
+
+        let new_path = dir_path.join(&self.name);
+        match fs::rename(&self.path, &new_path) {
+            Err(error) if error.kind() == IoErrorKind::CrossesDevices => return move_by_copy(self, &new_dir),
+            result => result?,
+        }
+        self.path = new_path;
+
+        let old_parent = std::mem::replace(&mut self.parent, new_dir.clone());
+        old_parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        new_dir.write().unwrap().scan()?;
+
+        Ok(())
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        if self.deleted {
+            return Err(self.deleted_error());
+        }
+        if self.file.is_some() {
+            self.close();
+        }
+
+        self.open_handle(true)
+    }
+
+    fn as_std_file(&mut self) -> Option<&fs::File> {
+        // Finding the position seeks in place, which flushes, opens, and lines the handle's cursor up.
+        self.stream_position().ok()?;
+        self.file.as_ref()
+    }
+
+    /// False once the file was deleted, whether through this object or by something else while
+    /// it was open. Deletion by something else is only noticed here, by checking the handle;
+    /// reads and writes until then go on using it, and the first after it errors with
+    /// [`FsError::Deleted`].
+    fn is_open(&self) -> bool {
+        if self.unlinked.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match self.file.as_ref().map(fs::File::metadata) {
+            Some(Ok(metadata)) if sys::is_unlinked(&metadata, &self.path) => {
+                self.unlinked.store(true, Ordering::Relaxed);
+                false
+            },
+            Some(Ok(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        self.sync(false)
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        self.sync(true)
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let duplicate = Self::new(&self.name, self.path.clone(), self.parent.clone(), self.buffer_size, self.readonly);
+        {
+            let mut duplicate = duplicate.write().unwrap();
+            duplicate.mode = self.mode;
+            duplicate.retry = self.retry.clone();
+            duplicate.atomic = self.atomic;
+            duplicate.flush_on_drop = self.flush_on_drop;
+            duplicate.flush_threshold = self.flush_threshold;
+            duplicate.direct = self.direct.as_ref().map(|_| AlignedBuffer::new(self.buffer_size));
+            #[cfg(feature="mmap")]
+            { duplicate.mmap_reads = self.mmap_reads; }
+        }
+
+        Ok(duplicate)
+    }
+
+    fn close(&mut self) {
+        // Flushing after the file was deleted underneath would only create it again.
+        if self.file.is_some() && !self.is_open() {
+            self.release();
+            return;
+        }
+
+        let mut tail = Vec::new();
+        self.text.finish(&mut tail);
+        if !tail.is_empty() && self.begin_write().is_ok() {
+            self.buffer.append(&mut tail);
+        }
+
+        // `close` can't report errors, so a failed flush only loses the pending bytes.
+        let _ = self.flush();
+
+        self.file = None;
+        self.buffer.clear();
+        self.buf_filled = 0;
+        self.cursor = 0;
+        self.drop_mapping();
+    }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// A file that hasn't been written out yet is only renamed here; the first flush creates it
+    /// under the new name.
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+        if self.deleted {
+            return Err(FsError::deleted(self.display_path()));
+        }
+        check_segment(new_name)?;
+
+        let new_path = self.path.with_file_name(new_name);
+        if fs::symlink_metadata(&new_path).is_ok() {
+            return Err(FsError::already_exists(new_path.to_string_lossy().to_string()));
+        }
+        if self.exists() {
+            fs::rename(&self.path, &new_path)?;
+        }
+
+        let old_name = std::mem::replace(&mut self.name, PathBuf::from(new_name));
+        self.path = new_path;
+
+        let mut parent = self.parent.write().unwrap();
+        parent.drop_child(&old_name.to_string_lossy())?;
+        parent.cache_child(new_name, self.get())
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Takes effect at the next refill, so unread buffered bytes and pending writes are left as
+    /// they are.
+    fn set_buffer_size(&mut self, size: usize) {
+        self.buffer_size = size;
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Removes the file, discarding pending writes.
+    fn delete(&mut self) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        self.release();
+
+        fs::remove_file(&self.path)?;
+        self.deleted = true;
+        self.parent.write().unwrap().drop_child(&self.name.to_string_lossy())
+    }
+
+    fn invalidate(&mut self) {
+        self.release();
+        self.deleted = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::LineEnding;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn open_locked_exclusive_holds_the_lock_until_dropped() {
+        let tmp = TempDir::new("lock");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+
+        let first = dir.write().unwrap().open_locked_exclusive("app.lock").unwrap();
+        let second = dir.write().unwrap().open_locked_exclusive("app.lock");
+        assert!(matches!(second, Err(FsError::Locked(..))), "{second:?}");
+
+        // Another process's attempt, simulated with a handle of its own.
+        let other = fs::File::open(tmp.join("app.lock")).unwrap();
+        assert!(matches!(other.try_lock(), Err(fs::TryLockError::WouldBlock)));
+
+        drop(first);
+        dir.write().unwrap().open_locked_exclusive("app.lock").unwrap();
+    }
+
+    #[test]
+    fn deleted_through_the_api() {
+        let tmp = TempDir::new("deleted-api");
+        fs::write(tmp.join("gone.txt"), b"contents").unwrap();
+        let file = PhysicalFile::from_path(tmp.join("gone.txt"), 4);
+        let mut file = file.write().unwrap();
+
+        let mut buf = [0; 2];
+        file.read_exact(&mut buf).unwrap();
+        file.delete().unwrap();
+
+        assert!(!file.is_open());
+        assert!(!file.exists());
+        assert!(matches!(file.read_at(0, 2), Err(FsError::Deleted(..))));
+        let error = FsError::from(file.read(&mut buf).unwrap_err());
+        assert!(matches!(error, FsError::Deleted(..)), "{error:?}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn deleted_underneath() {
+        let tmp = TempDir::new("deleted-underneath");
+        fs::write(tmp.join("gone.txt"), b"contents").unwrap();
+        let file = PhysicalFile::from_path(tmp.join("gone.txt"), 4);
+        let mut file = file.write().unwrap();
+
+        let mut buf = [0; 2];
+        file.read_exact(&mut buf).unwrap();
+        assert!(file.is_open());
+        fs::remove_file(tmp.join("gone.txt")).unwrap();
+
+        // Unnoticed until checked, the open handle reads on.
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"nt");
+
+        assert!(!file.is_open());
+        assert!(!file.exists());
+        let error = FsError::from(file.read(&mut buf).unwrap_err());
+        assert!(matches!(error, FsError::Deleted(..)), "{error:?}");
+        // Nothing was written back on close.
+        file.close();
+        assert!(!tmp.join("gone.txt").exists());
+    }
+
+
+    #[test]
+    fn temp_files_are_cached() {
+        let tmp = TempDir::new("temp-cached");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+
+        let file = dir.write().unwrap().create_temp_file("scratch", "tmp").unwrap();
+        let name = file.read().unwrap().name().to_string_lossy().to_string();
+        assert!(Arc::ptr_eq(&file, &dir.read().unwrap().get_child(&name).unwrap()));
+    }
+
+    #[test]
+    fn text_normalization_survives_flush_and_seek() {
+        let tmp = TempDir::new("text-flush");
+        let file = PhysicalFile::from_path(tmp.join("out.txt"), 0);
+        let mut file = file.write().unwrap();
+        file.set_text_normalization(TextPolicy { line_ending: LineEnding::Lf, trim_trailing_whitespace: true });
+
+        file.write_all(b"one \r").unwrap();
+        file.flush().unwrap();
+        file.write_all(b"\ntwo\r\nthree\t").unwrap();
+        let end = file.seek(SeekFrom::End(0)).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"ONE").unwrap();
+        file.seek(SeekFrom::Start(end)).unwrap();
+        file.write_all(b"\r\nfour  ").unwrap();
+        file.close();
+
+        assert_eq!(fs::read_to_string(tmp.join("out.txt")).unwrap(), "ONE\ntwo\nthree\t\nfour");
+    }
+
+
+    #[test]
+    fn atomic_flush_never_shows_a_torn_file() {
+        let tmp = TempDir::new("atomic");
+        let path = tmp.join("config.bin");
+        let old = vec![b'o'; 1 << 20];
+        let new = vec![b'n'; 4 << 20];
+        fs::write(&path, &old).unwrap();
+
+        let file = PhysicalFile::from_path(&path, 64 * 1024);
+        let mut file = file.write().unwrap();
+        file.set_atomic(true);
+        for chunk in new.chunks(64 * 1024) {
+            file.write_all(chunk).unwrap();
+        }
+        assert_eq!(fs::read(&path).unwrap(), old);
+
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (done, path) = (done.clone(), path.clone());
+            std::thread::spawn(move || {
+                while !done.load(Ordering::Acquire) {
+                    let contents = fs::read(&path).unwrap();
+                    let torn = contents.first() != contents.last() || ![1 << 20, 4 << 20].contains(&contents.len());
+                    assert!(!torn, "read {} bytes mid-flush", contents.len());
+                }
+            })
+        };
+        file.flush().unwrap();
+        done.store(true, Ordering::Release);
+        reader.join().unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), new);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn concurrent_atomic_flushes_use_separate_temp_files() {
+        let tmp = TempDir::new("atomic-concurrent");
+        let path = tmp.join("shared.bin");
+        fs::write(&path, b"").unwrap();
+
+        let writers: Vec<_> = (0..8u8).map(|i| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let file = PhysicalFile::from_path(&path, 0);
+                let mut file = file.write().unwrap();
+                file.set_atomic(true);
+                for _ in 0..20 {
+                    file.seek(SeekFrom::Start(0)).unwrap();
+                    file.write_all(&[b'a' + i; 4096]).unwrap();
+                    file.flush().unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // Whoever renamed last wins, with their whole block.
+        let contents = fs::read(&path).unwrap();
+        assert_eq!(contents.len(), 4096);
+        assert!(contents.iter().all(|&byte| byte == contents[0]));
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    #[test]
+    fn direct_io_needs_alignment() {
+        let tmp = TempDir::new("direct");
+        let path = tmp.join("data.bin");
+        let contents: Vec<u8> = (0..2 * DIRECT_IO_ALIGNMENT).map(|i| i as u8).collect();
+        fs::write(&path, &contents).unwrap();
+
+        let file = PhysicalFile::from_path(&path, 100);
+        let mut file = file.write().unwrap();
+        assert!(matches!(file.open_direct(), Err(FsError::Generic(..))));
+
+        file.set_buffer_size(DIRECT_IO_ALIGNMENT);
+        file.set_open_mode(OpenMode::ReadWrite);
+        file.open_direct().unwrap();
+
+        let mut block = vec![0; DIRECT_IO_ALIGNMENT];
+        file.read_exact(&mut block).unwrap();
+        assert_eq!(block, contents[..DIRECT_IO_ALIGNMENT]);
+
+        file.write_all(&vec![b'x'; DIRECT_IO_ALIGNMENT]).unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[DIRECT_IO_ALIGNMENT..], vec![b'x'; DIRECT_IO_ALIGNMENT]);
+
+        file.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(file.read(&mut block).unwrap_err().kind(), IoErrorKind::InvalidInput);
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"short").unwrap();
+        assert_eq!(file.flush().unwrap_err().kind(), IoErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn readonly_directories_reject_changes() {
+        let tmp = TempDir::new("readonly");
+        tmp.write("a.txt", "a");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let file = dir.read().unwrap().get_child("a.txt").unwrap();
+        dir.write().unwrap().set_readonly(true).unwrap();
+
+        let mut dir = dir.write().unwrap();
+        assert!(dir.readonly());
+        assert!(matches!(dir.new_file("b.txt", 0), Err(FsError::ReadOnly(..))));
+        assert!(matches!(dir.child_rename("a.txt", "c.txt"), Err(FsError::ReadOnly(..))));
+
+        let mut file = file.write().unwrap();
+        assert!(file.readonly());
+        let error = FsError::from(file.write(b"changed").unwrap_err());
+        assert!(matches!(error, FsError::ReadOnly(..)), "{error:?}");
+        assert!(matches!(file.delete(), Err(FsError::ReadOnly(..))));
+        assert_eq!(fs::read_to_string(tmp.join("a.txt")).unwrap(), "a");
+        assert!(!tmp.join("b.txt").exists());
+    }
+
+    #[test]
+    fn read_only_directories_drop_the_mutating_capabilities() {
+        let tmp = TempDir::new("capabilities");
+        tmp.write("a.txt", "a");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let file = dir.read().unwrap().get_child("a.txt").unwrap();
+        let writable = Capabilities{
+            writable: true,
+            can_create: true,
+            can_delete: true,
+            can_rename: true,
+            has_timestamps: true,
+            has_permissions: true,
+        };
+        assert_eq!(dir.read().unwrap().capabilities(), writable);
+        assert_eq!(file.read().unwrap().capabilities(), writable);
+
+        dir.write().unwrap().set_readonly(true).unwrap();
+        let read_only = Capabilities{ has_timestamps: true, has_permissions: true, ..Capabilities::default() };
+        assert_eq!(dir.read().unwrap().capabilities(), read_only);
+        assert_eq!(file.read().unwrap().capabilities(), read_only);
+    }
+
+    #[test]
+    fn debug_output_counts_children_without_listing_them() {
+        let tmp = TempDir::new("debug");
+        tmp.write("sub/hidden-name.txt", "x");
+        tmp.write("a.txt", "x");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+
+        let before = format!("{:?}", dir.read().unwrap());
+        assert!(before.starts_with("PhysicalDirectory"), "{before}");
+        assert!(before.contains("scanned: false"), "{before}");
+        assert!(before.contains("cached_children: 0"), "{before}");
+
+        dir.write().unwrap().scan().unwrap();
+        let after = format!("{:?}", dir.read().unwrap());
+        assert!(after.contains("scanned: true"), "{after}");
+        assert!(after.contains("cached_children: 2"), "{after}");
+        assert!(!after.contains("hidden-name"), "{after}");
+
+        let file = PhysicalFile::from_path(tmp.join("a.txt"), 4096);
+        let debug = format!("{:?}", file.read().unwrap());
+        assert!(debug.starts_with("PhysicalFile"), "{debug}");
+        assert!(debug.contains("a.txt"), "{debug}");
+        assert!(debug.contains("open: false"), "{debug}");
+    }
+
+    #[test]
+    fn large_writes_spill_past_the_high_water_mark() {
+        let tmp = TempDir::new("spill");
+        let path = tmp.join("big.bin");
+        fs::write(&path, b"").unwrap();
+        let data: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+
+        let file = PhysicalFile::from_path(&path, 4096);
+        let mut file = file.write().unwrap();
+        for chunk in data.chunks(1000) {
+            file.write_all(chunk).unwrap();
+            assert!(file.buffer.len() <= 4096, "{} bytes pending", file.buffer.len());
+        }
+        assert!(fs::metadata(&path).unwrap().len() >= data.len() as u64 - 4096);
+
+        file.flush().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), data);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn open_child_at_stays_on_the_original_directory() {
+        let tmp = TempDir::new("openat");
+        tmp.write("data/a.txt", "hello");
+        tmp.write("data/b.txt", "real");
+        let dir = PhysicalDirectory::from_path(tmp.join("data"));
+
+        let mut contents = String::new();
+        let a = dir.read().unwrap().open_child_at("a.txt", OpenMode::Read).unwrap();
+        a.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello");
+
+        // The directory handle from the first open is reused, so a directory swapped in under
+        // the old path isn't looked at.
+        fs::rename(tmp.join("data"), tmp.join("moved")).unwrap();
+        tmp.write("data/b.txt", "decoy");
+        contents.clear();
+        let b = dir.read().unwrap().open_child_at("b.txt", OpenMode::Read).unwrap();
+        b.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "real");
+
+        std::os::unix::fs::symlink(tmp.join("moved/a.txt"), tmp.join("moved/link.txt")).unwrap();
+        assert!(dir.read().unwrap().open_child_at("link.txt", OpenMode::Read).is_err());
+        assert!(dir.read().unwrap().open_child_at("../a.txt", OpenMode::Read).is_err());
+    }
+
+    #[test]
+    fn append_mode_keeps_existing_content() {
+        let tmp = TempDir::new("append");
+        let path = tmp.join("log.txt");
+        fs::write(&path, "first\n").unwrap();
+
+        let file = PhysicalFile::from_path(&path, 4096);
+        let mut file = file.write().unwrap();
+        file.set_open_mode(OpenMode::Append);
+        file.open().unwrap();
+        file.write_all(b"second\n").unwrap();
+        file.flush().unwrap();
+        file.write_all(b"third\n").unwrap();
+        file.flush().unwrap();
+        file.close();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\nthird\n");
+
+        file.set_open_mode(OpenMode::Write);
+        file.open().unwrap();
+        file.write_all(b"fresh\n").unwrap();
+        file.flush().unwrap();
+        file.close();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fresh\n");
+    }
+
+    #[test]
+    fn truncate_shrinks_the_file_and_drops_stale_buffers() {
+        let tmp = TempDir::new("truncate");
+        let path = tmp.join("data.txt");
+        fs::write(&path, "0123456789").unwrap();
+
+        let file = PhysicalFile::from_path(&path, 4096);
+        let mut file = file.write().unwrap();
+        file.open().unwrap();
+        let mut head = [0; 2];
+        file.read_exact(&mut head).unwrap();
+
+        file.truncate(5).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "01234");
+        assert_eq!(file.size().unwrap(), 5);
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "234");
+
+        file.truncate(0).unwrap();
+        assert_eq!(file.size().unwrap(), 0);
+        file.seek(SeekFrom::Start(0)).unwrap();
+        rest.clear();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn hard_links_share_an_inode() {
+        let tmp = TempDir::new("inodes");
+        tmp.write("original.txt", "shared");
+        tmp.write("separate.txt", "shared");
+        fs::hard_link(tmp.join("original.txt"), tmp.join("link.txt")).unwrap();
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let inodes: HashMap<String, u64> = dir.read().unwrap().children_with_inodes().unwrap()
+            .into_iter()
+            .map(|(child, inode)| (child.read().unwrap().name().to_string_lossy().to_string(), inode))
+            .collect();
+        assert_eq!(inodes.len(), 3);
+        assert_eq!(inodes["original.txt"], inodes["link.txt"]);
+        assert_ne!(inodes["original.txt"], inodes["separate.txt"]);
+    }
+
+    #[test]
+    fn revalidate_detects_a_changed_kind() {
+        let tmp = TempDir::new("revalidate");
+        tmp.write("swap/inner.txt", "x");
+        tmp.write("file.txt", "x");
+        let dir = PhysicalDirectory::from_path(tmp.join("swap"));
+        let file = PhysicalFile::from_path(tmp.join("file.txt"), 0);
+        assert_eq!(dir.read().unwrap().revalidate().unwrap(), FsKind::Directory);
+        assert_eq!(file.read().unwrap().revalidate().unwrap(), FsKind::File);
+
+        fs::remove_dir_all(tmp.join("swap")).unwrap();
+        tmp.write("swap", "now a file");
+        fs::remove_file(tmp.join("file.txt")).unwrap();
+        fs::create_dir(tmp.join("file.txt")).unwrap();
+
+        let result = dir.read().unwrap().revalidate();
+        assert!(matches!(result, Err(FsError::NotADirectory(_, FsKind::File, ..))), "{result:?}");
+        let result = file.read().unwrap().revalidate();
+        assert!(matches!(result, Err(FsError::NotAFile(_, FsKind::Directory, ..))), "{result:?}");
+
+        fs::remove_file(tmp.join("swap")).unwrap();
+        let result = dir.read().unwrap().revalidate();
+        assert!(matches!(result, Err(FsError::FileNotPresent(..))), "{result:?}");
+    }
+
+    #[test]
+    fn creating_a_taken_name_fails_unless_overwriting() {
+        let tmp = TempDir::new("exists");
+        tmp.write("on-disk.txt", "keep");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+
+        dir.new_file("a.txt", 0).unwrap();
+        assert!(matches!(dir.new_file("a.txt", 0), Err(FsError::AlreadyExists(..))));
+        assert!(matches!(dir.new_file("on-disk.txt", 0), Err(FsError::AlreadyExists(..))));
+        dir.new_dir("sub").unwrap();
+        assert!(tmp.join("sub").is_dir());
+        assert!(matches!(dir.new_dir("sub"), Err(FsError::AlreadyExists(..))));
+        assert!(matches!(dir.new_dir("a.txt"), Err(FsError::AlreadyExists(..))));
+        assert_eq!(fs::read_to_string(tmp.join("on-disk.txt")).unwrap(), "keep");
+
+        dir.set_overwrite(true);
+        dir.new_dir("sub").unwrap();
+        let file = dir.new_file("on-disk.txt", 0).unwrap();
+        file.write().unwrap().write_all(b"replaced").unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("on-disk.txt")).unwrap(), "replaced");
+    }
+
+    #[test]
+    fn new_dir_creates_directories_files_can_go_in() {
+        let tmp = TempDir::new("new-dir");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let outer = dir.write().unwrap().new_dir("outer").unwrap();
+        let inner = outer.write().unwrap().new_dir("inner").unwrap();
+        assert!(inner.read().unwrap().exists());
+
+        let file = inner.write().unwrap().new_file("note.txt", 0).unwrap();
+        file.write().unwrap().write_all(b"nested").unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("outer/inner/note.txt")).unwrap(), "nested");
+        let mut contents = String::new();
+        file.write().unwrap().seek(SeekFrom::Start(0)).unwrap();
+        file.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "nested");
+
+        let missing = PhysicalDirectory::from_path(tmp.join("missing/parent"));
+        assert!(missing.write().unwrap().new_dir("child").is_err());
+        missing.write().unwrap().set_create_parents(true);
+        missing.write().unwrap().new_dir("child").unwrap();
+        assert!(tmp.join("missing/parent/child").is_dir());
+    }
+
+    #[test]
+    fn read_at_leaves_the_cursor_alone() {
+        let tmp = TempDir::new("read-at");
+        tmp.write("data.bin", "0123456789abcdef");
+        let file = PhysicalFile::from_path(tmp.join("data.bin"), 4);
+        let mut file = file.write().unwrap();
+        file.open().unwrap();
+
+        let mut head = [0; 3];
+        file.read_exact(&mut head).unwrap();
+        assert_eq!(file.read_at(2, 6).unwrap(), b"234567");
+        assert_eq!(file.read_at(4, 6).unwrap(), b"456789");
+        assert_eq!(file.read_at(14, 10).unwrap(), b"ef");
+        assert!(file.read_at(20, 4).unwrap().is_empty());
+
+        assert_eq!(file.position().unwrap(), 3);
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "3456789abcdef");
+    }
+
+    #[test]
+    fn as_std_file_lends_an_up_to_date_handle() {
+        let tmp = TempDir::new("as-std-file");
+        tmp.write("data.txt", "hello world");
+        let file = PhysicalFile::from_path(tmp.join("data.txt"), 4096);
+        let mut file = file.write().unwrap();
+        file.set_open_mode(OpenMode::ReadWrite);
+
+        assert!(!file.is_open());
+        assert!(file.as_std_file().is_some());
+        assert!(file.is_open());
+
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(b"WORLD").unwrap();
+        assert_eq!(fs::read_to_string(tmp.join("data.txt")).unwrap(), "hello world");
+        let mut handle = file.as_std_file().unwrap();
+        assert_eq!(handle.stream_position().unwrap(), 11);
+        handle.rewind().unwrap();
+        let mut contents = String::new();
+        handle.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello WORLD");
+
+        // A buffered read leaves the handle's cursor past the logical position.
+        file.seek(SeekFrom::Start(2)).unwrap();
+        let mut byte = [0; 1];
+        file.read_exact(&mut byte).unwrap();
+        let mut handle = file.as_std_file().unwrap();
+        assert_eq!(handle.stream_position().unwrap(), 3);
+        let mut rest = String::new();
+        handle.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "lo WORLD");
+
+        let memory = crate::MemoryFile::from_bytes("memory.txt", b"in memory");
+        assert!(memory.write().unwrap().as_std_file().is_none());
+    }
+
+    #[cfg(feature="watch")]
+    #[test]
+    fn watched_changes_are_reported_and_invalidate_the_listing() {
+        let tmp = TempDir::new("watch");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let events = dir.read().unwrap().watch().unwrap();
+        assert_eq!(dir.read().unwrap().get_children().unwrap().len(), 0);
+
+        tmp.write("new.txt", "hello");
+        let created = loop {
+            match events.recv_timeout(std::time::Duration::from_secs(5)).unwrap() {
+                FsEvent::Created(path) => break path,
+                _ => continue,
+            }
+        };
+        assert_eq!(created.file_name().unwrap(), "new.txt");
+        assert_eq!(dir.read().unwrap().get_children().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn batch_writes_land_in_full() {
+        let tmp = TempDir::new("batch");
+        tmp.write("b.txt", "old contents that are longer");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        let stale = dir.get_child("b.txt").unwrap();
+
+        let big = vec![b'x'; 100_000];
+        dir.write_batch_durable(&[("a.txt", b"alpha"), ("b.txt", b"beta"), ("big.bin", &big)]).unwrap();
+        assert_eq!(fs::read(tmp.join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(fs::read(tmp.join("b.txt")).unwrap(), b"beta");
+        assert_eq!(fs::read(tmp.join("big.bin")).unwrap(), big);
+        assert!(!Arc::ptr_eq(&stale, &dir.get_child("b.txt").unwrap()));
+
+        let result = dir.write_batch_durable(&[("c.txt", b""), ("../escape.txt", b"")]);
+        assert!(result.is_err());
+        assert!(!tmp.join("c.txt").exists());
+    }
+
+    #[test]
+    fn fs_type_names_the_filesystem() {
+        let tmp = TempDir::new("fs-type");
+        tmp.write("file.txt", "");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let fs_type = dir.read().unwrap().fs_type().unwrap();
+        assert!(!fs_type.is_empty());
+        assert!(!fs_type.contains('\0'), "{fs_type:?}");
+
+        let file = dir.read().unwrap().get_child("file.txt").unwrap();
+        assert_eq!(file.read().unwrap().fs_type().unwrap(), fs_type);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_link_follows_to_a_live_object() {
+        let tmp = TempDir::new("resolve-link");
+        tmp.write("data/target.txt", "through the link");
+        tmp.write("data/nested/inner.txt", "");
+        std::os::unix::fs::symlink("data/target.txt", tmp.join("file-link")).unwrap();
+        std::os::unix::fs::symlink(tmp.join("data"), tmp.join("dir-link")).unwrap();
+        std::os::unix::fs::symlink("missing.txt", tmp.join("broken")).unwrap();
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let dir = dir.read().unwrap();
+
+        assert_eq!(dir.read_link("file-link").unwrap(), Path::new("data/target.txt"));
+        let file = dir.resolve_link("file-link").unwrap();
+        assert_eq!(file.read().unwrap().kind(), FsKind::File);
+        let mut contents = String::new();
+        file.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "through the link");
+
+        let linked = dir.resolve_link("dir-link").unwrap();
+        assert!(linked.read().unwrap().get_child("nested").unwrap().read().unwrap().has_child("inner.txt").unwrap());
+
+        let result = dir.resolve_link("broken");
+        assert!(matches!(result, Err(FsError::FileNotPresent(..))), "{result:?}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn created_modes_respect_the_umask() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Read rather than set, as the umask is shared with tests running in parallel.
+        let status = fs::read_to_string("/proc/self/status").unwrap();
+        let umask = status.lines()
+            .find_map(|line| line.strip_prefix("Umask:"))
+            .map(|mask| u32::from_str_radix(mask.trim(), 8).unwrap())
+            .unwrap();
+        let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let tmp = TempDir::new("umask");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        let file = dir.new_file("default.txt", 0).unwrap();
+        file.write().unwrap().write_all(b"x").unwrap();
+        file.write().unwrap().flush().unwrap();
+        dir.new_dir("default-dir").unwrap();
+        assert_eq!(mode(tmp.join("default.txt")), 0o666 & !umask);
+        assert_eq!(mode(tmp.join("default-dir")), 0o777 & !umask);
+
+        dir.set_file_mode(Some(0o640));
+        dir.set_dir_mode(Some(0o750));
+        let file = dir.new_file("private.txt", 0).unwrap();
+        file.write().unwrap().write_all(b"x").unwrap();
+        file.write().unwrap().flush().unwrap();
+        dir.new_dir("private-dir").unwrap();
+        assert_eq!(mode(tmp.join("private.txt")), 0o640 & !umask);
+        assert_eq!(mode(tmp.join("private-dir")), 0o750 & !umask);
+    }
+
+    #[test]
+    fn disk_space_is_reported_for_the_temp_dir() {
+        let tmp = TempDir::new("space");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let dir = dir.read().unwrap();
+        let (free, total) = (dir.free_space().unwrap(), dir.total_space().unwrap());
+        assert!(free > 0);
+        assert!(total >= free);
+    }
+
+    #[cfg(feature="mmap")]
+    #[test]
+    fn mmap_windows_cover_the_file() {
+        let tmp = TempDir::new("mmap-windows");
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        tmp.write("big.bin", &data);
+        let file = PhysicalFile::from_path(tmp.join("big.bin"), 0);
+        let mut file = file.write().unwrap();
+
+        let plain: Vec<u8> = file.mmap_windows(8192, 0).unwrap()
+            .flat_map(|window| window.unwrap().to_vec())
+            .collect();
+        assert_eq!(plain, data);
+
+        let mut joined = Vec::new();
+        let mut count = 0;
+        for window in file.mmap_windows(10_000, 1000).unwrap() {
+            let window = window.unwrap();
+            assert_eq!(window.offset(), count * 9000);
+            // Everything after the first window repeats the previous one's last 1000 bytes.
+            let seen = joined.len() - window.offset() as usize;
+            assert!(count == 0 || seen == 1000);
+            joined.extend_from_slice(&window[seen..]);
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert_eq!(joined, data);
+
+        assert!(file.mmap_windows(100, 100).is_err());
+    }
+
+    #[cfg(feature="mmap")]
+    #[test]
+    fn mapped_reads_keep_the_position_across_writes_and_closes() {
+        let tmp = TempDir::new("mmap-reads");
+        let data: Vec<u8> = (0..1024u32).map(|i| (i % 256) as u8).collect();
+        tmp.write("data.bin", &data);
+        let file = PhysicalFile::from_path(tmp.join("data.bin"), 16);
+        let mut file = file.write().unwrap();
+        let mut chunk = [0; 4];
+
+        // Switching over mid-read picks up where the buffered reads left off.
+        file.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [0, 1, 2, 3]);
+        file.set_mmap_reads(true).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 4);
+        file.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [4, 5, 6, 7]);
+        assert_eq!(file.stream_position().unwrap(), 8);
+
+        // The whole rest of the file is lent out at once.
+        let available = file.fill_buf().unwrap();
+        assert_eq!(available, &data[8..]);
+        file.consume(2);
+        assert_eq!(file.stream_position().unwrap(), 10);
+
+        assert_eq!(file.read_at(100, 3).unwrap(), [100, 101, 102]);
+        assert_eq!(file.read_at(1022, 10).unwrap(), [254, 255]);
+        assert_eq!(file.stream_position().unwrap(), 10);
+
+        file.seek(SeekFrom::Start(500)).unwrap();
+        file.read_exact(&mut chunk[..2]).unwrap();
+        assert_eq!(chunk[..2], [244, 245]);
+        file.seek(SeekFrom::Current(-2)).unwrap();
+        assert_eq!(file.stream_position().unwrap(), 500);
+
+        // Writing drops the mapping, so the next reads see the new bytes and length.
+        file.write_all(b"xy").unwrap();
+        assert_eq!(file.stream_position().unwrap(), 502);
+        file.read_exact(&mut chunk[..2]).unwrap();
+        assert_eq!(chunk[..2], [246, 247]);
+        assert_eq!(file.read_at(500, 2).unwrap(), b"xy");
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"tail").unwrap();
+        file.seek(SeekFrom::Start(1022)).unwrap();
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [254, 255, b't', b'a', b'i', b'l']);
+        assert_eq!(file.stream_position().unwrap(), 1028);
+
+        // Closing drops the mapping too; reads start over from a fresh handle.
+        file.close();
+        file.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [0, 1, 2, 3]);
+        assert_eq!(file.stream_position().unwrap(), 4);
+        assert_eq!(file.mmap().unwrap().len(), 1028);
+
+        // And switching back carries on from the same spot through the buffer.
+        file.set_mmap_reads(false).unwrap();
+        file.read_exact(&mut chunk).unwrap();
+        assert_eq!(chunk, [4, 5, 6, 7]);
+        assert_eq!(std::fs::read(tmp.join("data.bin")).unwrap()[500..502], *b"xy");
+    }
+
+    #[test]
+    fn delete_all_removes_the_tree_and_invalidates_handles() {
+        let tmp = TempDir::new("delete-all");
+        tmp.write("tree/a.txt", "a");
+        tmp.write("tree/sub/b.txt", "b");
+        tmp.write("tree/sub/deeper/c.txt", "c");
+        let root = PhysicalDirectory::from_path(tmp.path());
+        let tree = root.read().unwrap().get_child("tree").unwrap();
+        let sub = tree.read().unwrap().get_child("sub").unwrap();
+        let b = sub.read().unwrap().get_child("b.txt").unwrap();
+        b.write().unwrap().write_all(b"pending").unwrap();
+
+        tree.write().unwrap().delete_all().unwrap();
+        assert!(!tmp.join("tree").exists());
+        assert!(!tree.read().unwrap().exists());
+        assert!(!sub.read().unwrap().exists());
+        assert!(!b.read().unwrap().exists());
+        assert!(!root.read().unwrap().has_child("tree").unwrap());
+    }
+
+    #[test]
+    fn corrupt_child_caches_are_detected_and_repaired() {
+        let tmp = TempDir::new("cache");
+        tmp.write("a.txt", "a");
+        tmp.write("b.txt", "b");
+        tmp.write("sub/c.txt", "c");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        dir.scan().unwrap();
+        assert!(dir.validate_cache().unwrap());
+        let kept = dir.get_child("a.txt").unwrap();
+
+        // A partial scan, and a path that changed kind since it was cached.
+        dir.children.write().unwrap().remove("b.txt");
+        fs::remove_dir_all(tmp.join("sub")).unwrap();
+        tmp.write("sub", "now a file");
+        assert!(!dir.validate_cache().unwrap());
+
+        dir.repair_cache().unwrap();
+        assert!(dir.validate_cache().unwrap());
+        assert_eq!(dir.count_children().unwrap(), 3);
+        assert!(dir.get_child("sub").unwrap().read().unwrap().is_file());
+        assert!(Arc::ptr_eq(&kept, &dir.get_child("a.txt").unwrap()));
+    }
+
+    #[test]
+    fn lazy_lookups_stat_only_the_requested_entry() {
+        let tmp = TempDir::new("lazy");
+        for i in 0..20 {
+            tmp.write(&format!("file-{i}.txt"), "");
+        }
+        tmp.write("sub/inner.txt", "");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        dir.set_lazy(true);
+
+        let file = dir.get_child("file-3.txt").unwrap();
+        assert!(dir.has_child("sub").unwrap());
+        assert!(!dir.has_child("missing.txt").unwrap());
+        assert!(matches!(dir.get_child("missing.txt"), Err(FsError::FileNotPresent(..))));
+        assert_eq!(dir.children.read().unwrap().len(), 2);
+        assert!(!dir.scanned.load(Ordering::Acquire));
+
+        assert_eq!(dir.get_children().unwrap().len(), 21);
+        assert!(Arc::ptr_eq(&file, &dir.get_child("file-3.txt").unwrap()));
+    }
+
+    #[test]
+    fn list_leaves_the_children_cache_alone() {
+        let tmp = TempDir::new("list-cache");
+        tmp.write("a.txt", "");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let dir = dir.read().unwrap();
+
+        assert_eq!(dir.list().unwrap().len(), 1);
+        assert!(!dir.scanned.load(Ordering::Acquire));
+        assert!(dir.children.read().unwrap().is_empty());
+
+        let cached = dir.get_children().unwrap();
+        tmp.write("b.txt", "");
+        fs::remove_file(tmp.join("a.txt")).unwrap();
+        let listed = dir.list().unwrap().into_iter().map(|entry| entry.name).collect::<Vec<_>>();
+        assert_eq!(listed, ["b.txt"]);
+        assert!(dir.scanned.load(Ordering::Acquire));
+        let children = dir.children.read().unwrap();
+        assert_eq!(children.len(), 1);
+        assert!(Arc::ptr_eq(&cached[0], &children["a.txt"]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "needs /dev/shm on a separate device from the temp dir"]
+    fn moves_across_devices_copy_then_delete() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new("move-src");
+        let shm = Path::new("/dev/shm");
+        assert_ne!(fs::metadata(shm).unwrap().dev(), fs::metadata(tmp.path()).unwrap().dev(), "/dev/shm is on the temp dir's device");
+        let dest = shm.join(format!("fsa-test-{}-move", std::process::id()));
+        fs::create_dir(&dest).unwrap();
+        let target = PhysicalDirectory::from_path(&dest);
+
+        tmp.write("file.txt", "moved file");
+        tmp.write("tree/a.txt", "a");
+        tmp.write("tree/sub/b.txt", "b");
+        let source = PhysicalDirectory::from_path(tmp.path());
+        let file = source.read().unwrap().get_child("file.txt").unwrap();
+        let tree = source.read().unwrap().get_child("tree").unwrap();
+        let file_result = file.write().unwrap().move_to(target.clone());
+        let tree_result = tree.write().unwrap().move_to(target.clone());
+
+        let moved = (fs::read_to_string(dest.join("file.txt")), fs::read_to_string(dest.join("tree/sub/b.txt")));
+        fs::remove_dir_all(&dest).unwrap();
+        file_result.unwrap();
+        tree_result.unwrap();
+        assert_eq!(moved.0.unwrap(), "moved file");
+        assert_eq!(moved.1.unwrap(), "b");
+        assert!(!tmp.join("file.txt").exists());
+        assert!(!tmp.join("tree").exists());
+    }
+
+    #[test]
+    fn moves_into_a_chroot_stay_inside_it() {
+        let tmp = TempDir::new("move-chroot");
+        tmp.write("file.txt", "moved file");
+        tmp.write("tree/a.txt", "a");
+        fs::create_dir(tmp.join("jail")).unwrap();
+        let source = PhysicalDirectory::from_path(tmp.path());
+        let jail = crate::ChrootDirectory::new(PhysicalDirectory::from_path(tmp.join("jail"))).unwrap();
+
+        let file = source.read().unwrap().get_child("file.txt").unwrap();
+        file.write().unwrap().move_to(jail.clone()).unwrap();
+        let tree = source.read().unwrap().get_child("tree").unwrap();
+        tree.write().unwrap().move_to(jail.clone()).unwrap();
+
+        assert_eq!(fs::read_to_string(tmp.join("jail/file.txt")).unwrap(), "moved file");
+        assert_eq!(fs::read_to_string(tmp.join("jail/tree/a.txt")).unwrap(), "a");
+        assert!(!tmp.join("file.txt").exists());
+        assert!(!tmp.join("tree").exists());
+        let moved = jail.read().unwrap().get_child("file.txt").unwrap();
+        assert_eq!(moved.read().unwrap().get_full_path(), Path::new("/file.txt"));
+    }
+
+    #[test]
+    fn moves_into_a_quota_directory_are_charged() {
+        let tmp = TempDir::new("move-quota");
+        tmp.write("small.txt", "12345");
+        tmp.write("large.txt", "0123456789");
+        fs::create_dir(tmp.join("limited")).unwrap();
+        let source = PhysicalDirectory::from_path(tmp.path());
+        let quota = crate::QuotaDirectory::new(PhysicalDirectory::from_path(tmp.join("limited")), 8).unwrap();
+
+        let small = source.read().unwrap().get_child("small.txt").unwrap();
+        small.write().unwrap().move_to(quota.clone()).unwrap();
+        assert_eq!(quota.read().unwrap().used(), 5);
+
+        let large = source.read().unwrap().get_child("large.txt").unwrap();
+        assert!(matches!(large.write().unwrap().move_to(quota.clone()), Err(FsError::QuotaExceeded { .. })));
+        assert_eq!(fs::read_to_string(tmp.join("large.txt")).unwrap(), "0123456789");
+        assert_eq!(quota.read().unwrap().used(), 5);
+    }
+
+    #[test]
+    fn moves_into_read_only_directories_fail() {
+        let tmp = TempDir::new("move-read-only");
+        tmp.write("file.txt", "stays");
+        tmp.write("tree/a.txt", "a");
+        fs::create_dir(tmp.join("locked")).unwrap();
+        let source = PhysicalDirectory::from_path(tmp.path());
+        let locked = PhysicalDirectory::from_path(tmp.join("locked"));
+        locked.write().unwrap().set_readonly(true).unwrap();
+
+        let file = source.read().unwrap().get_child("file.txt").unwrap();
+        assert!(matches!(file.write().unwrap().move_to(locked.clone()), Err(FsError::ReadOnly(..))));
+        let tree = source.read().unwrap().get_child("tree").unwrap();
+        assert!(matches!(tree.write().unwrap().move_to(locked.clone()), Err(FsError::ReadOnly(..))));
+        assert!(tmp.join("file.txt").exists());
+        assert!(tmp.join("tree/a.txt").exists());
+        assert_eq!(fs::read_dir(tmp.join("locked")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn resizing_the_buffer_mid_read_loses_nothing() {
+        let tmp = TempDir::new("resize");
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        tmp.write("data.bin", &data);
+        let file = PhysicalFile::from_path(tmp.join("data.bin"), 16);
+        let mut file = file.write().unwrap();
+
+        let mut read = Vec::new();
+        for (step, size) in [(5, 3), (20, 64), (7, 1), (100, 0), (50, 10)] {
+            let mut chunk = vec![0; step];
+            file.read_exact(&mut chunk).unwrap();
+            read.extend_from_slice(&chunk);
+            file.set_buffer_size(size);
+        }
+        file.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[test]
+    fn concurrent_temp_files_get_unique_names() {
+        let tmp = TempDir::new("temp-unique");
+        let threads: Vec<_> = (0..8).map(|_| {
+            // Separate objects for the same directory, so only `create_new` keeps names apart.
+            let dir = PhysicalDirectory::from_path(tmp.path());
+            std::thread::spawn(move || {
+                (0..25)
+                    .map(|_| {
+                        let file = dir.write().unwrap().create_temp_file("download-", ".part").unwrap();
+                        file.read().unwrap().name().to_string_lossy().to_string()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut names: Vec<String> = threads.into_iter().flat_map(|thread| thread.join().unwrap()).collect();
+        assert!(names.iter().all(|name| name.starts_with("download-") && name.ends_with(".part")));
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 200);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 200);
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let temp_dir = dir.write().unwrap().create_temp_dir("stage-").unwrap();
+        assert!(temp_dir.read().unwrap().get_full_path().is_dir());
+    }
+
+    #[test]
+    fn children_come_back_sorted_every_time() {
+        let tmp = TempDir::new("sorted");
+        for name in ["b.txt", "sub/x", "A.txt", "a.txt", "Z", "10", "9"] {
+            tmp.write(name, "");
+        }
+        let names = || {
+            let dir = PhysicalDirectory::from_path(tmp.path());
+            let mut dir = dir.write().unwrap();
+            dir.scan().unwrap();
+            dir.get_children().unwrap()
+                .into_iter()
+                .map(|child| child.read().unwrap().name().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let first = names();
+        assert_eq!(first, ["10", "9", "A.txt", "Z", "a.txt", "b.txt", "sub"]);
+        assert_eq!(names(), first);
+    }
+
+    #[test]
+    fn dropping_a_file_writes_out_pending_bytes() {
+        let tmp = TempDir::new("drop");
+        tmp.write("kept.txt", "");
+        tmp.write("discarded.txt", "");
+
+        let file = PhysicalFile::from_path(tmp.join("kept.txt"), 4096);
+        file.write().unwrap().write_all(b"not flushed").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(tmp.join("kept.txt")).unwrap(), "not flushed");
+
+        let file = PhysicalFile::from_path(tmp.join("discarded.txt"), 4096);
+        file.write().unwrap().set_flush_on_drop(false);
+        file.write().unwrap().write_all(b"not flushed").unwrap();
+        drop(file);
+        assert_eq!(fs::read_to_string(tmp.join("discarded.txt")).unwrap(), "");
+    }
+
+    #[test]
+    fn case_insensitive_lookups_fold_case() {
+        let tmp = TempDir::new("case");
+        tmp.write("readme.md", "readme");
+        tmp.write("Docs/guide.md", "");
+        tmp.write("notes.txt", "lower");
+        tmp.write("NOTES.txt", "upper");
+        // Entries differing only in case need a case-sensitive filesystem.
+        if fs::read_dir(tmp.path()).unwrap().count() < 4 {
+            return;
+        }
+        let sensitive = PhysicalDirectory::from_path(tmp.path());
+        sensitive.write().unwrap().set_case_insensitive(false);
+        assert!(!sensitive.read().unwrap().has_child("README.md").unwrap());
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        dir.set_case_insensitive(true);
+        let readme = dir.get_child("README.md").unwrap();
+        assert_eq!(readme.read().unwrap().name(), Path::new("readme.md"));
+        assert!(dir.has_child("docs").unwrap());
+        assert!(dir.get_child("docs").unwrap().read().unwrap().has_child("GUIDE.MD").unwrap());
+        // Exact matches win; otherwise an ambiguous name is an error, not a guess.
+        assert_eq!(dir.get_child("NOTES.txt").unwrap().read().unwrap().name(), Path::new("NOTES.txt"));
+        assert!(dir.get_child("Notes.txt").is_err());
+    }
+
+    #[test]
+    fn link_child_creates_aliases_of_the_target() {
+        let tmp = TempDir::new("link-child");
+        tmp.write("original.txt", "shared");
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        let mut dir = dir.write().unwrap();
+        let original = dir.get_child("original.txt").unwrap();
+
+        let hard = dir.link_child("hard.txt", &original, LinkKind::Hard).unwrap();
+        assert!(hard.read().unwrap().same_file(&original).unwrap());
+        #[cfg(unix)]
+        {
+            let symbolic = dir.link_child("soft.txt", &original, LinkKind::Symbolic).unwrap();
+            assert!(symbolic.read().unwrap().same_file(&original).unwrap());
+            assert_eq!(dir.read_link("soft.txt").unwrap(), tmp.join("original.txt"));
+        }
+
+        assert!(matches!(dir.link_child("hard.txt", &original, LinkKind::Hard), Err(FsError::AlreadyExists(..))));
+        let memory: FilesystemObject = crate::MemoryFile::from_bytes("memory.txt", b"");
+        let result = dir.link_child("memory.txt", &memory, LinkKind::Hard);
+        assert!(matches!(result, Err(FsError::Unsupported { .. })), "{result:?}");
+    }
+
+    #[test]
+    fn exclusive_locks_serialize_handles() {
+        use std::sync::atomic::AtomicBool;
+
+        let tmp = TempDir::new("lock");
+        tmp.write("shared.lock", "");
+        let path = tmp.join("shared.lock");
+        let first = PhysicalFile::from_path(&path, 0);
+        let guard = first.write().unwrap().lock_exclusive().unwrap();
+
+        let acquired = Arc::new(AtomicBool::new(false));
+        let waiter = std::thread::spawn({
+            let acquired = acquired.clone();
+            move || {
+                let second = PhysicalFile::from_path(&path, 0);
+                let _guard = second.write().unwrap().lock_exclusive().unwrap();
+                acquired.store(true, Ordering::SeqCst);
+            }
+        });
+
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!acquired.load(Ordering::SeqCst), "the second handle got the lock while it was held");
+        drop(guard);
+        waiter.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+
+        let _shared = first.write().unwrap().lock_shared().unwrap();
+        let other = PhysicalFile::from_path(tmp.join("shared.lock"), 0);
+        let _also_shared = other.write().unwrap().lock_shared().unwrap();
+        other.read().unwrap().unlock().unwrap();
+    }
+
+    #[test]
+    fn sync_writes_out_pending_bytes() {
+        let tmp = TempDir::new("sync");
+        let path = tmp.join("durable.bin");
+        fs::write(&path, "").unwrap();
+
+        let file = PhysicalFile::from_path(&path, 1 << 20);
+        let mut file = file.write().unwrap();
+        file.set_open_mode(OpenMode::Write);
+        file.open().unwrap();
+        file.write_all(&[7; 1000]).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        file.sync_all().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 1000);
+
+        file.write_all(&[8; 500]).unwrap();
+        file.sync_data().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 1500);
+        file.close();
+
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        assert!(matches!(dir.write().unwrap().sync_all(), Err(FsError::NotAFile(..))));
+        let memory = crate::MemoryFile::from_bytes("memory.bin", b"");
+        assert!(matches!(memory.write().unwrap().sync_data(), Err(FsError::Unsupported { .. })));
+    }
+
+    #[test]
+    fn advise_needs_an_open_file() {
+        let tmp = TempDir::new("advise");
+        let path = tmp.join("data.bin");
+        fs::write(&path, [1; 8192]).unwrap();
+
+        let file = PhysicalFile::from_path(&path, 4096);
+        let mut file = file.write().unwrap();
+        assert!(matches!(file.advise(AccessHint::Sequential), Err(FsError::FileNotOpen(..))));
+
+        file.open().unwrap();
+        for hint in [AccessHint::Sequential, AccessHint::Random, AccessHint::WillNeed, AccessHint::DontNeed] {
+            file.advise(hint).unwrap();
+        }
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, [1; 8192]);
+
+        file.close();
+        assert!(matches!(file.advise(AccessHint::Random), Err(FsError::FileNotOpen(..))));
+    }
+
+    #[test]
+    fn flush_threshold_bounds_pending_writes() {
+        let tmp = TempDir::new("flush-threshold");
+        let path = tmp.join("stream.bin");
+        fs::write(&path, b"").unwrap();
+        let data: Vec<u8> = (0..10 << 20).map(|i| (i % 253) as u8).collect();
+        let threshold = 64 * 1024;
+
+        let file = PhysicalFile::from_path(&path, 0);
+        let mut file = file.write().unwrap();
+        file.set_flush_threshold(Some(threshold));
+        let (first, rest) = data.split_at(data.len() / 2);
+        for chunk in first.chunks(4096) {
+            file.write_all(chunk).unwrap();
+            assert!(file.buffer.len() <= threshold, "{} bytes pending", file.buffer.len());
+        }
+        assert!(fs::metadata(&path).unwrap().len() >= (first.len() - threshold) as u64);
+
+        // A read in between sees everything written so far, and writing carries on where it was.
+        let position = file.stream_position().unwrap();
+        file.seek(SeekFrom::Start(1000)).unwrap();
+        let mut sample = [0; 4096];
+        file.read_exact(&mut sample).unwrap();
+        assert_eq!(sample, data[1000..1000 + 4096]);
+        file.seek(SeekFrom::Start(position)).unwrap();
+
+        for chunk in rest.chunks(4096) {
+            file.write_all(chunk).unwrap();
+        }
+        file.flush().unwrap();
+        // Plain asserts, as a mismatch would print megabytes.
+        assert!(fs::read(&path).unwrap() == data);
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).unwrap();
+        assert!(contents == data);
+    }
+
+    #[test]
+    fn created_and_renamed_names_are_single_segments() {
+        let tmp = TempDir::new("segments");
+        tmp.write("dir/a.txt", "a");
+        let dir = PhysicalDirectory::from_path(tmp.join("dir"));
+        let mut dir = dir.write().unwrap();
+
+        assert!(matches!(dir.new_dir("../escaped"), Err(FsError::Generic(..))));
+        assert!(matches!(dir.new_file("../f.txt", 0), Err(FsError::Generic(..))));
+        assert!(matches!(dir.new_file("sub/f.txt", 0), Err(FsError::Generic(..))));
+        assert!(matches!(dir.child_rename("a.txt", "../moved.txt"), Err(FsError::Generic(..))));
+        assert!(matches!(dir.child_rename("..", "moved"), Err(FsError::Generic(..))));
+        assert!(!tmp.join("escaped").exists() && !tmp.join("moved.txt").exists());
+        assert!(tmp.join("dir").join("a.txt").exists());
     }
 }
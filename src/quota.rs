@@ -0,0 +1,826 @@
+//! The [quota](`self`) module provides [`QuotaDirectory`], a wrapper capping how many bytes the
+//! files under a directory may take up.
+//!
+//! Usage starts out as the size of everything already under the directory. Files and directories
+//! reached through the wrapper are wrapped in turn and share its quota: writes are charged for
+//! however far they grow a file, truncating and deleting give the space back, and once the limit
+//! is reached growing writes and new files fail with [`FsError::QuotaExceeded`]. Space is reserved
+//! atomically before anything is written, so concurrent writers can't overshoot the limit between
+//! them. Writes made through the wrapped objects directly bypass the quota.
+
+use std::io::{
+    Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
+    BufRead, Write, Read,
+    Seek, SeekFrom,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock, Mutex, Weak, Arc};
+use std::path::{PathBuf, Path};
+use std::time::SystemTime;
+use std::fmt;
+
+use super::{
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind, DirEntry,
+    walk_files,
+    wrapper::{WrappedChildren, recache_renamed, fill_buf_copy},
+};
+
+/// Byte budget shared by everything under a [`QuotaDirectory`].
+struct Quota {
+    limit: u64,
+    used: AtomicU64,
+}
+
+impl Quota {
+    /// Claims `bytes` if they fit under the limit.
+    fn reserve(&self, bytes: u64) -> bool {
+        self.used
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| used.checked_add(bytes).filter(|total| *total <= self.limit))
+            .is_ok()
+    }
+
+    fn release(&self, bytes: u64) {
+        let _ = self.used.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| Some(used.saturating_sub(bytes)));
+    }
+
+    fn exceeded(&self, path: &Path) -> FsError {
+        FsError::quota_exceeded(path.to_string_lossy().to_string(), self.limit)
+    }
+}
+
+pub struct QuotaDirectory {
+    inner: FilesystemObject,
+    name: PathBuf,
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+
+    quota: Arc<Quota>,
+    /// Wrapped children, kept so each file's charge stays with a single wrapper.
+    children: WrappedChildren,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+pub struct QuotaFile {
+    inner: FilesystemObject,
+    name: PathBuf,
+    path: PathBuf,
+    parent: FilesystemObject,
+
+    quota: Arc<Quota>,
+    /// Bytes this file is charged for, i.e. its size as far as the quota knows. Shared with the
+    /// file's duplicates, and held while charging so they don't charge for the same growth twice.
+    charged: Arc<Mutex<u64>>,
+    /// Copy of the inner file's buffered bytes, for `BufRead`.
+    buffer: Vec<u8>,
+
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
+
+impl QuotaDirectory {
+    /// Wraps the directory `inner`, allowing the files under it `limit` bytes in total. The
+    /// existing files count towards the limit, so wrapping walks the whole tree once.
+    pub fn new(inner: FilesystemObject, limit: u64) -> FsResult<Arc<RwLock<Self>>> {
+        let mut used = 0;
+        walk_files(&*inner.read().unwrap(), Path::new(""), &mut |_, file| {
+            used += file.read().unwrap().size()? as u64;
+            Ok(())
+        })?;
+
+        let quota = Arc::new(Quota{ limit, used: AtomicU64::new(used) });
+        Ok(Self::new_node(inner, None, quota))
+    }
+
+    fn new_node(inner: FilesystemObject, parent: Option<FilesystemObject>, quota: Arc<Quota>) -> Arc<RwLock<Self>> {
+        let (name, path) = {
+            let inner = inner.read().unwrap();
+            (inner.name().to_path_buf(), inner.get_full_path())
+        };
+
+        let new = Self{
+            inner,
+            name,
+            path,
+            parent,
+
+            quota,
+            children: WrappedChildren::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    /// The total number of bytes the files under this directory may take up.
+    pub fn limit(&self) -> u64 {
+        self.quota.limit
+    }
+
+    /// The number of bytes currently charged against the quota.
+    pub fn used(&self) -> u64 {
+        self.quota.used.load(Ordering::Acquire)
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Returns the wrapper for the inner child `child` named `name`, creating it if needed.
+    fn wrap(&self, name: &str, child: FilesystemObject) -> FsResult<FilesystemObject> {
+        self.children.wrap(name, child, |_, child, kind| self.make_wrapper(child, kind))
+    }
+
+    /// Makes a wrapper sharing this directory's quota for the inner child `child`, of kind `kind`.
+    fn make_wrapper(&self, child: FilesystemObject, kind: FsKind) -> FsResult<FilesystemObject> {
+        Ok(match kind {
+            FsKind::Directory => Self::new_node(child, Some(self.get()), self.quota.clone()),
+            FsKind::File => QuotaFile::new(child, self.get(), self.quota.clone())?,
+        })
+    }
+
+    /// Deletes the inner directory, along with everything under it if `recursive`, and gives back
+    /// the space taken up by the files under it.
+    fn remove(&mut self, recursive: bool) -> FsResult {
+        let mut freed = 0;
+        walk_files(&*self.inner.read().unwrap(), Path::new(""), &mut |_, file| {
+            freed += file.read().unwrap().size()? as u64;
+            Ok(())
+        })?;
+
+        {
+            let mut inner = self.inner.write().unwrap();
+            if recursive { inner.delete_all()? } else { inner.delete()? }
+        }
+        self.quota.release(freed);
+        self.children.clear();
+        if let Some(parent) = self.parent.as_ref() {
+            parent.write().unwrap().drop_child(&self.name.to_string_lossy())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl QuotaFile {
+    fn new(inner: FilesystemObject, parent: FilesystemObject, quota: Arc<Quota>) -> FsResult<Arc<RwLock<Self>>> {
+        let size = {
+            let inner = inner.read().unwrap();
+            // Files that don't exist yet have nothing to charge for.
+            if inner.exists() { inner.size()? as u64 } else { 0 }
+        };
+
+        Ok(Self::with_charge(inner, parent, quota, Arc::new(Mutex::new(size))))
+    }
+
+    /// Wraps `inner`, sharing the charge `charged` with the other wrappers of the same file.
+    fn with_charge(
+        inner: FilesystemObject, parent: FilesystemObject, quota: Arc<Quota>, charged: Arc<Mutex<u64>>,
+    ) -> Arc<RwLock<Self>> {
+        let (name, path) = {
+            let inner = inner.read().unwrap();
+            (inner.name().to_path_buf(), inner.get_full_path())
+        };
+
+        let new = Self{
+            inner,
+            name,
+            path,
+            parent,
+
+            quota,
+            charged,
+            buffer: Vec::new(),
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(new));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        arc
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Brings the `charged` bytes in line with a new size for the file, failing if growing it
+    /// doesn't fit.
+    fn recharge(&self, charged: &mut u64, size: u64) -> FsResult {
+        if size > *charged {
+            if !self.quota.reserve(size - *charged) {
+                return Err(self.quota.exceeded(&self.path));
+            }
+        } else {
+            self.quota.release(*charged - size);
+        }
+        *charged = size;
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for QuotaDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("limit", &self.limit())
+            .field("used", &self.used())
+            .finish()
+    }
+}
+
+impl fmt::Debug for QuotaFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuotaFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("charged", &*self.charged.lock().unwrap())
+            .finish()
+    }
+}
+
+impl Read for QuotaDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for QuotaDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for QuotaDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for QuotaDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for QuotaDirectory {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.read().unwrap().exists()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.inner.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        self.inner.read().unwrap().identity()
+    }
+
+    fn readonly(&self) -> bool {
+        self.inner.read().unwrap().readonly()
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.inner.write().unwrap().set_readonly(readonly)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.read().unwrap().capabilities()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        self.inner.read().unwrap().fs_type()
+    }
+
+    /// What's left of the quota, or of the underlying storage if that's less.
+    fn free_space(&self) -> FsResult<u64> {
+        let left = self.quota.limit.saturating_sub(self.quota.used.load(Ordering::Acquire));
+        Ok(left.min(self.inner.read().unwrap().free_space()?))
+    }
+
+    /// The quota's limit, or the underlying storage's size if that's less.
+    fn total_space(&self) -> FsResult<u64> {
+        Ok(self.quota.limit.min(self.inner.read().unwrap().total_space()?))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("quota", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn is_open(&self) -> bool {
+        false
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        let children = self.inner.read().unwrap().get_children()?;
+        children.into_iter()
+            .map(|child| self.children.wrap_named(child, |_, child, kind| self.make_wrapper(child, kind)))
+            .collect()
+    }
+
+    fn count_children(&self) -> FsResult<usize> {
+        self.inner.read().unwrap().count_children()
+    }
+
+    fn list(&self) -> FsResult<Vec<DirEntry>> {
+        self.inner.read().unwrap().list()
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        let child = self.inner.read().unwrap().get_child(name)?;
+        self.wrap(name, child)
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        self.inner.read().unwrap().has_child(name)
+    }
+
+    fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult {
+        self.children.child_rename(&self.inner, name, new_name)
+    }
+
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        self.inner.write().unwrap().rename(new_name)?;
+
+        let old_name = std::mem::replace(&mut self.name, self.inner.read().unwrap().name().to_path_buf());
+        self.path = self.inner.read().unwrap().get_full_path();
+        // Wrapped children carry the old path.
+        self.children.clear();
+
+        match self.parent.as_ref() {
+            Some(parent) => recache_renamed(parent, &old_name, &self.name.to_string_lossy(), self.get()),
+            None => Ok(()),
+        }
+    }
+
+    fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        if self.used() >= self.limit() {
+            return Err(self.quota.exceeded(&self.path.join(name)));
+        }
+
+        let file = self.inner.write().unwrap().new_file(name, buffer_size)?;
+        self.children.remove(name);
+        self.wrap(name, file)
+    }
+
+    fn new_dir(&mut self, name: &str) -> FsResult<FilesystemObject> {
+        let dir = self.inner.write().unwrap().new_dir(name)?;
+        self.children.remove(name);
+        self.wrap(name, dir)
+    }
+
+    fn create_temp_file(&mut self, prefix: &str, ext: &str) -> FsResult<FilesystemObject> {
+        if self.used() >= self.limit() {
+            return Err(self.quota.exceeded(&self.path.join(prefix)));
+        }
+
+        let file = self.inner.write().unwrap().create_temp_file(prefix, ext)?;
+        self.children.wrap_named(file, |_, file, kind| self.make_wrapper(file, kind))
+    }
+
+    fn create_temp_dir(&mut self, prefix: &str) -> FsResult<FilesystemObject> {
+        let dir = self.inner.write().unwrap().create_temp_dir(prefix)?;
+        self.children.wrap_named(dir, |_, dir, kind| self.make_wrapper(dir, kind))
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.remove(name);
+        self.inner.write().unwrap().drop_child(name)
+    }
+
+    fn cache_child(&mut self, name: &str, child: FilesystemObject) -> FsResult {
+        self.children.insert(name, child);
+        Ok(())
+    }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        self.inner.write().unwrap().scan()
+    }
+
+    fn validate_cache(&self) -> FsResult<bool> {
+        self.inner.read().unwrap().validate_cache()
+    }
+
+    /// Repairs the inner directory's cache, then drops the wrappers of children that changed kind.
+    fn repair_cache(&mut self) -> FsResult {
+        self.children.repair(&self.inner)
+    }
+
+    /// Deletes the directory and gives back the space taken up by the files under it.
+    fn delete(&mut self) -> FsResult {
+        self.remove(false)
+    }
+
+    /// Deletes the directory and everything under it, giving back the space the files took up.
+    fn delete_all(&mut self) -> FsResult {
+        self.remove(true)
+    }
+
+    fn invalidate(&mut self) {
+        self.children.clear();
+        self.inner.write().unwrap().invalidate();
+    }
+}
+
+impl Read for QuotaFile {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.inner.write().unwrap().read(buf)
+    }
+}
+
+impl BufRead for QuotaFile {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        fill_buf_copy(&self.inner, &mut self.buffer)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.write().unwrap().consume(amt);
+    }
+}
+
+impl Seek for QuotaFile {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        self.inner.write().unwrap().seek(pos)
+    }
+}
+
+impl Write for QuotaFile {
+    /// Charges for however far the write extends the file, assuming it lands at the inner file's
+    /// current position. A file that isn't open yet is opened first, as writing would, so that
+    /// a truncating open is accounted for and appends start from the end.
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let unopened = {
+            let inner = self.inner.read().unwrap();
+            !inner.is_open() && inner.exists()
+        };
+        if unopened {
+            self.open()?;
+        }
+
+        let mut charged = self.charged.lock().unwrap();
+        let start = self.inner.read().unwrap().position()?;
+        let end = start + buf.len() as u64;
+        let growth = end.saturating_sub(*charged);
+        if !self.quota.reserve(growth) {
+            return Err(IoError::new(IoErrorKind::QuotaExceeded, self.quota.exceeded(&self.path)));
+        }
+
+        let count = match self.inner.write().unwrap().write(buf) {
+            Ok(count) => count,
+            Err(err) => {
+                self.quota.release(growth);
+                return Err(err);
+            },
+        };
+
+        // Give back whatever a short write didn't use.
+        let written = start + count as u64;
+        self.quota.release(end.saturating_sub(written.max(*charged)));
+        *charged = (*charged).max(written);
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().flush()
+    }
+}
+
+impl FilesystemObjectRaw for QuotaFile {
+    fn get(&self) -> FilesystemObject {
+        self.handle.get().unwrap().upgrade().unwrap()
+    }
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
+    }
+
+    fn exists(&self) -> bool {
+        self.inner.read().unwrap().exists()
+    }
+
+    fn size(&self) -> FsResult<usize> {
+        self.inner.read().unwrap().size()
+    }
+
+    fn truncate(&mut self, len: usize) -> FsResult {
+        let mut charged = self.charged.lock().unwrap();
+        let previous = *charged;
+        self.recharge(&mut charged, len as u64)?;
+        let result = self.inner.write().unwrap().truncate(len);
+        if result.is_err() {
+            self.recharge(&mut charged, previous)?;
+        }
+
+        result
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        self.inner.read().unwrap().position()
+    }
+
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        self.inner.read().unwrap().read_at(offset, len)
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.inner.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
+    }
+
+    fn get_full_path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        self.inner.read().unwrap().identity()
+    }
+
+    fn readonly(&self) -> bool {
+        self.inner.read().unwrap().readonly()
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.inner.write().unwrap().set_readonly(readonly)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.read().unwrap().capabilities()
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        self.inner.read().unwrap().fs_type()
+    }
+
+    /// What's left of the quota, or of the underlying storage if that's less.
+    fn free_space(&self) -> FsResult<u64> {
+        let left = self.quota.limit.saturating_sub(self.quota.used.load(Ordering::Acquire));
+        Ok(left.min(self.inner.read().unwrap().free_space()?))
+    }
+
+    /// The quota's limit, or the underlying storage's size if that's less.
+    fn total_space(&self) -> FsResult<u64> {
+        Ok(self.quota.limit.min(self.inner.read().unwrap().total_space()?))
+    }
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::unsupported("quota", "move_to"))
+    }
+
+    fn open(&mut self) -> IoResult<()> {
+        self.inner.write().unwrap().open()?;
+
+        // Opening may have truncated the file.
+        let size = self.inner.read().unwrap().size()? as u64;
+        let mut charged = self.charged.lock().unwrap();
+        if size < *charged {
+            self.recharge(&mut charged, size)?;
+        }
+
+        Ok(())
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.read().unwrap().is_open()
+    }
+
+    fn sync_all(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_all()
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        self.inner.write().unwrap().sync_data()
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let inner = self.inner.read().unwrap().duplicate()?;
+        Ok(QuotaFile::with_charge(inner, self.parent.clone(), self.quota.clone(), self.charged.clone()))
+    }
+
+    fn close(&mut self) {
+        self.inner.write().unwrap().close();
+    }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        self.inner.write().unwrap().rename(new_name)?;
+
+        let old_name = std::mem::replace(&mut self.name, self.inner.read().unwrap().name().to_path_buf());
+        self.path = self.inner.read().unwrap().get_full_path();
+
+        recache_renamed(&self.parent, &old_name, new_name, self.get())
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn set_buffer_size(&mut self, size: usize) {
+        self.inner.write().unwrap().set_buffer_size(size);
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Deletes the file and gives back the space it was charged for.
+    fn delete(&mut self) -> FsResult {
+        self.inner.write().unwrap().delete()?;
+        let mut charged = self.charged.lock().unwrap();
+        self.quota.release(*charged);
+        *charged = 0;
+        drop(charged);
+
+        self.parent.write().unwrap().drop_child(&self.name.to_string_lossy())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PhysicalDirectory, OpenMode};
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn delete_all_gives_the_space_back() {
+        let tmp = TempDir::new("quota-delete-all");
+        let quota = QuotaDirectory::new(PhysicalDirectory::from_path(tmp.path()), 100).unwrap();
+        let sub = quota.write().unwrap().new_dir("sub").unwrap();
+        let file = sub.write().unwrap().new_file("a.bin", 0).unwrap();
+        file.write().unwrap().write_all(&[0; 40]).unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(quota.read().unwrap().used(), 40);
+
+        sub.write().unwrap().delete_all().unwrap();
+        assert!(!tmp.join("sub").exists());
+        assert_eq!(quota.read().unwrap().used(), 0);
+    }
+
+    #[test]
+    fn writes_fail_past_the_quota_until_space_is_freed() {
+        let tmp = TempDir::new("quota");
+        let quota = QuotaDirectory::new(PhysicalDirectory::from_path(tmp.path()), 10).unwrap();
+
+        let file = quota.write().unwrap().new_file("a.bin", 0).unwrap();
+        file.write().unwrap().write_all(&[1; 8]).unwrap();
+        let error = file.write().unwrap().write_all(&[2; 4]).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::QuotaExceeded);
+        assert_eq!(quota.read().unwrap().used(), 8);
+
+        file.write().unwrap().flush().unwrap();
+        file.write().unwrap().delete().unwrap();
+        assert_eq!(quota.read().unwrap().used(), 0);
+        let file = quota.write().unwrap().new_file("b.bin", 0).unwrap();
+        file.write().unwrap().write_all(&[3; 10]).unwrap();
+    }
+
+    #[test]
+    fn duplicates_share_the_charge() {
+        let tmp = TempDir::new("quota-duplicate");
+        std::fs::write(tmp.join("a.bin"), [0; 6]).unwrap();
+        let quota = QuotaDirectory::new(PhysicalDirectory::from_path(tmp.path()), 10).unwrap();
+        let file = quota.read().unwrap().get_child("a.bin").unwrap();
+        let duplicate = file.read().unwrap().duplicate().unwrap();
+
+        // Each handle has its own cursor, so both write over the same first bytes.
+        duplicate.write().unwrap().write_all(&[1; 8]).unwrap();
+        duplicate.write().unwrap().flush().unwrap();
+        file.write().unwrap().write_all(&[2; 8]).unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(quota.read().unwrap().used(), 8);
+        assert!(file.write().unwrap().write_all(&[3; 3]).is_err());
+
+        duplicate.write().unwrap().delete().unwrap();
+        assert_eq!(quota.read().unwrap().used(), 0);
+        let other = quota.write().unwrap().new_file("b.bin", 0).unwrap();
+        other.write().unwrap().write_all(&[4; 10]).unwrap();
+        assert_eq!(quota.read().unwrap().used(), 10);
+    }
+
+    #[test]
+    fn appends_are_charged_from_the_end() {
+        let tmp = TempDir::new("quota-append");
+        std::fs::write(tmp.join("log.txt"), b"12345").unwrap();
+        let dir = PhysicalDirectory::from_path(tmp.path());
+        dir.read().unwrap().open_child_at("log.txt", OpenMode::Append).unwrap();
+
+        let quota = QuotaDirectory::new(dir, 10).unwrap();
+        let file = quota.read().unwrap().get_child("log.txt").unwrap();
+        file.write().unwrap().write_all(b"678").unwrap();
+        assert_eq!(quota.read().unwrap().used(), 8);
+        assert!(file.write().unwrap().write_all(b"901").is_err());
+    }
+
+    #[test]
+    fn free_space_is_what_is_left_of_the_quota() {
+        let tmp = TempDir::new("quota-free");
+        let quota = QuotaDirectory::new(PhysicalDirectory::from_path(tmp.path()), 100).unwrap();
+        assert_eq!(quota.read().unwrap().free_space().unwrap(), 100);
+        assert_eq!(quota.read().unwrap().total_space().unwrap(), 100);
+
+        let file = quota.write().unwrap().new_file("a.bin", 0).unwrap();
+        file.write().unwrap().write_all(&[0; 30]).unwrap();
+        file.write().unwrap().flush().unwrap();
+        assert_eq!(quota.read().unwrap().free_space().unwrap(), 70);
+        assert_eq!(file.read().unwrap().free_space().unwrap(), 70);
+    }
+}
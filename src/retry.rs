@@ -0,0 +1,114 @@
+//! The [retry](`self`) module provides [`RetryPolicy`], which retries I/O that fails with a
+//! transient error, as network mounts (NFS, SMB) sometimes report.
+
+use std::io::{Result as IoResult, ErrorKind as IoErrorKind};
+use std::time::Duration;
+
+/// How often, and after which errors, an operation is tried again before its error is returned.
+/// The default tries once, never retrying.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times the operation is tried in total, including the first.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry, doubling before each one after that.
+    pub backoff: Duration,
+    /// The error kinds worth retrying. Anything else, like `NotFound` or `PermissionDenied`,
+    /// fails straight away.
+    pub retry_on: Vec<IoErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(1, Duration::ZERO)
+    }
+}
+
+impl RetryPolicy {
+    /// Tries up to `max_attempts` times, waiting `backoff` and then twice as long each time, on
+    /// `Interrupted`, `WouldBlock` and `TimedOut` errors.
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self{
+            max_attempts,
+            backoff,
+            retry_on: vec![IoErrorKind::Interrupted, IoErrorKind::WouldBlock, IoErrorKind::TimedOut],
+        }
+    }
+
+    /// Runs `op` until it succeeds, fails with an error that isn't retried, or runs out of
+    /// attempts, returning its last result.
+    pub fn run<T>(&self, mut op: impl FnMut() -> IoResult<T>) -> IoResult<T> {
+        let mut delay = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Err(error) if attempt < self.max_attempts && self.retry_on.contains(&error.kind()) => {
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error as IoError, Read};
+
+    use super::*;
+
+    /// Fails with `kind` the first `failures` times it's read, then reads `data`.
+    struct FlakyReader {
+        failures: u32,
+        kind: IoErrorKind,
+        data: &'static [u8],
+        reads: u32,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+            self.reads += 1;
+            if self.reads <= self.failures {
+                return Err(IoError::from(self.kind));
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    fn flaky(failures: u32, kind: IoErrorKind) -> FlakyReader {
+        FlakyReader{ failures, kind, data: b"payload", reads: 0 }
+    }
+
+    #[test]
+    fn transient_errors_are_retried_until_success() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let mut reader = flaky(2, IoErrorKind::TimedOut);
+        let mut buf = [0; 16];
+
+        let count = policy.run(|| reader.read(&mut buf)).unwrap();
+        assert_eq!(&buf[..count], b"payload");
+        assert_eq!(reader.reads, 3);
+
+        let mut reader = flaky(2, IoErrorKind::Interrupted);
+        let error = RetryPolicy::new(2, Duration::ZERO).run(|| reader.read(&mut buf)).unwrap_err();
+        assert_eq!(error.kind(), IoErrorKind::Interrupted);
+        assert_eq!(reader.reads, 2);
+    }
+
+    #[test]
+    fn other_errors_fail_immediately() {
+        let policy = RetryPolicy::new(5, Duration::ZERO);
+        let mut buf = [0; 16];
+        for kind in [IoErrorKind::NotFound, IoErrorKind::PermissionDenied] {
+            let mut reader = flaky(2, kind);
+            assert_eq!(policy.run(|| reader.read(&mut buf)).unwrap_err().kind(), kind);
+            assert_eq!(reader.reads, 1);
+        }
+
+        let mut reader = flaky(1, IoErrorKind::WouldBlock);
+        assert!(RetryPolicy::default().run(|| reader.read(&mut buf)).is_err());
+        assert_eq!(reader.reads, 1);
+    }
+}
@@ -0,0 +1,459 @@
+//! Platform specific plumbing for the physical backend.
+
+use std::io::{Result as IoResult, Error as IoError, ErrorKind as IoErrorKind};
+use std::fs;
+
+#[cfg(unix)]
+use super::physical::OpenMode;
+use super::physical::AccessHint;
+
+/// Alignment required of buffers, offsets and lengths when using direct I/O. This is the common
+/// page size; it covers the logical block size of practically every device.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// A heap buffer whose start is aligned to [`DIRECT_IO_ALIGNMENT`].
+pub(crate) struct AlignedBuffer {
+    data: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuffer {
+    pub fn new(len: usize) -> Self {
+        let data = vec![0; len + DIRECT_IO_ALIGNMENT];
+        let offset = data.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+
+        Self{ data, offset, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[self.offset..self.offset + self.len]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[self.offset..self.offset + self.len]
+    }
+}
+
+/// Errors unless `offset` and `len` both satisfy [`DIRECT_IO_ALIGNMENT`].
+pub(crate) fn check_direct_alignment(offset: u64, len: usize) -> IoResult<()> {
+    let align = DIRECT_IO_ALIGNMENT;
+    if !offset.is_multiple_of(align as u64) || !len.is_multiple_of(align) {
+        return Err(IoError::new(
+            IoErrorKind::InvalidInput,
+            format!("direct I/O requires offsets and lengths aligned to {align} bytes (got offset {offset}, length {len}); seek and size buffers in multiples of {align}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Configures `options` to bypass the page cache where the platform does so at open time.
+#[allow(unused_variables)]
+pub(crate) fn set_direct(options: &mut fs::OpenOptions) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_DIRECT);
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_FLAG_NO_BUFFERING: u32 = 0x2000_0000;
+        options.custom_flags(FILE_FLAG_NO_BUFFERING);
+    }
+}
+
+/// Sets the permission bits `options` creates files with on Unix. The process umask still
+/// applies, as it does for `open(2)`.
+#[allow(unused_variables)]
+pub(crate) fn set_mode(options: &mut fs::OpenOptions, mode: u32) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+}
+
+/// Creates the directory at `path`, and its missing ancestors if `recursive`, with the permission
+/// bits `mode` on Unix, less the process umask.
+#[allow(unused_variables)]
+pub(crate) fn create_dir(path: &std::path::Path, recursive: bool, mode: u32) -> IoResult<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(recursive);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(mode);
+    }
+
+    builder.create(path)
+}
+
+/// Finishes enabling direct I/O on an already opened file, for platforms that do so after open.
+#[allow(unused_variables)]
+pub(crate) fn after_direct_open(file: &fs::File) -> IoResult<()> {
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::fd::AsRawFd;
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+            return Err(IoError::last_os_error());
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    return Err(IoError::new(IoErrorKind::Unsupported, "direct I/O is not supported on this platform"));
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    Ok(())
+}
+
+/// Returns the device and inode of `path` on Unix, or its volume serial number and file index on
+/// Windows, which together tell files apart even across hard links.
+pub(crate) fn file_id(path: &std::path::Path) -> IoResult<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path)?;
+        Ok((metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::{fs::OpenOptionsExt, io::AsRawHandle};
+
+        #[repr(C)]
+        struct ByHandleFileInformation {
+            file_attributes: u32,
+            times: [u32; 6],
+            volume_serial_number: u32,
+            file_size: [u32; 2],
+            number_of_links: u32,
+            file_index_high: u32,
+            file_index_low: u32,
+        }
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn GetFileInformationByHandle(file: *mut std::ffi::c_void, information: *mut ByHandleFileInformation) -> i32;
+        }
+        // Needed to open directories as well as files.
+        const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+        let file = fs::OpenOptions::new().read(true).custom_flags(FILE_FLAG_BACKUP_SEMANTICS).open(path)?;
+        let mut information = std::mem::MaybeUninit::<ByHandleFileInformation>::uninit();
+        if unsafe { GetFileInformationByHandle(file.as_raw_handle(), information.as_mut_ptr()) } == 0 {
+            return Err(IoError::last_os_error());
+        }
+        let information = unsafe { information.assume_init() };
+
+        let index = (information.file_index_high as u64) << 32 | information.file_index_low as u64;
+        Ok((information.volume_serial_number as u64, index))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        Err(IoError::new(IoErrorKind::Unsupported, "file identities are not supported on this platform"))
+    }
+}
+
+/// Passes `hint` on to the OS for the whole of `file`: `posix_fadvise` where there is one, and
+/// `F_RDAHEAD` on macOS for the sequential and random hints. Does nothing elsewhere.
+#[allow(unused_variables)]
+pub(crate) fn advise(file: &fs::File, hint: AccessHint) -> IoResult<()> {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    {
+        use std::os::fd::AsRawFd;
+
+        let advice = match hint {
+            AccessHint::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            AccessHint::Random => libc::POSIX_FADV_RANDOM,
+            AccessHint::WillNeed => libc::POSIX_FADV_WILLNEED,
+            AccessHint::DontNeed => libc::POSIX_FADV_DONTNEED,
+        };
+        // Returns the error number rather than setting `errno`.
+        let error = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) };
+        if error != 0 {
+            return Err(IoError::from_raw_os_error(error));
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let read_ahead = match hint {
+            AccessHint::Sequential => 1,
+            AccessHint::Random => 0,
+            AccessHint::WillNeed | AccessHint::DontNeed => return Ok(()),
+        };
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDAHEAD, read_ahead) } == -1 {
+            return Err(IoError::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads up to `len` bytes at `offset` without going through (or, except on Windows, moving) the
+/// handle's cursor.
+pub(crate) fn read_at(file: &fs::File, offset: u64, len: usize) -> IoResult<Vec<u8>> {
+    let len = file.metadata()?.len().saturating_sub(offset).min(len as u64) as usize;
+    let mut data = vec![0; len];
+
+    let mut filled = 0;
+    while filled < len {
+        let position = offset + filled as u64;
+        #[cfg(unix)]
+        let count = std::os::unix::fs::FileExt::read_at(file, &mut data[filled..], position);
+        #[cfg(windows)]
+        let count = std::os::windows::fs::FileExt::seek_read(file, &mut data[filled..], position);
+        #[cfg(not(any(unix, windows)))]
+        let count: IoResult<usize> = Err(IoError::new(IoErrorKind::Unsupported, "positional reads are not supported on this platform"));
+
+        match count {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(err) if err.kind() == IoErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    data.truncate(filled);
+
+    Ok(data)
+}
+
+/// Opens a directory so children can later be opened relative to it with [`open_at`].
+#[cfg(unix)]
+pub(crate) fn open_dir(path: &std::path::Path) -> IoResult<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(path)
+}
+
+/// Opens `name` relative to the directory `dir` in a single `openat` call, so nothing can be
+/// swapped in between resolving and opening it. A symlink in place of `name` is refused rather
+/// than followed. A file created by the call gets the `permissions` bits, less the umask.
+#[cfg(unix)]
+pub(crate) fn open_at(dir: &fs::File, name: &str, mode: OpenMode, permissions: u32) -> IoResult<fs::File> {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let name = CString::new(name)?;
+    let flags = match mode {
+        OpenMode::Read => libc::O_RDONLY,
+        OpenMode::Write => libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC,
+        OpenMode::Append => libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND,
+        OpenMode::ReadWrite => libc::O_RDWR | libc::O_CREAT,
+    };
+
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags | libc::O_NOFOLLOW | libc::O_CLOEXEC, permissions as libc::c_uint) };
+    if fd == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(unsafe { fs::File::from_raw_fd(fd) })
+}
+
+/// Creates a symbolic link at `link` pointing to `target`. Windows needs to know whether `target`
+/// is a directory.
+pub(crate) fn symlink(target: &std::path::Path, link: &std::path::Path, is_dir: bool) -> IoResult<()> {
+    #[cfg(unix)]
+    {
+        let _ = is_dir;
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(windows)]
+    {
+        match is_dir {
+            true => std::os::windows::fs::symlink_dir(target, link),
+            false => std::os::windows::fs::symlink_file(target, link),
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, link, is_dir);
+        Err(IoError::new(IoErrorKind::Unsupported, "symbolic links are not supported on this platform"))
+    }
+}
+
+/// Names the type of the filesystem `path` lives on, in lower case (`ext4`, `apfs`, `ntfs`, ...).
+/// Linux only reports a magic number, so filesystems missing from the table below come back as
+/// `unknown (0x...)`.
+pub(crate) fn fs_type(path: &std::path::Path) -> IoResult<String> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let stat = statfs(path)?;
+        let name = match stat.f_type as u32 {
+            // ext2 and ext3 share the magic, and are mounted by the ext4 driver on modern kernels.
+            0xEF53 => "ext4",
+            0x9123_683E => "btrfs",
+            0x5846_5342 => "xfs",
+            0xF2F5_2010 => "f2fs",
+            0xCA45_1A4E => "bcachefs",
+            0x2FC1_2FC1 => "zfs",
+            0x5265_4973 => "reiserfs",
+            0x7371_7368 => "squashfs",
+            0x794C_7630 => "overlay",
+            0x0102_1994 => "tmpfs",
+            0x8584_58F6 => "ramfs",
+            0x6969 => "nfs",
+            0xFF53_4D42 | 0xFE53_4D42 => "cifs",
+            0x517B => "smb",
+            0x00C3_6400 => "ceph",
+            0x0102_1997 => "9p",
+            0x6573_5546 => "fuse",
+            0x4D44 => "vfat",
+            0x2011_BAB0 => "exfat",
+            0x5346_544E => "ntfs",
+            0x9660 => "iso9660",
+            0x9FA0 => "proc",
+            0x6265_6572 => "sysfs",
+            magic => return Ok(format!("unknown (0x{magic:x})")),
+        };
+
+        Ok(name.to_string())
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd"))]
+    {
+        let stat = statfs(path)?;
+        let name = unsafe { std::ffi::CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+
+        Ok(name.to_string_lossy().to_lowercase())
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn GetVolumePathNameW(file_name: *const u16, volume_path_name: *mut u16, buffer_length: u32) -> i32;
+            fn GetVolumeInformationW(
+                root_path_name: *const u16,
+                volume_name_buffer: *mut u16, volume_name_size: u32,
+                volume_serial_number: *mut u32, maximum_component_length: *mut u32, file_system_flags: *mut u32,
+                file_system_name_buffer: *mut u16, file_system_name_size: u32,
+            ) -> i32;
+        }
+        const MAX_PATH: usize = 261;
+
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut root = vec![0u16; path.len().max(MAX_PATH)];
+        if unsafe { GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root.len() as u32) } == 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let null = std::ptr::null_mut();
+        let mut name = [0u16; MAX_PATH];
+        if unsafe { GetVolumeInformationW(root.as_ptr(), null, 0, null.cast(), null.cast(), null.cast(), name.as_mut_ptr(), name.len() as u32) } == 0 {
+            return Err(IoError::last_os_error());
+        }
+        let len = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+
+        Ok(String::from_utf16_lossy(&name[..len]).to_lowercase())
+    }
+
+    #[cfg(not(any(
+        target_os = "linux", target_os = "android",
+        target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd",
+        windows,
+    )))]
+    {
+        let _ = path;
+        Err(IoError::new(IoErrorKind::Unsupported, "detecting the filesystem type is not supported on this platform"))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd"))]
+fn statfs(path: &std::path::Path) -> IoResult<libc::statfs> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(unsafe { stat.assume_init() })
+}
+
+/// Returns the bytes available to this process and the total size, in that order, of the
+/// filesystem `path` lives on.
+pub(crate) fn disk_space(path: &std::path::Path) -> IoResult<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+            return Err(IoError::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+
+        // `f_bavail` leaves out blocks only root may use, unlike `f_bfree`.
+        #[allow(clippy::unnecessary_cast)]
+        let (fragment, available, blocks) = (stat.f_frsize as u64, stat.f_bavail as u64, stat.f_blocks as u64);
+        Ok((available * fragment, blocks * fragment))
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        #[link(name = "kernel32")]
+        unsafe extern "system" {
+            fn GetDiskFreeSpaceExW(
+                directory_name: *const u16,
+                free_bytes_available_to_caller: *mut u64, total_number_of_bytes: *mut u64,
+                total_number_of_free_bytes: *mut u64,
+            ) -> i32;
+        }
+
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let (mut available, mut total) = (0, 0);
+        if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut available, &mut total, std::ptr::null_mut()) } == 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok((available, total))
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        Err(IoError::new(IoErrorKind::Unsupported, "querying disk space is not supported on this platform"))
+    }
+}
+
+/// Whether the file whose handle gave `metadata` has been removed from the filesystem. On Unix
+/// that's its link count dropping to 0, so a file renamed away still counts as there; elsewhere
+/// it's `path` no longer existing.
+pub(crate) fn is_unlinked(metadata: &fs::Metadata, path: &std::path::Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let _ = path;
+        metadata.nlink() == 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        matches!(fs::symlink_metadata(path), Err(error) if error.kind() == IoErrorKind::NotFound)
+    }
+}
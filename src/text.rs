@@ -0,0 +1,152 @@
+//! Text transforms applied to bytes as they're written, so generated files come out consistent
+//! regardless of how the source fragments were formatted.
+
+/// Line ending style to normalize to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Keep each line's original ending.
+    #[default]
+    Preserve,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+/// Controls how text written to a file is normalized. The default leaves bytes untouched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextPolicy {
+    /// Line ending every `\n`, `\r\n` and lone `\r` is rewritten to.
+    pub line_ending: LineEnding,
+    /// Strips spaces and tabs at the end of every line.
+    pub trim_trailing_whitespace: bool,
+}
+
+/// Streaming normalizer for a [`TextPolicy`]. Whitespace and carriage returns at the end of a
+/// chunk are held back until the following bytes show whether they end a line.
+#[derive(Default)]
+pub(crate) struct TextNormalizer {
+    policy: TextPolicy,
+    pending_ws: Vec<u8>,
+    pending_cr: bool,
+    /// Set when [`Self::release`] emitted a `\r` as a line ending, so a `\n` right after it is
+    /// taken as part of the same one.
+    released_cr: bool,
+}
+
+impl TextNormalizer {
+    pub fn set_policy(&mut self, policy: TextPolicy) {
+        self.policy = policy;
+    }
+
+    fn is_passthrough(&self) -> bool {
+        self.policy == TextPolicy::default() && self.pending_ws.is_empty() && !self.pending_cr && !self.released_cr
+    }
+
+    fn newline(&mut self, original: &[u8], out: &mut Vec<u8>) {
+        if self.policy.trim_trailing_whitespace {
+            self.pending_ws.clear();
+        } else {
+            out.append(&mut self.pending_ws);
+        }
+
+        match self.policy.line_ending {
+            LineEnding::Preserve => out.extend_from_slice(original),
+            LineEnding::Lf => out.push(b'\n'),
+            LineEnding::CrLf => out.extend_from_slice(b"\r\n"),
+        }
+    }
+
+    /// Normalizes `buf`, appending the output to `out`.
+    pub fn push(&mut self, buf: &[u8], out: &mut Vec<u8>) {
+        if self.is_passthrough() {
+            out.extend_from_slice(buf);
+            return;
+        }
+
+        for &byte in buf {
+            if std::mem::take(&mut self.released_cr) && byte == b'\n' {
+                if self.policy.line_ending == LineEnding::Preserve {
+                    out.push(byte);
+                }
+                continue;
+            }
+
+            if self.pending_cr {
+                self.pending_cr = false;
+                if byte == b'\n' {
+                    self.newline(b"\r\n", out);
+                    continue;
+                }
+                self.newline(b"\r", out);
+            }
+
+            match byte {
+                b'\r' => self.pending_cr = true,
+                b'\n' => self.newline(b"\n", out),
+                b' ' | b'\t' if self.policy.trim_trailing_whitespace => self.pending_ws.push(byte),
+                _ => {
+                    out.append(&mut self.pending_ws);
+                    out.push(byte);
+                },
+            }
+        }
+    }
+
+    /// Emits anything held back without waiting for the bytes after it, for when the file is
+    /// flushed mid-line. Held back whitespace is kept as is, since it may not be trailing, and a
+    /// `\r` is written as a line ending, with a `\n` written next folded into it.
+    pub fn release(&mut self, out: &mut Vec<u8>) {
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.released_cr = true;
+            self.newline(b"\r", out);
+        }
+
+        out.append(&mut self.pending_ws);
+    }
+
+    /// Emits anything still held back, treating it as the end of the text.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        self.released_cr = false;
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.newline(b"\r", out);
+        }
+
+        if self.policy.trim_trailing_whitespace {
+            self.pending_ws.clear();
+        } else {
+            out.append(&mut self.pending_ws);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(policy: TextPolicy, chunks: &[&str]) -> String {
+        let mut normalizer = TextNormalizer::default();
+        normalizer.set_policy(policy);
+
+        let mut out = Vec::new();
+        for chunk in chunks {
+            normalizer.push(chunk.as_bytes(), &mut out);
+        }
+        normalizer.finish(&mut out);
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn mixed_line_endings_and_trailing_whitespace() {
+        let chunks = ["one  \r", "\ntwo\t\rthree \n", "four \t", " \r\nfive  "];
+        let trim = |line_ending| TextPolicy { line_ending, trim_trailing_whitespace: true };
+
+        assert_eq!(normalize(trim(LineEnding::Lf), &chunks), "one\ntwo\nthree\nfour\nfive");
+        assert_eq!(normalize(trim(LineEnding::CrLf), &chunks), "one\r\ntwo\r\nthree\r\nfour\r\nfive");
+        assert_eq!(normalize(trim(LineEnding::Preserve), &chunks), "one\r\ntwo\rthree\nfour\r\nfive");
+        assert_eq!(normalize(TextPolicy::default(), &chunks), chunks.concat());
+    }
+}
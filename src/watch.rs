@@ -0,0 +1,71 @@
+//! Change notifications for physical directories, built on the [`notify`] crate.
+
+use notify::{Event, EventKind, RecursiveMode, RecommendedWatcher, Watcher};
+use notify::event::{ModifyKind, RenameMode};
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::path::{PathBuf, Path};
+
+use super::{FsError, FsResult};
+
+/// A change to an entry of a watched directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FsEvent {
+    Created(PathBuf),
+    /// The entry's contents or metadata changed.
+    Modified(PathBuf),
+    Removed(PathBuf),
+    /// Some platforms report each side of a rename on its own as well, so this may follow a
+    /// `Removed` of `from` and a `Created` of `to`. Entries moved in or out of the directory only
+    /// show up as the latter.
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+/// Watches the entries directly inside `path`, calling `on_change` and sending the matching
+/// [`FsEvent`]s for every change. The returned watcher stops when dropped.
+pub(crate) fn watch_dir<F>(path: &Path, mut on_change: F) -> FsResult<(RecommendedWatcher, Receiver<FsEvent>)>
+where
+    F: FnMut() + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        // Backend errors have nowhere to go but the next event.
+        let Ok(event) = event else { return };
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Any | EventKind::Other) {
+            return;
+        }
+
+        on_change();
+        send_events(&sender, event);
+    }).map_err(|err| FsError::generic(format!("failed to start watching '{}': {err}", path.display())))?;
+
+    watcher.watch(path, RecursiveMode::NonRecursive)
+        .map_err(|err| FsError::generic(format!("failed to watch '{}': {err}", path.display())))?;
+
+    Ok((watcher, receiver))
+}
+
+fn send_events(sender: &Sender<FsEvent>, event: Event) {
+    let mut paths = event.paths.into_iter();
+    let events: Vec<FsEvent> = match event.kind {
+        EventKind::Create(_) => paths.map(FsEvent::Created).collect(),
+        EventKind::Remove(_) => paths.map(FsEvent::Removed).collect(),
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match (paths.next(), paths.next()) {
+            (Some(from), Some(to)) => vec![FsEvent::Renamed { from, to }],
+            _ => Vec::new(),
+        },
+        // Only one side of the rename was seen, so it's as good as a creation or removal here.
+        EventKind::Modify(ModifyKind::Name(_)) => paths
+            .map(|path| if path.exists() { FsEvent::Created(path) } else { FsEvent::Removed(path) })
+            .collect(),
+        EventKind::Modify(_) => paths.map(FsEvent::Modified).collect(),
+        _ => Vec::new(),
+    };
+
+    for event in events {
+        // The receiver was dropped; nothing left to report to.
+        if sender.send(event).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Plumbing shared by the backends that wrap another directory and hand out wrappers of its
+//! children in turn, [`ChrootDirectory`](crate::chroot::ChrootDirectory) and
+//! [`QuotaDirectory`](crate::quota::QuotaDirectory).
+
+use std::io::Result as IoResult;
+use std::sync::RwLock;
+use std::path::Path;
+use std::collections::HashMap;
+
+use super::{FilesystemObject, FsResult, FsKind};
+
+/// The wrappers handed out for a wrapped directory's children, kept by name so each child is
+/// wrapped once.
+pub(crate) struct WrappedChildren(RwLock<HashMap<String, FilesystemObject>>);
+
+impl WrappedChildren {
+    pub(crate) fn new() -> Self {
+        Self(RwLock::new(HashMap::new()))
+    }
+
+    /// Returns the wrapper for the inner child `child` named `name`, making one with `make` from
+    /// the name, the child and its kind if there isn't one yet.
+    pub(crate) fn wrap(
+        &self, name: &str, child: FilesystemObject,
+        make: impl FnOnce(&str, FilesystemObject, FsKind) -> FsResult<FilesystemObject>,
+    ) -> FsResult<FilesystemObject> {
+        if let Some(wrapped) = self.0.read().unwrap().get(name) {
+            return Ok(wrapped.clone());
+        }
+
+        let kind = child.read().unwrap().kind();
+        let wrapped = make(name, child, kind)?;
+        self.0.write().unwrap().insert(name.to_string(), wrapped.clone());
+
+        Ok(wrapped)
+    }
+
+    /// Like [`Self::wrap`], under the inner child's own name, as for children the inner
+    /// directory named itself.
+    pub(crate) fn wrap_named(
+        &self, child: FilesystemObject,
+        make: impl FnOnce(&str, FilesystemObject, FsKind) -> FsResult<FilesystemObject>,
+    ) -> FsResult<FilesystemObject> {
+        let name = child.read().unwrap().name().to_string_lossy().to_string();
+        self.wrap(&name, child, make)
+    }
+
+    /// Renames the inner child `name` to `new_name`. Wrappers carry their path, so the ones for
+    /// either name are dropped to be rebuilt on the next lookup.
+    pub(crate) fn child_rename(&self, inner: &FilesystemObject, name: &str, new_name: &str) -> FsResult {
+        inner.write().unwrap().child_rename(name, new_name)?;
+
+        let mut children = self.0.write().unwrap();
+        children.remove(name);
+        children.remove(new_name);
+
+        Ok(())
+    }
+
+    /// Repairs the inner directory's cache, then drops the wrappers of children that are gone or
+    /// changed kind.
+    pub(crate) fn repair(&self, inner: &FilesystemObject) -> FsResult {
+        inner.write().unwrap().repair_cache()?;
+
+        let inner = inner.read().unwrap();
+        self.0.write().unwrap().retain(|name, wrapped| match inner.get_child(name) {
+            Ok(child) => child.read().unwrap().is_dir() == wrapped.read().unwrap().is_dir(),
+            Err(_) => false,
+        });
+
+        Ok(())
+    }
+
+    pub(crate) fn insert(&self, name: &str, wrapped: FilesystemObject) {
+        self.0.write().unwrap().insert(name.to_string(), wrapped);
+    }
+
+    pub(crate) fn remove(&self, name: &str) {
+        self.0.write().unwrap().remove(name);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+/// Moves the wrapper `wrapped` from `old_name` to `new_name` in its parent's cache, after the
+/// object underneath was renamed.
+pub(crate) fn recache_renamed(parent: &FilesystemObject, old_name: &Path, new_name: &str, wrapped: FilesystemObject) -> FsResult {
+    let mut parent = parent.write().unwrap();
+    parent.drop_child(&old_name.to_string_lossy())?;
+    parent.cache_child(new_name, wrapped)
+}
+
+/// Copies the inner file's buffered bytes into `buffer` for a wrapper's `BufRead` impl, since
+/// they can't be lent out past the inner file's lock guard.
+pub(crate) fn fill_buf_copy<'a>(inner: &FilesystemObject, buffer: &'a mut Vec<u8>) -> IoResult<&'a [u8]> {
+    let mut inner = inner.write().unwrap();
+    let available = inner.fill_buf()?;
+    buffer.clear();
+    buffer.extend_from_slice(available);
+
+    Ok(buffer)
+}
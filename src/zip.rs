@@ -1,74 +1,241 @@
 //! The [zip](`self`) module provides an abstraction over [`ZipArchive`]s and [`ZipFile`]s from the
 //! [`zip`] crate
+//!
+//! Archives always separate directories with `/`, so entry names, and names passed in to look
+//! entries up, have any `\` turned into `/` first, letting `sub\file.txt` find `sub/file.txt`.
+//! Entries are named that way as [`name`](FilesystemObjectRaw::name), while
+//! [`get_full_path`](FilesystemObjectRaw::get_full_path) uses the platform's separator.
+//!
+//! An entry name that's absolute, starts with a drive letter or has a `..` segment could lead
+//! outside the archive's root when joined onto a path ("Zip Slip"), so such entries are left out
+//! of the archive's children, and looking one up errors with [`FsError::UnsafePath`].
 
-use zip::ZipArchive;
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
+use zip::write::SimpleFileOptions;
+use zip::result::ZipError;
+use flate2::{Crc, read::DeflateDecoder};
 
 use std::io::{
     Result as IoResult, Error as IoError, ErrorKind as IoErrorKind,
     BufRead, Write, Read,
     Seek, SeekFrom,
 };
-use std::cell::{OnceCell, RefCell};
-use std::sync::{RwLock, Weak, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, RwLock, Weak, Arc};
 use std::path::{PathBuf, Path};
-use std::collections::HashMap;
+use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
 use std::fs;
+use std::fmt;
 
 use super::{
-    FilesystemObject, Directory, File,
-    FsError, Result,
+    FilesystemObject, FilesystemObjectRaw, Capabilities, ObjectId,
+    FsError, FsResult, FsKind, DirEntry,
+    error::read_only_io,
+    civil_time, path_segments, check_segment,
 };
 
+/// How entries added to a [`ZipDirectory`] are compressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ZipCompression {
+    /// No compression, for data that's already compressed.
+    Stored,
+    #[default]
+    Deflated,
+    Zstd,
+}
+
+impl From<ZipCompression> for CompressionMethod {
+    fn from(compression: ZipCompression) -> Self {
+        match compression {
+            ZipCompression::Stored => CompressionMethod::Stored,
+            ZipCompression::Deflated => CompressionMethod::Deflated,
+            ZipCompression::Zstd => CompressionMethod::Zstd,
+        }
+    }
+}
+
+/// What the archive's central directory records about an entry, from [`ZipFile::entry_info`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZipEntryInfo {
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    pub method: CompressionMethod,
+    pub crc32: u32,
+    /// The entry's own comment, empty if it has none.
+    pub comment: String,
+    /// `None` if the stored timestamp isn't a valid date.
+    pub last_modified: Option<SystemTime>,
+}
+
+/// Compression level used with [`ZipCompression::Deflated`] unless set otherwise.
+const DEFAULT_COMPRESSION_LEVEL: i64 = 6;
+
+/// Buffer size of the files [`ZipDirectory::extract_to`] writes.
+const EXTRACT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// `name` with `\\` separators turned into the `/` archives use.
+fn archive_name(name: &str) -> Cow<'_, str> {
+    match name.contains('\\') {
+        true => Cow::Owned(name.replace('\\', "/")),
+        false => Cow::Borrowed(name),
+    }
+}
+
+/// `name` with `/` separators, or [`FsError::UnsafePath`] if joining it onto a path could lead
+/// outside that path: it's absolute, starts with a drive letter, or has a `..` segment.
+fn safe_name(name: &str) -> FsResult<Cow<'_, str>> {
+    let normalized = archive_name(name);
+    let drive = normalized.as_bytes().get(1) == Some(&b':') && normalized.as_bytes()[0].is_ascii_alphabetic();
+    if normalized.starts_with('/') || drive || normalized.split('/').any(|segment| segment == "..") {
+        return Err(FsError::unsafe_path(name.to_string()));
+    }
+
+    Ok(normalized)
+}
+
+/// An archive name as a relative path with the platform's separator.
+fn native_path(name: &str) -> PathBuf {
+    name.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
 pub struct ZipDirectory {
     name: PathBuf,
-    parent: Arc<RwLock<dyn Directory>>,
+    path: PathBuf,
+    parent: Option<FilesystemObject>,
+
+    children: RwLock<HashMap<String, Arc<RwLock<ZipFile>>>>,
+    scanned: AtomicBool,
+    readonly: bool,
+
+    /// The file the archive was opened from.
+    source: FilesystemObject,
+    /// Whether the archive is a file on disk, rather than e.g. an entry of another archive.
+    on_disk: bool,
+    archive: Arc<RwLock<ZipArchive<ArchiveReader>>>,
+    /// Password encrypted entries are decrypted with, handed on to every entry.
+    password: Option<Arc<[u8]>>,
+    compression: (ZipCompression, i64),
+    handle: OnceLock<Weak<RwLock<Self>>>,
+}
 
-    children: RefCell<HashMap<String, Arc<RwLock<ZipFile>>>>,
-    scanned: RefCell<bool>,
+type EntryStream = Box<dyn Read + Send + Sync>;
 
-    archive: Arc<RwLock<ZipArchive<fs::File>>>,
-    handle: OnceCell<Weak<RwLock<Self>>>,
+/// Where a [`ZipDirectory`] reads its archive from: the file on disk, or the object holding the
+/// archive, e.g. an entry of an enclosing archive.
+pub struct ArchiveReader(ReaderSource);
+
+enum ReaderSource {
+    File(fs::File),
+    /// Reads seek the object to `position` first, so other users moving it around don't matter.
+    Object { object: FilesystemObject, position: u64 },
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        match &mut self.0 {
+            ReaderSource::File(file) => file.read(buf),
+            ReaderSource::Object { object, position } => {
+                let mut object = object.write().unwrap();
+                object.seek(SeekFrom::Start(*position))?;
+                let count = object.read(buf)?;
+                *position += count as u64;
+
+                Ok(count)
+            },
+        }
+    }
+}
+
+impl Seek for ArchiveReader {
+    fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
+        match &mut self.0 {
+            ReaderSource::File(file) => file.seek(pos),
+            ReaderSource::Object { object, position } => {
+                *position = match pos {
+                    SeekFrom::Start(pos) => pos,
+                    SeekFrom::End(_) => object.write().unwrap().seek(pos)?,
+                    SeekFrom::Current(offset) => position.checked_add_signed(offset)
+                        .ok_or_else(|| IoError::new(IoErrorKind::InvalidInput, "Invalid seek offset"))?,
+                };
+
+                Ok(*position)
+            },
+        }
+    }
 }
 
 pub struct ZipFile {
     name: PathBuf,
+    path: PathBuf,
     file_index: usize,
     parent: Arc<RwLock<ZipDirectory>>,
 
+    archive: Arc<RwLock<ZipArchive<ArchiveReader>>>,
+    /// The archive's path, if it's a file on disk that entries can be streamed from directly.
+    archive_path: Option<PathBuf>,
+    password: Option<Arc<[u8]>>,
+    /// Decompressing reader kept alive between buffer fills, along with the offset it's at, so
+    /// forward reads don't decompress the entry from the start every time.
+    stream: Option<(u64, EntryStream)>,
     buffer: Vec<u8>,
     seek_offset: i64,
     buf_filled: usize,
     cursor: usize,
 
-    handle: OnceCell<Weak<RwLock<Self>>>,
+    handle: OnceLock<Weak<RwLock<Self>>>,
 }
 
 impl ZipDirectory {
-    pub fn new(file: Arc<RwLock<super::PhysicalFile>>) -> Result<Arc<RwLock<Self>>> {
-        let (name, parent, file) = {
+    /// Opens the archive held by `file`, which can be any file object: a physical file, or an
+    /// entry of another archive to browse nested archives without extracting them. Archives on
+    /// disk are read through a handle of their own, after flushing pending writes to `file`.
+    pub fn new(file: FilesystemObject) -> FsResult<Arc<RwLock<Self>>> {
+        Self::open(file, None)
+    }
+
+    /// Like [`Self::new`], but decrypts encrypted entries (AES or ZipCrypto) with `password` as
+    /// they're read. Reading an entry with the wrong password errors with [`FsError::ZipError`].
+    pub fn new_with_password(file: FilesystemObject, password: &[u8]) -> FsResult<Arc<RwLock<Self>>> {
+        Self::open(file, Some(password.into()))
+    }
+
+    fn open(file: FilesystemObject, password: Option<Arc<[u8]>>) -> FsResult<Arc<RwLock<Self>>> {
+        let (name, path, parent, on_disk) = {
             let mut file_guard = file.write().unwrap();
-            let path = Path::new(&file_guard.name()).to_path_buf();
-            let parent = file_guard.get_parent();
-            let file_handle = if let Some(fh) = file_guard.take_handle() {
-                fh
-            } else {
-                file_guard.open()?;
-                file_guard.take_handle().unwrap()
-            };
-            (path, parent, file_handle)
+            if !file_guard.is_file() {
+                return Err(FsError::not_a_file(file_guard.get_full_path().to_string_lossy().to_string(), file_guard.kind()));
+            }
+
+            let on_disk = file_guard.physical_path().is_some();
+            if on_disk {
+                file_guard.flush()?;
+            }
+            (file_guard.name().to_path_buf(), file_guard.get_full_path(), file_guard.get_parent(), on_disk)
         };
 
-        let archive = Arc::new(RwLock::new(ZipArchive::new(file)?));
+        let reader = match on_disk {
+            true => ReaderSource::File(fs::File::open(&path)?),
+            false => ReaderSource::Object { object: file.clone(), position: 0 },
+        };
+        let archive = Arc::new(RwLock::new(ZipArchive::new(ArchiveReader(reader))?));
 
         let new = Self{
             name,
+            path,
             parent,
 
-            children: RefCell::new(HashMap::new()),
-            scanned: RefCell::new(false),
+            children: RwLock::new(HashMap::new()),
+            scanned: AtomicBool::new(false),
+            readonly: false,
 
+            source: file,
+            on_disk,
             archive,
-            handle: OnceCell::new(),
+            password,
+            compression: (ZipCompression::default(), DEFAULT_COMPRESSION_LEVEL),
+            handle: OnceLock::new(),
         };
 
         let arc = Arc::new(RwLock::new(new));
@@ -77,28 +244,293 @@ impl ZipDirectory {
         Ok(arc)
     }
 
-    pub fn get_archive(&self) -> Arc<RwLock<ZipArchive<fs::File>>> {
+    pub fn get_archive(&self) -> Arc<RwLock<ZipArchive<ArchiveReader>>> {
         self.archive.clone()
     }
+
+    /// Sets how entries created in this archive, by [`Self::add_file`] or `new_file`, are
+    /// compressed, and at what level. The level is ignored for [`ZipCompression::Stored`].
+    /// Defaults to deflate at level 6. Existing entries keep their compression, since rewriting the
+    /// archive copies them raw.
+    pub fn set_compression(&mut self, method: ZipCompression, level: i64) {
+        self.compression = (method, level);
+    }
+
+    /// The compression method and level set by [`Self::set_compression`].
+    pub fn compression(&self) -> (ZipCompression, i64) {
+        self.compression
+    }
+
+    /// Adds an entry named `name` holding `contents`, compressed as set by
+    /// [`Self::set_compression`], by rewriting the archive with the entry appended. Errors with
+    /// [`FsError::AlreadyExists`] if an entry has that name already. Like renames, this needs the
+    /// archive to be a file on disk.
+    pub fn add_file(&mut self, name: &str, contents: &[u8]) -> FsResult<Arc<RwLock<ZipFile>>> {
+        self.check_writable()?;
+        let name = safe_name(name)?;
+        if self.has_child(&name)? {
+            return Err(FsError::already_exists(self.path.join(native_path(&name)).to_string_lossy().to_string()));
+        }
+        if !self.on_disk {
+            return Err(self.unsupported("new_file"));
+        }
+
+        let (method, level) = self.compression;
+        let level = match method {
+            ZipCompression::Stored => None,
+            _ => Some(level),
+        };
+        let options = SimpleFileOptions::default()
+            .compression_method(method.into())
+            .compression_level(level);
+
+        let index = self.archive.read().unwrap().len();
+        self.rewrite(|archive, writer| {
+            for i in 0..archive.len() {
+                writer.raw_copy_file(archive.by_index_raw(i)?)?;
+            }
+            writer.start_file(&*name, options)?;
+            writer.write_all(contents)?;
+            Ok(())
+        })?;
+
+        Ok(self.cached_entry(&name, index))
+    }
+
+    /// Returns the file entries whose names start with `prefix`, sorted by name. This is a plain
+    /// string match, so pass `src/` rather than `src` to leave out siblings like `src2/`. Only the
+    /// cached names are checked; the archive isn't read again.
+    pub fn entries_under(&self, prefix: &str) -> FsResult<Vec<FilesystemObject>> {
+        self.scan_if_needed()?;
+        let prefix = archive_name(prefix);
+
+        let children = self.children.read().unwrap();
+        let mut entries: Vec<_> = children.iter()
+            .filter(|(name, _)| name.starts_with(&*prefix))
+            .collect();
+        entries.sort_unstable_by_key(|(name, _)| *name);
+
+        Ok(entries.into_iter().map(|(_, child)| child.clone() as FilesystemObject).collect())
+    }
+
+    /// Looks up the entry stored under exactly `archive_path`, e.g. `a/b.txt`, via the archive's
+    /// own name index rather than the cached children. The entry is cached like any other child, so
+    /// repeated lookups share one object. Errors with [`FsError::FileNotPresent`] if no entry has
+    /// that name, and [`FsError::NotAFile`] if it names a directory entry.
+    pub fn get_entry(&self, archive_path: &str) -> FsResult<Arc<RwLock<ZipFile>>> {
+        let archive_path = &*safe_name(archive_path)?;
+        if let Some(child) = self.children.read().unwrap().get(archive_path) {
+            return Ok(child.clone());
+        }
+
+        let index = {
+            let mut archive = self.archive.write().unwrap();
+            let index = archive.index_for_name(archive_path)
+                .ok_or_else(|| FsError::file_not_present(self.display_path(), archive_path.to_string()))?;
+            if archive.by_index_raw(index)?.is_dir() {
+                return Err(FsError::not_a_file(self.path.join(archive_path).to_string_lossy().to_string(), FsKind::Directory));
+            }
+            index
+        };
+
+        Ok(self.cached_entry(archive_path, index))
+    }
+
+    /// Recreates the archive's contents under `dest`, streaming each file entry out and creating
+    /// directories as needed. On Unix, files stored with permission bits get them. Entries whose
+    /// names are absolute or contain `..` would land outside `dest`, so they're skipped. Files
+    /// that already exist under `dest` make it error, unless `dest` allows overwriting them.
+    pub fn extract_to(&self, dest: FilesystemObject) -> FsResult {
+        let entries = {
+            let mut archive = self.archive.write().unwrap();
+            let mut entries = Vec::with_capacity(archive.len());
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i)?;
+                entries.push((i, file.name().to_string(), file.is_dir(), file.unix_mode()));
+            }
+            entries
+        };
+
+        // A name stored more than once is extracted from its first entry, as when scanning.
+        let mut extracted = HashSet::new();
+        for (index, name, is_dir, mode) in entries {
+            let Ok(archive_path) = safe_name(&name) else { continue };
+            let Ok(segments) = path_segments(&archive_path) else { continue };
+            let path = segments.join("/");
+            if !extracted.insert(path.clone()) {
+                continue;
+            }
+
+            if is_dir {
+                dest.write().unwrap().new_dir_all(&path)?;
+                continue;
+            }
+
+            let file = dest.write().unwrap().new_file_all(&path, EXTRACT_BUFFER_SIZE)?;
+            let mut file = file.write().unwrap();
+            let entry = self.cached_entry(&archive_path, index);
+            let mut entry = entry.write().unwrap();
+            entry.seek(SeekFrom::Start(0))?;
+            std::io::copy(&mut *entry, &mut *file)?;
+            file.flush()?;
+
+            #[cfg(unix)]
+            if let (Some(mode), Some(handle)) = (mode, file.as_std_file()) {
+                use std::os::unix::fs::PermissionsExt;
+                handle.set_permissions(fs::Permissions::from_mode(mode & 0o7777))?;
+            }
+            #[cfg(not(unix))]
+            let _ = mode;
+
+            file.close();
+        }
+
+        Ok(())
+    }
+
+    /// The cached object for the entry at `index`, stored under `name`, creating it if needed.
+    fn cached_entry(&self, name: &str, index: usize) -> Arc<RwLock<ZipFile>> {
+        let mut children = self.children.write().unwrap();
+        children.entry(name.to_string()).or_insert_with(|| ZipFile::new(self, name, index, 512)).clone()
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Errors with [`FsError::ReadOnly`] when the archive has been made read-only.
+    fn check_writable(&self) -> FsResult {
+        if self.readonly {
+            return Err(FsError::read_only(self.display_path()));
+        }
+
+        Ok(())
+    }
+
+    /// The error for mutations the zip backend can't perform. A read-only archive reports that
+    /// instead, since it wouldn't allow them either way.
+    fn unsupported(&self, op: &'static str) -> FsError {
+        match self.check_writable() {
+            Err(error) => error,
+            Ok(()) => FsError::unsupported("zip", op),
+        }
+    }
+
+    /// Writes a copy of the archive with the entry at `index` renamed, then swaps it in for the
+    /// original. Entries are copied raw in their original order, so nothing is recompressed and
+    /// every other entry keeps its index.
+    fn rewrite_renamed(&self, index: usize, new_name: &str) -> FsResult {
+        self.rewrite(|archive, writer| {
+            for i in 0..archive.len() {
+                let entry = archive.by_index_raw(i)?;
+                if i == index {
+                    writer.raw_copy_file_rename(entry, new_name)?;
+                } else {
+                    writer.raw_copy_file(entry)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes a new archive next to the original with `write`, which gets the original to copy
+    /// entries from, then swaps it in. The archive comment is carried over.
+    fn rewrite(
+        &self,
+        write: impl FnOnce(&mut ZipArchive<ArchiveReader>, &mut ZipWriter<fs::File>) -> FsResult,
+    ) -> FsResult {
+        let temp_name = format!(".{}.{}.tmp", self.name.to_string_lossy(), std::process::id());
+        let temp_path = self.path.with_file_name(temp_name);
+
+        let mut archive = self.archive.write().unwrap();
+        let result = (|| -> FsResult<ZipArchive<ArchiveReader>> {
+            let mut writer = ZipWriter::new(fs::File::create(&temp_path)?);
+            write(&mut archive, &mut writer)?;
+            writer.set_raw_comment(archive.comment().into());
+            writer.finish()?.sync_all()?;
+
+            fs::rename(&temp_path, &self.path)?;
+            Ok(ZipArchive::new(ArchiveReader(ReaderSource::File(fs::File::open(&self.path)?)))?)
+        })();
+
+        match result {
+            Ok(rewritten) => {
+                *archive = rewritten;
+                Ok(())
+            },
+            Err(error) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(error)
+            },
+        }
+    }
+
+    /// Renames the entry at `index`, cached as `name`, to `new_name` in the archive and the
+    /// children cache. The entry object's own name is left to the caller, which may hold its lock.
+    fn rename_entry(&self, index: usize, name: &str, new_name: &str) -> FsResult {
+        self.check_writable()?;
+        let new_name = safe_name(new_name)?;
+        if self.has_child(&new_name)? {
+            return Err(FsError::already_exists(self.path.join(native_path(&new_name)).to_string_lossy().to_string()));
+        }
+        if !self.on_disk {
+            return Err(self.unsupported("child_rename"));
+        }
+
+        self.rewrite_renamed(index, &new_name)?;
+
+        let mut children = self.children.write().unwrap();
+        if let Some(child) = children.remove(name) {
+            children.insert(new_name.into_owned(), child);
+        }
+
+        Ok(())
+    }
+
+    /// Populates the children cache from the archive's entries if it hasn't been already.
+    fn scan_if_needed(&self) -> FsResult {
+        if self.scanned.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        let mut archive_handle = self.archive.write().unwrap();
+
+        let mut children = self.children.write().unwrap();
+        for i in 0..archive_handle.len() {
+            let file = archive_handle.by_index_raw(i)?;
+            let Ok(child_name) = safe_name(file.name()) else { continue };
+            if file.is_file() && !children.contains_key(&*child_name) {
+                let child_name = child_name.into_owned();
+                let child = ZipFile::new(self, &child_name, i, 512);
+                children.insert(child_name, child);
+            }
+        }
+
+        self.scanned.store(true, Ordering::Release);
+
+        Ok(())
+    }
 }
 
 impl ZipFile {
-    fn new(name: &Path, parent: Arc<RwLock<ZipDirectory>>, buffer_size: usize) -> Arc<RwLock<Self>> {
-        let archive = parent.read().unwrap().get_archive();
-        let archive_handle = archive.read().unwrap();
-        let file_index = archive_handle.index_for_name(name.to_str().unwrap()).unwrap();
-
+    /// The entry at `file_index` in `dir`'s archive, stored under `name`.
+    fn new(dir: &ZipDirectory, name: &str, file_index: usize, buffer_size: usize) -> Arc<RwLock<Self>> {
         let new = Self{
-            name: name.to_path_buf(),
+            name: PathBuf::from(name),
+            path: dir.path.join(native_path(name)),
             file_index,
-            parent,
+            parent: dir.handle.get().unwrap().upgrade().unwrap(),
 
-            buffer: Vec::with_capacity(buffer_size),
+            archive: dir.archive.clone(),
+            archive_path: dir.on_disk.then(|| dir.path.clone()),
+            password: dir.password.clone(),
+            stream: None,
+            buffer: vec![0; buffer_size],
             seek_offset: 0,
             buf_filled: 0,
             cursor: 0,
 
-            handle: OnceCell::new(),
+            handle: OnceLock::new(),
         };
 
         let arc = Arc::new(RwLock::new(new));
@@ -107,17 +539,150 @@ impl ZipFile {
         arc
     }
 
-    pub fn get_archive(&self) -> Arc<RwLock<ZipArchive<fs::File>>> {
-        self.parent.read().unwrap().get_archive()
+    pub fn get_archive(&self) -> Arc<RwLock<ZipArchive<ArchiveReader>>> {
+        self.archive.clone()
     }
 
-    fn fill_buffer(&mut self) -> IoResult<()> {
+    /// The position the caller has read up to. `seek_offset` is where the buffer was filled up
+    /// to, and the caller is behind it by whatever is still unread.
+    fn logical_position(&self) -> i64 {
+        self.seek_offset - (self.buf_filled - self.cursor) as i64
+    }
+
+    fn display_path(&self) -> String {
+        self.get_full_path().to_string_lossy().to_string()
+    }
+
+    /// Sizes, compression method, CRC-32, comment and timestamp of the entry as recorded in the
+    /// archive, read without decompressing anything.
+    pub fn entry_info(&self) -> FsResult<ZipEntryInfo> {
         let archive = self.get_archive();
         let mut archive_handle = archive.write().unwrap();
-        let mut file = archive_handle.by_index_seek(self.file_index).unwrap();
+        let entry = archive_handle.by_index_raw(self.file_index)?;
 
-        file.seek_relative(self.seek_offset)?;
-        self.buf_filled = file.read(&mut self.buffer)?;
+        Ok(ZipEntryInfo{
+            compressed_size: entry.compressed_size(),
+            uncompressed_size: entry.size(),
+            method: entry.compression(),
+            crc32: entry.crc32(),
+            comment: entry.comment().to_string(),
+            last_modified: entry.last_modified().map(entry_time),
+        })
+    }
+
+    /// Reads the whole entry through the buffer and checks it against the CRC-32 stored in the
+    /// archive, returning `Ok(false)` on a mismatch. Data that fails to decompress errors with
+    /// [`FsError::ZipError`]. The entry is rewound first and left at its end.
+    pub fn verify(&mut self) -> FsResult<bool> {
+        let (expected, size) = {
+            let archive = self.get_archive();
+            let mut archive_handle = archive.write().unwrap();
+            let entry = archive_handle.by_index_raw(self.file_index)?;
+            (entry.crc32(), entry.size())
+        };
+
+        self.seek(SeekFrom::Start(0))?;
+        let mut crc = Crc::new();
+        let mut read = 0;
+        loop {
+            let chunk = match self.fill_buf() {
+                Ok(chunk) => chunk,
+                // The archive's own reader checks the CRC too, failing the read past the last byte.
+                Err(_) if read == size && crc.sum() != expected => return Ok(false),
+                Err(error) => return Err(match FsError::from(error) {
+                    FsError::IoError(error, ..) => FsError::from(ZipError::Io(error)),
+                    error => error,
+                }),
+            };
+            if chunk.is_empty() {
+                break;
+            }
+
+            let len = chunk.len();
+            crc.update(chunk);
+            read += len as u64;
+            self.consume(len);
+        }
+
+        Ok(crc.sum() == expected)
+    }
+
+    /// Opens the entry through the archive's own reader, decrypting it if the archive was given a
+    /// password. Zip errors travel inside the I/O error, so they come out as [`FsError::ZipError`].
+    fn open_entry<'a>(&self, archive: &'a mut ZipArchive<ArchiveReader>) -> IoResult<zip::read::ZipFile<'a, ArchiveReader>> {
+        let entry = match self.password.as_deref() {
+            Some(password) => archive.by_index_decrypt(self.file_index, password),
+            None => archive.by_index(self.file_index),
+        };
+
+        entry.map_err(|error| IoError::other(FsError::from(error)))
+    }
+
+    /// Opens an independent reader over the entry's data, positioned at `offset`. Returns `None`
+    /// for entries that need the archive's own reader (encrypted, not stored/deflated, or in an
+    /// archive that isn't on disk).
+    fn open_stream(&self, offset: u64) -> IoResult<Option<EntryStream>> {
+        let Some(archive_path) = self.archive_path.as_ref() else {
+            return Ok(None);
+        };
+        let (method, data_start, compressed_size) = {
+            let archive = self.get_archive();
+            let mut archive_handle = archive.write().unwrap();
+            let entry = archive_handle.by_index_raw(self.file_index)?;
+            if entry.encrypted() {
+                return Ok(None);
+            }
+            (entry.compression(), entry.data_start(), entry.compressed_size())
+        };
+
+        // A separate handle has its own cursor, so it can't be disturbed by other entries' reads.
+        let mut file = fs::File::open(archive_path)?;
+
+        let mut stream: EntryStream = match method {
+            CompressionMethod::Stored => {
+                let offset = offset.min(compressed_size);
+                file.seek(SeekFrom::Start(data_start + offset))?;
+                return Ok(Some(Box::new(file.take(compressed_size - offset))));
+            },
+            CompressionMethod::Deflated => {
+                file.seek(SeekFrom::Start(data_start))?;
+                Box::new(DeflateDecoder::new(file.take(compressed_size)))
+            },
+            _ => return Ok(None),
+        };
+
+        std::io::copy(&mut (&mut stream).take(offset), &mut std::io::sink())?;
+        Ok(Some(stream))
+    }
+
+    fn fill_buffer(&mut self) -> IoResult<()> {
+        let offset = self.seek_offset as u64;
+
+        // Keep the live stream when only moving forward; anything else starts a new one.
+        match self.stream.as_mut() {
+            Some((position, stream)) if *position <= offset => {
+                std::io::copy(&mut stream.take(offset - *position), &mut std::io::sink())?;
+                *position = offset;
+            },
+            _ => self.stream = self.open_stream(offset)?.map(|stream| (offset, stream)),
+        }
+
+        self.buf_filled = match self.stream.as_mut() {
+            Some((position, stream)) => {
+                let count = stream.read(&mut self.buffer)?;
+                *position += count as u64;
+                count
+            },
+            None => {
+                let archive = self.get_archive();
+                let mut archive_handle = archive.write().unwrap();
+                let mut file = self.open_entry(&mut archive_handle)?;
+
+                // Compressed entries can't seek, so decompress up to the offset and discard it.
+                std::io::copy(&mut (&mut file).take(offset), &mut std::io::sink())?;
+                file.read(&mut self.buffer)?
+            },
+        };
         self.seek_offset += self.buf_filled as i64;
         self.cursor = 0;
 
@@ -125,88 +690,283 @@ impl ZipFile {
     }
 }
 
-impl Directory for ZipDirectory {
-    fn get(&self) -> Arc<RwLock<dyn Directory>> {
+/// Converts an entry's timestamp, which zip stores without a time zone, taking it as UTC.
+fn entry_time(time: zip::DateTime) -> SystemTime {
+    civil_time(time.year() as i64, time.month() as u32, time.day() as u32, time.hour() as u32, time.minute() as u32, time.second() as u32, 0)
+}
+
+impl fmt::Debug for ZipDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Children are only counted, printing them would recurse through the whole tree.
+        f.debug_struct("ZipDirectory")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("scanned", &self.scanned.load(Ordering::Acquire))
+            .field("cached_children", &self.children.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Read for ZipDirectory {
+    fn read(&mut self, _buf: &mut [u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl BufRead for ZipDirectory {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn consume(&mut self, _amt: usize) { }
+}
+
+impl Seek for ZipDirectory {
+    fn seek(&mut self, _pos: SeekFrom) -> IoResult<u64> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+}
+
+impl Write for ZipDirectory {
+    fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl FilesystemObjectRaw for ZipDirectory {
+    fn get(&self) -> FilesystemObject {
         self.handle.get().unwrap().upgrade().unwrap()
     }
-    
-    fn name(&self) -> String {
-        self.name.to_string_lossy().to_string()
+
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn kind(&self) -> FsKind {
+        FsKind::Directory
     }
 
     fn exists(&self) -> bool {
-        let path = self.get_full_path();
-        path.exists() && path.is_dir()
+        self.source.read().unwrap().exists()
     }
 
-    fn get_parent(&self) -> Option<Arc<RwLock<dyn Directory>>> {
-        Some(self.parent.clone())
+    fn size(&self) -> FsResult<usize> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn position(&self) -> FsResult<u64> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn read_at(&self, _offset: u64, _len: usize) -> FsResult<Vec<u8>> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    /// The modification time of the archive file itself.
+    fn modified(&self) -> FsResult<SystemTime> {
+        self.source.read().unwrap().modified()
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        self.parent.clone()
     }
-    
+
     fn get_full_path(&self) -> PathBuf {
-        self.parent.read().unwrap().get_full_path().join(&self.name)
+        self.path.clone()
+    }
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Ok(ObjectId::Archive { archive: Arc::as_ptr(&self.archive) as *const () as usize, index: None })
     }
 
-    fn get_children(&self) -> Result<Vec<FilesystemObject>> {
-        if *self.scanned.borrow() {
-            let children: Vec<FilesystemObject> = self.children.borrow().values()
-                .map(|child| FilesystemObject::File(child.clone()))
-                .collect();
+    fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    fn set_readonly(&mut self, readonly: bool) -> FsResult {
+        self.readonly = readonly;
+        Ok(())
+    }
 
-            Ok(children)
-        } else {
-            let handle = self.handle.get().unwrap().upgrade().unwrap();
-            let children = self.archive.read().unwrap().file_names()
-                .map(|name| {
-                    FilesystemObject::File(ZipFile::new(Path::new(name), handle.clone(), 512))
-                })
-                .collect::<Vec<_>>();
-            Ok(children)
+    fn capabilities(&self) -> Capabilities {
+        Capabilities{
+            // Renaming rewrites the archive file, so it has to be on disk.
+            can_rename: !self.readonly() && self.on_disk,
+            has_timestamps: true,
+            ..Capabilities::default()
         }
     }
 
-    fn get_child(&self, name: &str) -> Result<FilesystemObject> {
-        let children = self.children.borrow();
-        let lookup_result = children.get(name)
-            .map(|child| FilesystemObject::File(child.clone()))
-            .ok_or_else(|| FsError::FileNotPresent(self.get_full_path().to_string_lossy().to_string(), name.to_string()))?;
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("zip".to_string())
+    }
 
-        Ok(lookup_result.clone())
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("zip", "free_space"))
     }
-    
-    fn has_child(&self, name: &str) -> Result<bool> {
-        Ok(self.children.borrow().contains_key(name))
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("zip", "total_space"))
     }
 
-    fn new_file(&mut self, _name: String, _buffer_size: usize) -> Result<Arc<RwLock<dyn File>>> {
-        unimplemented!()
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(self.unsupported("move_to"))
     }
 
-    fn new_dir(&mut self, _name: String) -> Result<Arc<RwLock<dyn Directory>>> {
-        unimplemented!()
+    fn open(&mut self) -> IoResult<()> {
+        Err(IoError::from(IoErrorKind::IsADirectory))
     }
 
-    fn scan(&mut self) -> Result<()> {
-        if !*self.scanned.borrow() && self.exists() {
-            let handle = self.handle.get().unwrap().upgrade().unwrap();
-            let archive = self.archive.clone();
-            let mut archive_handle = archive.write().unwrap();
+    fn is_open(&self) -> bool {
+        false
+    }
 
-            let mut children = self.children.borrow_mut();
-            for i in 0..archive_handle.len() {
-                let file = archive_handle.by_index(i)?;
-                if file.is_file() {
-                    let child_name = file.name();
-                    let child = ZipFile::new(Path::new(child_name), handle.clone(), 512);
-                    children.insert(child_name.to_string(), child);
-                }
-            }
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_file(self.display_path(), self.kind()))
+    }
 
-            *self.scanned.borrow_mut() = true;
+    fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        let children = self.children.read().unwrap();
+        let mut entries: Vec<_> = children.iter().collect();
+        entries.sort_unstable_by_key(|(name, _)| *name);
+
+        Ok(entries.into_iter().map(|(_, child)| child.clone() as FilesystemObject).collect())
+    }
+
+    fn count_children(&self) -> FsResult<usize> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().len())
+    }
+
+    /// Reads the central directory, without building entry objects.
+    fn list(&self) -> FsResult<Vec<DirEntry>> {
+        let mut archive_handle = self.archive.write().unwrap();
+
+        let mut entries: Vec<DirEntry> = Vec::with_capacity(archive_handle.len());
+        for i in 0..archive_handle.len() {
+            let file = archive_handle.by_index_raw(i)?;
+            let Ok(name) = safe_name(file.name()) else { continue };
+            if file.is_file() {
+                entries.push(DirEntry{ name: name.into_owned(), kind: FsKind::File, size_hint: Some(file.size()) });
+            }
         }
+        // A name stored more than once resolves to its first entry, as when scanning.
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries.dedup_by(|a, b| a.name == b.name);
+
+        Ok(entries)
+    }
+
+    fn get_child(&self, name: &str) -> FsResult<FilesystemObject> {
+        self.try_get_child(name)?
+            .ok_or_else(|| FsError::file_not_present(self.display_path(), name.to_string()))
+    }
+
+    fn try_get_child(&self, name: &str) -> FsResult<Option<FilesystemObject>> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().get(&*safe_name(name)?).map(|child| child.clone() as FilesystemObject))
+    }
+
+    fn has_child(&self, name: &str) -> FsResult<bool> {
+        self.scan_if_needed()?;
+
+        Ok(self.children.read().unwrap().contains_key(&*safe_name(name)?))
+    }
+
+    /// Renames an entry by rewriting the archive's headers; the entry data is copied as is.
+    fn child_rename(&mut self, name: &str, new_name: &str) -> FsResult {
+        self.check_writable()?;
+        self.scan_if_needed()?;
+        let name = archive_name(name);
 
+        let Some(child) = self.children.read().unwrap().get(&*name).cloned() else {
+            return Err(FsError::file_not_present(self.display_path(), name.to_string()));
+        };
+        let index = child.read().unwrap().file_index;
+        self.rename_entry(index, &name, new_name)?;
+
+        let new_name = safe_name(new_name)?;
+        let mut child = child.write().unwrap();
+        child.name = PathBuf::from(&*new_name);
+        child.path = self.path.join(native_path(&new_name));
+        // Headers before the entry's data may have changed length, so its offset moved.
+        child.stream = None;
+
+        Ok(())
+    }
+
+    fn rename(&mut self, _new_name: &str) -> FsResult {
+        Err(self.unsupported("rename"))
+    }
+
+    /// Adds an empty entry with [`ZipDirectory::add_file`]. Entries can't be written to, so use
+    /// that to give one contents.
+    fn new_file(&mut self, name: &str, buffer_size: usize) -> FsResult<FilesystemObject> {
+        let entry = self.add_file(name, &[])?;
+        entry.write().unwrap().set_buffer_size(buffer_size);
+
+        Ok(entry)
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(self.unsupported("new_dir"))
+    }
+
+    fn new_file_all(&mut self, _path: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(self.unsupported("new_file_all"))
+    }
+
+    fn new_dir_all(&mut self, _path: &str) -> FsResult<FilesystemObject> {
+        Err(self.unsupported("new_dir_all"))
+    }
+
+    fn drop_child(&mut self, name: &str) -> FsResult {
+        self.children.write().unwrap().remove(&*archive_name(name));
         Ok(())
     }
+
+    fn set_buffer_size(&mut self, _size: usize) { }
+
+    fn scan(&mut self) -> FsResult<()> {
+        self.scanned.store(false, Ordering::Release);
+        self.scan_if_needed()
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(self.unsupported("delete"))
+    }
+}
+
+impl fmt::Debug for ZipFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZipFile")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("open", &self.is_open())
+            .finish()
+    }
 }
 
 impl Read for ZipFile {
@@ -241,84 +1001,150 @@ impl BufRead for ZipFile {
 
 impl Seek for ZipFile {
     fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
-        self.buf_filled = 0;
-        self.cursor = 0;
-
-        match pos {
-            SeekFrom::Start(pos) => self.seek_offset = pos as i64,
+        let position = self.logical_position();
+        let target = match pos {
+            SeekFrom::Start(pos) => pos as i64,
             SeekFrom::End(pos) => {
                 let archive = self.get_archive();
                 let mut archive_handle = archive.write().unwrap();
-                let file = archive_handle.by_index(self.file_index).unwrap();
+                let file = archive_handle.by_index_raw(self.file_index)?;
 
-                self.seek_offset = file.size() as i64 + pos;
+                file.size() as i64 + pos
             },
-            SeekFrom::Current(pos) => self.seek_offset += pos,
-        }
+            SeekFrom::Current(pos) => position + pos,
+        };
 
-        if self.seek_offset < 0 {
+        if target < 0 {
             return Err(IoError::new(IoErrorKind::InvalidInput, "Invalid seek offset"));
         }
 
-        Ok(self.seek_offset as u64)
+        // Staying put keeps the buffered bytes, so `stream_position` doesn't cost a refill.
+        if target != position {
+            self.buf_filled = 0;
+            self.cursor = 0;
+            self.seek_offset = target;
+        }
+
+        Ok(target as u64)
     }
 }
 
 impl Write for ZipFile {
     fn write(&mut self, _buf: &[u8]) -> IoResult<usize> {
-        unimplemented!()
+        Err(read_only_io(self.display_path()))
     }
 
     fn flush(&mut self) -> IoResult<()> {
-        unimplemented!()
+        Ok(())
     }
 }
 
-impl File for ZipFile {
-    fn get(&self) -> Arc<RwLock<dyn File>> {
+impl FilesystemObjectRaw for ZipFile {
+    fn get(&self) -> FilesystemObject {
         self.handle.get().unwrap().upgrade().unwrap()
     }
-    
-    fn name(&self) -> String {
-        self.name.to_string_lossy().to_string()
+
+    fn name(&self) -> &Path {
+        &self.name
     }
-    
-    fn stem(&self) -> String {
-        self.name.file_stem().unwrap().to_string_lossy().to_string()
+
+    fn kind(&self) -> FsKind {
+        FsKind::File
     }
-    
-    fn ext(&self) -> String {
-        self.name.extension().unwrap().to_string_lossy().to_string()
+
+    fn exists(&self) -> bool {
+        true
     }
-    
-    fn size(&self) -> Result<usize> {
+
+    fn size(&self) -> FsResult<usize> {
         let size = self.get_archive()
             .write().unwrap()
-            .by_index_raw(self.file_index).unwrap()
+            .by_index_raw(self.file_index)?
             .size();
         Ok(size as usize)
     }
 
-    fn exists(&self) -> bool {
-        true
+    fn truncate(&mut self, _len: usize) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
     }
 
-    fn rename(&self, _name: &str) -> Result<()> {
-        unimplemented!()
+    fn position(&self) -> FsResult<u64> {
+        Ok(self.logical_position() as u64)
     }
 
-    fn get_parent(&self) -> Arc<RwLock<dyn Directory>> {
-        self.parent.clone()
+    /// Decompresses into a reader of its own, so the position and the stream kept for sequential
+    /// reads are left alone.
+    fn read_at(&self, offset: u64, len: usize) -> FsResult<Vec<u8>> {
+        let mut data = Vec::new();
+        match self.open_stream(offset)? {
+            Some(stream) => stream.take(len as u64).read_to_end(&mut data)?,
+            None => {
+                let archive = self.get_archive();
+                let mut archive_handle = archive.write().unwrap();
+                let mut file = self.open_entry(&mut archive_handle)?;
+
+                std::io::copy(&mut (&mut file).take(offset), &mut std::io::sink())?;
+                file.take(len as u64).read_to_end(&mut data)?
+            },
+        };
+
+        Ok(data)
+    }
+
+    /// Zip entries record local time without a zone, so the time is taken as UTC.
+    fn modified(&self) -> FsResult<SystemTime> {
+        let archive = self.get_archive();
+        let mut archive_handle = archive.write().unwrap();
+        let time = archive_handle.by_index_raw(self.file_index)?.last_modified()
+            .ok_or_else(|| IoError::new(IoErrorKind::InvalidData, format!("'{}' has an invalid modification time", self.display_path())))?;
+
+        Ok(entry_time(time))
+    }
+
+    fn get_parent(&self) -> Option<FilesystemObject> {
+        Some(self.parent.clone())
     }
 
     fn get_full_path(&self) -> PathBuf {
-        self.parent.read().unwrap().get_full_path()
+        self.path.clone()
     }
-    
-    fn set_buffer_size(&mut self, size: usize) {
-        self.buffer.resize(size, 0);
+
+    fn identity(&self) -> FsResult<ObjectId> {
+        Ok(ObjectId::Archive { archive: Arc::as_ptr(&self.archive) as *const () as usize, index: Some(self.file_index as u64) })
+    }
+
+    fn readonly(&self) -> bool {
+        true
+    }
+
+    fn set_readonly(&mut self, _readonly: bool) -> FsResult {
+        Ok(())
+    }
+
+    /// Entries are renamed through their directory's [`child_rename`](FilesystemObjectRaw::child_rename).
+    fn capabilities(&self) -> Capabilities {
+        Capabilities{
+            has_timestamps: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn fs_type(&self) -> FsResult<String> {
+        Ok("zip".to_string())
+    }
+
+    fn free_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("zip", "free_space"))
+    }
+
+    fn total_space(&self) -> FsResult<u64> {
+        Err(FsError::unsupported("zip", "total_space"))
     }
-    
+
+    fn move_to(&mut self, _new_dir: FilesystemObject) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+
     fn open(&mut self) -> IoResult<()> {
         Ok(())
     }
@@ -327,5 +1153,461 @@ impl File for ZipFile {
         true
     }
 
+    fn sync_all(&mut self) -> FsResult {
+        Err(FsError::unsupported("zip", "sync_all"))
+    }
+
+    fn sync_data(&mut self) -> FsResult {
+        Err(FsError::unsupported("zip", "sync_data"))
+    }
+
+    fn duplicate(&self) -> FsResult<FilesystemObject> {
+        let duplicate = Self{
+            name: self.name.clone(),
+            path: self.path.clone(),
+            file_index: self.file_index,
+            parent: self.parent.clone(),
+
+            archive: self.archive.clone(),
+            archive_path: self.archive_path.clone(),
+            password: self.password.clone(),
+            stream: None,
+            buffer: vec![0; self.buffer.len()],
+            seek_offset: 0,
+            buf_filled: 0,
+            cursor: 0,
+
+            handle: OnceLock::new(),
+        };
+
+        let arc = Arc::new(RwLock::new(duplicate));
+        arc.write().unwrap().handle.set(Arc::downgrade(&arc)).unwrap();
+
+        Ok(arc)
+    }
+
     fn close(&mut self) { }
+
+    fn get_children(&self) -> FsResult<Vec<FilesystemObject>> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn get_child(&self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn has_child(&self, _name: &str) -> FsResult<bool> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn child_rename(&mut self, _name: &str, _new_name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Keeps the entry in its directory within the archive, so `docs/a.txt` renamed to `b.txt`
+    /// becomes `docs/b.txt`.
+    fn rename(&mut self, new_name: &str) -> FsResult {
+        check_segment(new_name)?;
+
+        let name = self.name.to_string_lossy().to_string();
+        let new_name = match name.rsplit_once('/') {
+            Some((dir, _)) => format!("{dir}/{new_name}"),
+            None => new_name.to_string(),
+        };
+        self.parent.read().unwrap().rename_entry(self.file_index, &name, &new_name)?;
+
+        self.path = self.parent.read().unwrap().path.join(native_path(&new_name));
+        self.name = PathBuf::from(new_name);
+        // Headers before the entry's data may have changed length, so its offset moved.
+        self.stream = None;
+
+        Ok(())
+    }
+
+    fn new_file(&mut self, _name: &str, _buffer_size: usize) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn new_dir(&mut self, _name: &str) -> FsResult<FilesystemObject> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn drop_child(&mut self, _name: &str) -> FsResult {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    /// Keeps as many unread buffered bytes as fit in the new size. The rest are dropped and
+    /// decompressed again when reached.
+    fn set_buffer_size(&mut self, size: usize) {
+        let size = size.max(1);
+        let unread = self.buf_filled - self.cursor;
+        let kept = unread.min(size);
+
+        self.buffer.copy_within(self.cursor..self.cursor + kept, 0);
+        self.buffer.resize(size, 0);
+        self.seek_offset -= (unread - kept) as i64;
+        self.buf_filled = kept;
+        self.cursor = 0;
+    }
+
+    fn scan(&mut self) -> FsResult<()> {
+        Err(FsError::not_a_directory(self.display_path(), self.kind()))
+    }
+
+    fn delete(&mut self) -> FsResult {
+        Err(FsError::read_only(self.display_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PhysicalFile;
+    use crate::test_util::TempDir;
+
+    /// Writes an archive at `path` holding `entries`, deflated, in order.
+    fn write_archive(path: &Path, entries: &[(&str, &str)]) {
+        let mut writer = ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, contents) in entries {
+            writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn renamed_entries_survive_reopening() {
+        let tmp = TempDir::new("zip-rename");
+        let path = tmp.join("archive.zip");
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            for (name, contents) in [("old.txt", "renamed"), ("other.txt", "untouched")] {
+                writer.start_file(name, SimpleFileOptions::default()).unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = zip.read().unwrap().get_child("old.txt").unwrap();
+        zip.write().unwrap().child_rename("old.txt", "new.txt").unwrap();
+        assert_eq!(entry.read().unwrap().name(), Path::new("new.txt"));
+        assert!(zip.read().unwrap().has_child("new.txt").unwrap());
+        assert!(!zip.read().unwrap().has_child("old.txt").unwrap());
+        drop((entry, zip));
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let zip = zip.read().unwrap();
+        assert!(!zip.has_child("old.txt").unwrap());
+        let mut contents = String::new();
+        zip.get_child("new.txt").unwrap().write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "renamed");
+        assert_eq!(zip.get_child("other.txt").unwrap().read().unwrap().size().unwrap(), 9);
+    }
+
+    #[test]
+    fn archives_only_report_renaming() {
+        let tmp = TempDir::new("zip-capabilities");
+        let path = tmp.join("archive.zip");
+        write_archive(&path, &[("a.txt", "a")]);
+        let read_only = Capabilities{ has_timestamps: true, ..Capabilities::default() };
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = zip.read().unwrap().get_child("a.txt").unwrap();
+        assert_eq!(entry.read().unwrap().capabilities(), read_only);
+        // Renames rewrite the archive on disk; nothing else changes it.
+        assert_eq!(zip.read().unwrap().capabilities(), Capabilities{ can_rename: true, ..read_only });
+        zip.write().unwrap().set_readonly(true).unwrap();
+        assert_eq!(zip.read().unwrap().capabilities(), read_only);
+
+        let nested = ZipDirectory::new(crate::MemoryFile::from_bytes("archive.zip", &fs::read(&path).unwrap())).unwrap();
+        assert_eq!(nested.read().unwrap().capabilities(), read_only);
+    }
+
+    #[test]
+    fn entries_under_only_lists_the_prefix() {
+        let tmp = TempDir::new("zip-prefix");
+        let path = tmp.join("archive.zip");
+        write_archive(&path, &[
+            ("src/main.rs", ""), ("README.md", ""), ("src/util/mod.rs", ""), ("src2/other.rs", ""), ("src/lib.rs", ""),
+        ]);
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let names: Vec<_> = zip.read().unwrap().entries_under("src/").unwrap()
+            .into_iter()
+            .map(|entry| entry.read().unwrap().name().to_path_buf())
+            .collect();
+        assert_eq!(names, [Path::new("src/lib.rs"), Path::new("src/main.rs"), Path::new("src/util/mod.rs")]);
+        assert_eq!(zip.read().unwrap().entries_under("src").unwrap().len(), 4);
+        assert!(zip.read().unwrap().entries_under("docs/").unwrap().is_empty());
+    }
+
+    #[test]
+    fn encrypted_entries_need_the_right_password() {
+        let tmp = TempDir::new("zip-aes");
+        let path = tmp.join("secret.zip");
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            let options = SimpleFileOptions::default().with_aes_encryption(zip::AesMode::Aes256, "hunter2");
+            writer.start_file("secret.txt", options).unwrap();
+            writer.write_all(b"the plans").unwrap();
+            writer.finish().unwrap();
+        }
+        let read = |zip: Arc<RwLock<ZipDirectory>>| -> FsResult<String> {
+            let mut contents = String::new();
+            zip.read().unwrap().get_child("secret.txt")?.write().unwrap().read_to_string(&mut contents)?;
+            Ok(contents)
+        };
+
+        let zip = ZipDirectory::new_with_password(PhysicalFile::from_path(&path, 0), b"hunter2").unwrap();
+        assert_eq!(read(zip).unwrap(), "the plans");
+
+        let zip = ZipDirectory::new_with_password(PhysicalFile::from_path(&path, 0), b"wrong").unwrap();
+        let result = read(zip);
+        assert!(matches!(result, Err(FsError::ZipError(..))), "{result:?}");
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        assert!(read(zip).is_err());
+    }
+
+    #[test]
+    fn resizing_the_buffer_mid_read_loses_nothing() {
+        let tmp = TempDir::new("zip-resize");
+        let path = tmp.join("archive.zip");
+        let data: String = (0..1000).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        write_archive(&path, &[("data.txt", &data)]);
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = zip.read().unwrap().get_child("data.txt").unwrap();
+        let mut entry = entry.write().unwrap();
+        entry.set_buffer_size(16);
+
+        let mut read = Vec::new();
+        for (step, size) in [(5, 3), (20, 64), (7, 1), (100, 0), (50, 10)] {
+            let mut chunk = vec![0; step];
+            entry.read_exact(&mut chunk).unwrap();
+            read.extend_from_slice(&chunk);
+            entry.set_buffer_size(size);
+        }
+        entry.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data.as_bytes());
+    }
+
+    #[test]
+    fn verify_catches_corrupted_entries() {
+        let tmp = TempDir::new("zip-verify");
+        let path = tmp.join("archive.zip");
+        let contents = b"payload that will get corrupted on disk ".repeat(20);
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            writer.start_file("stored.bin", stored).unwrap();
+            writer.write_all(&contents).unwrap();
+            writer.start_file("intact.txt", SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"fine").unwrap();
+            writer.finish().unwrap();
+        }
+        let verify = |name: &str| {
+            let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+            let entry = zip.read().unwrap().get_entry(name).unwrap();
+            let mut entry = entry.write().unwrap();
+            entry.set_buffer_size(64);
+            entry.verify()
+        };
+        assert!(verify("stored.bin").unwrap());
+
+        let mut bytes = fs::read(&path).unwrap();
+        let start = bytes.windows(contents.len()).position(|window| window == contents).unwrap();
+        bytes[start + 100] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(!verify("stored.bin").unwrap());
+        assert!(verify("intact.txt").unwrap());
+    }
+
+    #[test]
+    fn nested_archives_open_from_entries() {
+        let tmp = TempDir::new("zip-nested");
+        let (inner_path, outer_path) = (tmp.join("inner.zip"), tmp.join("outer.zip"));
+        write_archive(&inner_path, &[("docs/leaf.txt", "deep inside"), ("other.txt", "x")]);
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&outer_path).unwrap());
+            writer.start_file("nested/inner.zip", SimpleFileOptions::default()).unwrap();
+            writer.write_all(&fs::read(&inner_path).unwrap()).unwrap();
+            writer.finish().unwrap();
+        }
+        fs::remove_file(&inner_path).unwrap();
+
+        let outer = ZipDirectory::new(PhysicalFile::from_path(&outer_path, 0)).unwrap();
+        let entry = outer.read().unwrap().get_child("nested/inner.zip").unwrap();
+        let inner = ZipDirectory::new(entry).unwrap();
+        assert_eq!(inner.read().unwrap().count_children().unwrap(), 2);
+
+        let leaf = inner.read().unwrap().get_child("docs/leaf.txt").unwrap();
+        let mut contents = String::new();
+        leaf.write().unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "deep inside");
+        assert_eq!(leaf.read().unwrap().read_at(5, 6).unwrap(), b"inside");
+    }
+
+    #[test]
+    fn entry_info_reports_the_central_directory() {
+        let tmp = TempDir::new("zip-info");
+        let path = tmp.join("archive.zip");
+        let contents = b"very compressible text, ".repeat(100);
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            let time = zip::DateTime::from_date_and_time(2020, 1, 2, 3, 4, 6).unwrap();
+            writer.start_file("text.txt", SimpleFileOptions::default().last_modified_time(time)).unwrap();
+            writer.write_all(&contents).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let info = zip.read().unwrap().get_entry("text.txt").unwrap().read().unwrap().entry_info().unwrap();
+        let mut crc = Crc::new();
+        crc.update(&contents);
+        assert_eq!(info.method, CompressionMethod::Deflated);
+        assert_eq!(info.uncompressed_size, contents.len() as u64);
+        assert!(info.compressed_size < info.uncompressed_size / 4, "{info:?}");
+        assert_eq!(info.crc32, crc.sum());
+        assert_eq!(info.comment, "");
+        assert_eq!(info.last_modified, Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_577_934_246)));
+    }
+
+    #[test]
+    fn stream_position_counts_consumed_bytes() {
+        let tmp = TempDir::new("zip-position");
+        let path = tmp.join("archive.zip");
+        let data: String = (0..500).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        write_archive(&path, &[("data.txt", &data)]);
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let entry = zip.read().unwrap().get_child("data.txt").unwrap();
+        let mut entry = entry.write().unwrap();
+        entry.set_buffer_size(64);
+
+        let mut head = [0; 10];
+        entry.read_exact(&mut head).unwrap();
+        assert_eq!(entry.stream_position().unwrap(), 10);
+
+        // Relative seeks start from what was consumed, not from the end of the buffer.
+        entry.seek(SeekFrom::Current(5)).unwrap();
+        let mut next = [0; 3];
+        entry.read_exact(&mut next).unwrap();
+        assert_eq!(&next, &data.as_bytes()[15..18]);
+        assert_eq!(entry.stream_position().unwrap(), 18);
+
+        entry.seek(SeekFrom::Current(-8)).unwrap();
+        entry.read_exact(&mut next).unwrap();
+        assert_eq!(&next, &data.as_bytes()[10..13]);
+        assert_eq!(entry.stream_position().unwrap(), 13);
+    }
+
+    #[test]
+    fn backslash_lookups_find_slash_entries() {
+        let tmp = TempDir::new("zip-backslash");
+        let path = tmp.join("archive.zip");
+        write_archive(&path, &[("sub/dir/file.txt", "found")]);
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let zip = zip.read().unwrap();
+
+        assert!(zip.has_child("sub\\dir\\file.txt").unwrap());
+        let entry = zip.get_child("sub\\dir\\file.txt").unwrap();
+        assert!(Arc::ptr_eq(&entry, &zip.get_child("sub/dir/file.txt").unwrap()));
+        assert_eq!(entry.read().unwrap().name(), Path::new("sub/dir/file.txt"));
+        assert_eq!(entry.read().unwrap().get_full_path(), path.join("sub").join("dir").join("file.txt"));
+        assert!(zip.get_entry("sub\\dir\\file.txt").is_ok());
+    }
+
+    #[test]
+    fn get_entry_finds_file_entries_by_their_full_name() {
+        let tmp = TempDir::new("zip-get-entry");
+        let path = tmp.join("archive.zip");
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            writer.add_directory("dir/", SimpleFileOptions::default()).unwrap();
+            writer.start_file("dir/file.txt", SimpleFileOptions::default()).unwrap();
+            writer.write_all(b"found").unwrap();
+            writer.finish().unwrap();
+        }
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let zip = zip.read().unwrap();
+
+        let entry = zip.get_entry("dir/file.txt").unwrap();
+        assert_eq!(entry.read().unwrap().size().unwrap(), 5);
+        assert!(Arc::ptr_eq(&entry, &zip.get_entry("dir/file.txt").unwrap()));
+        assert!(matches!(zip.get_entry("missing.txt"), Err(FsError::FileNotPresent(..))));
+        assert!(matches!(zip.get_entry("dir/"), Err(FsError::NotAFile(_, FsKind::Directory, ..))));
+    }
+
+    #[test]
+    fn unsafe_entry_names_are_left_out() {
+        let tmp = TempDir::new("zip-slip");
+        let path = tmp.join("slip.zip");
+        let unsafe_names = ["../../etc/passwd", "/etc/passwd", "C:\\Windows\\evil.dll", "sub/../../escape.txt"];
+        let mut entries = vec![("safe.txt", "fine")];
+        entries.extend(unsafe_names.iter().map(|name| (*name, "bad")));
+        write_archive(&path, &entries);
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let names: Vec<_> = zip.read().unwrap().get_children().unwrap()
+            .iter()
+            .map(|child| child.read().unwrap().name().to_path_buf())
+            .collect();
+        assert_eq!(names, [Path::new("safe.txt")]);
+
+        for name in unsafe_names {
+            let result = zip.read().unwrap().get_child(name);
+            assert!(matches!(&result, Err(FsError::UnsafePath(unsafe_name, ..)) if unsafe_name == name), "{name}: {result:?}");
+        }
+    }
+
+    /// Opens a new, empty archive at `path`.
+    fn empty_archive(path: &Path) -> Arc<RwLock<ZipDirectory>> {
+        ZipWriter::new(fs::File::create(path).unwrap()).finish().unwrap();
+        ZipDirectory::new(PhysicalFile::from_path(path, 0)).unwrap()
+    }
+
+    #[test]
+    fn stored_entries_round_trip_uncompressed() {
+        let tmp = TempDir::new("zip-stored");
+        let path = tmp.join("media.zip");
+        let contents = b"already compressed media, already compressed media".repeat(64);
+
+        let zip = empty_archive(&path);
+        zip.write().unwrap().set_compression(ZipCompression::Stored, 9);
+        let entry = zip.write().unwrap().add_file("clip.bin", &contents).unwrap();
+        assert_eq!(entry.read().unwrap().read_at(0, contents.len()).unwrap(), contents);
+
+        let info = entry.read().unwrap().entry_info().unwrap();
+        assert_eq!(info.method, CompressionMethod::Stored);
+        assert_eq!(info.compressed_size, contents.len() as u64);
+
+        let on_disk = fs::read(&path).unwrap();
+        assert!(on_disk.windows(contents.len()).any(|window| window == contents));
+    }
+
+    #[test]
+    fn extract_to_keeps_entries_inside_dest() {
+        let tmp = TempDir::new("zip-extract");
+        let path = tmp.join("archive.zip");
+        {
+            let mut writer = ZipWriter::new(fs::File::create(&path).unwrap());
+            for (name, contents) in [("../evil.txt", "escaped"), ("good.txt", "good"), ("sub\\ok.txt", "ok")] {
+                writer.start_file(name, SimpleFileOptions::default()).unwrap();
+                writer.write_all(contents.as_bytes()).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        fs::create_dir(tmp.join("out")).unwrap();
+
+        let zip = ZipDirectory::new(PhysicalFile::from_path(&path, 0)).unwrap();
+        let zip = zip.read().unwrap();
+        zip.extract_to(crate::PhysicalDirectory::from_path(tmp.join("out"))).unwrap();
+
+        assert!(!tmp.join("evil.txt").exists());
+        assert_eq!(fs::read_to_string(tmp.join("out").join("good.txt")).unwrap(), "good");
+        assert_eq!(fs::read_to_string(tmp.join("out").join("sub").join("ok.txt")).unwrap(), "ok");
+        // Entries are cached under their normalized names, as when scanning.
+        assert_eq!(zip.get_children().unwrap().len(), 2);
+    }
+
 }